@@ -1,7 +1,14 @@
-use std::io::stdin;
+use std::io::{BufWriter, Write, stdin};
 
 use clap::Parser;
-use speakie::{BitStream, Speakie};
+use speakie::{
+    BitOrder, BitStream, FrameRate, Speakie, Tap, detect, effects,
+    intonation::Contour,
+    lpc10::{self, Lpc10Decoder},
+    postfilter::{PostFilter, Preset},
+    tables::{self, TableBuf},
+};
+use speakie_enc::data_file::DataFormat;
 
 #[derive(Parser)]
 struct Args {
@@ -10,36 +17,94 @@ struct Args {
     input_file: Option<String>,
     #[arg(short, long)]
     output_file: String,
+    /// Decode the "speakie HQ" format.
+    #[arg(long)]
+    hq: bool,
+    /// Load coefficient tables from a file, instead of the TMS5220 ones.
+    #[arg(long)]
+    tables: Option<String>,
+    /// Detect the chip variant and bit order, and decode using the best guess.
+    #[arg(long)]
+    detect: bool,
+    /// TMS5220C frame rate code: 0 to 3 for 200, 150, 100 or 50 sample frames.
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3))]
+    rate: u8,
+    /// Read a 2 bit rate code at the start of each frame.
+    #[arg(long)]
+    variable_rate: bool,
+    /// Set the frame rate with a TMS5220C "load frame rate" command byte
+    /// instead, such as 0x06 for variable rate starting at code 2.
+    #[arg(long, value_parser = parse_rate_command, conflicts_with_all = ["rate", "variable_rate"])]
+    rate_command: Option<FrameRate>,
+    /// Emulate an analog output stage: flat, echo-ii, small-speaker or telephone.
+    #[arg(long, default_value = "flat", value_parser = parse_preset)]
+    post_filter: Preset,
+    /// Voice effect: normal, whisper, robot, child or giant.
+    #[arg(long, default_value = "normal", value_parser = parse_effect)]
+    effect: effects::Preset,
+    /// Scale formant frequencies, overriding the effect's.
+    #[arg(long)]
+    formant_scale: Option<f32>,
+    /// Scale pitch periods, overriding the effect's.
+    #[arg(long)]
+    period_scale: Option<f32>,
+    /// Apply a pitch contour: question, statement, emphasis, or breakpoints
+    /// as `pos:semitones,...` with positions increasing from 0 to 1, and at
+    /// most 24 semitones either way.
+    #[arg(long, value_parser = parse_contour)]
+    contour: Option<Contour>,
+    /// Excite the lattice with an 8kHz WAV file instead, as a vocoder. The
+    /// carrier loops if it's shorter than the speech.
+    #[arg(long)]
+    carrier: Option<String>,
+    /// Ramp energy per sample and fade out after the stop frame.
+    #[arg(long)]
+    smooth: bool,
+    /// Interrupt speech with a fade after this many frames.
+    #[arg(long)]
+    abort_after: Option<usize>,
+    /// Dump per-sample excitation, lattice state and parameters to a CSV
+    /// file. Not with `--carrier`, which bypasses the tap.
+    #[arg(long, conflicts_with = "carrier")]
+    tap_csv: Option<String>,
+    /// Decode FS-1015 LPC-10 frames, finding frame sync and bit order.
+    #[arg(long)]
+    lpc10: bool,
 }
 
-fn parse_hex(inp: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let mut result = vec![];
-    let inp1 = inp.trim();
-    let inp2 = inp1.replace(',', " ");
-    let inp3 = inp2.strip_prefix("[").unwrap_or(&inp2);
-    let inp4 = inp3.strip_suffix("]").unwrap_or(&inp3);
-    for word in inp4.split_ascii_whitespace() {
-        if word.is_empty() {
-            continue;
-        }
-        let word2 = word.strip_prefix("0x").unwrap_or(&word);
-        let byte = u8::from_str_radix(word2, 16)?;
-        result.push(byte);
-    }
-    Ok(result)
+fn parse_preset(name: &str) -> Result<Preset, String> {
+    Preset::from_name(name).ok_or_else(|| format!("unknown preset {name}"))
+}
+
+fn parse_effect(name: &str) -> Result<effects::Preset, String> {
+    effects::Preset::from_name(name).ok_or_else(|| format!("unknown effect {name}"))
+}
+
+fn parse_rate_command(text: &str) -> Result<FrameRate, String> {
+    let cmd = match text.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    cmd.ok()
+        .and_then(FrameRate::from_command)
+        .ok_or_else(|| format!("not a frame rate command: {text}"))
+}
+
+fn parse_contour(text: &str) -> Result<Contour, String> {
+    Contour::parse(text).ok_or_else(|| format!("bad contour {text}"))
 }
 
 impl Args {
     fn get_hex(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         if let Some(hex) = &self.hex {
-            parse_hex(hex)
+            Ok(DataFormat::Hex.parse(hex.as_bytes())?)
         } else if let Some(input_file) = &self.input_file {
-            let hex = std::fs::read_to_string(input_file)?;
-            parse_hex(&hex)
+            let data = std::fs::read(input_file)?;
+            Ok(DataFormat::detect(&data).parse(&data)?)
         } else {
             let mut hex = String::new();
             stdin().read_line(&mut hex)?;
-            parse_hex(&hex)
+            Ok(DataFormat::Hex.parse(hex.as_bytes())?)
         }
     }
 }
@@ -55,13 +120,190 @@ fn main() {
         sample_format: hound::SampleFormat::Int,
     };
     let mut writer = hound::WavWriter::create(&args.output_file, spec).unwrap();
-    let mut bs = BitStream::new(&lpc_encoded);
-    let mut speakie = Speakie::new();
-    while !speakie.process_frame(&mut bs) {
-        for _ in 0..200 {
-            let sample = speakie.get_sample();
+    let table_buf = args.tables.as_ref().map(|filename| {
+        let text = std::fs::read_to_string(filename).expect("error reading tables");
+        TableBuf::parse(&text).expect("error parsing tables")
+    });
+    let mut tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+    let mut bit_order = BitOrder::LsbFirst;
+    if args.detect {
+        let report = detect::analyze(&lpc_encoded);
+        for c in &report.candidates {
+            println!(
+                "{} {:?}: score {:.2} (structure {:.2}, codes {:.2}, spectral {:.2}), {} frames",
+                c.variant.name(),
+                c.bit_order,
+                c.score,
+                c.structure,
+                c.codes,
+                c.spectral,
+                c.n_frames
+            );
+        }
+        let best = report.best();
+        println!(
+            "Most likely {} {:?}, confidence {:.2}",
+            best.variant.name(),
+            best.bit_order,
+            report.confidence()
+        );
+        tables = best.variant.tables();
+        bit_order = best.bit_order;
+    }
+    if args.lpc10 {
+        let mut post_filter = PostFilter::from_preset(args.post_filter);
+        decode_lpc10(&lpc_encoded, |sample| {
+            writer.write_sample(post_filter.process(sample)).unwrap()
+        });
+        writer.finalize().unwrap();
+        return;
+    }
+    let mut bs = BitStream::with_bit_order(&lpc_encoded, bit_order);
+    let mut speakie = Speakie::with_tables(tables);
+    speakie.set_rate(speakie_rate(&args));
+    speakie.set_smoothing(args.smooth);
+    let mut effects = args.effect.effects();
+    if let Some(scale) = args.formant_scale {
+        effects.formant_scale = scale;
+    }
+    if let Some(scale) = args.period_scale {
+        effects.period_scale = scale;
+    }
+    speakie.set_effects(effects);
+    // A contour needs the length of the utterance, so count frames first.
+    let n_frames = args.contour.map(|_| {
+        let mut bs = BitStream::with_bit_order(&lpc_encoded, bit_order);
+        let mut speakie = Speakie::with_tables(tables);
+        speakie.set_rate(speakie_rate(&args));
+        let mut n: usize = 0;
+        while !bs.is_overrun() && !process(&mut speakie, &mut bs, args.hq) {
+            n += 1;
+        }
+        n
+    });
+    let mut post_filter = PostFilter::from_preset(args.post_filter);
+    let mut tap_csv = args.tap_csv.as_ref().map(|filename| {
+        let mut f = BufWriter::new(std::fs::File::create(filename).expect("error creating CSV"));
+        write_csv_header(&mut f).unwrap();
+        f
+    });
+    let carrier = args.carrier.as_ref().map(|filename| read_carrier(filename));
+    let mut n_samples = 0;
+    let mut get_sample = |speakie: &mut Speakie| {
+        let sample = if let Some(carrier) = &carrier {
+            speakie.get_sample_with_carrier(carrier[n_samples % carrier.len()])
+        } else if let Some(f) = &mut tap_csv {
+            let tap = speakie.get_sample_tap();
+            write_csv_row(f, n_samples, &tap).unwrap();
+            tap.sample
+        } else {
+            speakie.get_sample()
+        };
+        n_samples += 1;
+        post_filter.process(sample)
+    };
+    for i in 0.. {
+        if args.abort_after == Some(i) {
+            speakie.abort_with_fade();
+            break;
+        }
+        if let (Some(contour), Some(n)) = (&args.contour, n_frames) {
+            let pos = i as f32 / n.saturating_sub(1).max(1) as f32;
+            speakie.set_effects(effects::Effects {
+                period_scale: effects.period_scale * contour.period_scale_at(pos),
+                ..effects
+            });
+        }
+        if process(&mut speakie, &mut bs, args.hq) {
+            break;
+        }
+        for _ in 0..speakie.frame_len() {
+            let sample = get_sample(&mut speakie);
             writer.write_sample(sample).unwrap();
         }
     }
+    // Ring down after the stop frame or abort, when fading.
+    while !speakie.is_finished() && (args.smooth || args.abort_after.is_some()) {
+        let sample = get_sample(&mut speakie);
+        writer.write_sample(sample).unwrap();
+    }
     writer.finalize().unwrap();
 }
+
+/// Read the first channel of a WAV file.
+fn read_carrier(filename: &str) -> Vec<i16> {
+    let mut reader = hound::WavReader::open(filename).expect("error opening carrier");
+    let spec = reader.spec();
+    if spec.sample_rate != 8000 {
+        eprintln!("Warning: carrier sample rate is not 8000");
+    }
+    let samples = reader
+        .samples::<i16>()
+        .step_by(spec.channels as usize)
+        .map(|s| s.expect("error reading carrier"))
+        .collect::<Vec<_>>();
+    assert!(!samples.is_empty(), "empty carrier");
+    samples
+}
+
+fn speakie_rate(args: &Args) -> FrameRate {
+    args.rate_command.unwrap_or(FrameRate {
+        code: args.rate,
+        variable: args.variable_rate,
+    })
+}
+
+/// Process a frame in either format, returning true on "stop" frame.
+fn process(speakie: &mut Speakie, bs: &mut BitStream<&Vec<u8>>, hq: bool) -> bool {
+    if hq {
+        speakie.process_hq_frame(bs)
+    } else {
+        speakie.process_frame(bs)
+    }
+}
+
+fn decode_lpc10(data: &[u8], mut write: impl FnMut(i16)) {
+    let Some((bit_order, offset)) = [BitOrder::LsbFirst, BitOrder::MsbFirst]
+        .into_iter()
+        .find_map(|order| lpc10::find_sync(data, order).map(|offset| (order, offset)))
+    else {
+        eprintln!("No LPC-10 frame sync found");
+        std::process::exit(1);
+    };
+    println!("Frame sync at bit {offset}, {bit_order:?}");
+    let mut bs = BitStream::with_bit_order(data, bit_order);
+    bs.seek(offset);
+    let mut decoder = Lpc10Decoder::new();
+    while bs.bit_pos() + lpc10::FRAME_BITS <= data.len() * 8 {
+        decoder.process_frame(&mut bs);
+        for _ in 0..lpc10::FRAME_LEN {
+            write(decoder.get_sample());
+        }
+    }
+    println!("{:?}", decoder.errors());
+}
+
+fn write_csv_header(f: &mut impl Write) -> std::io::Result<()> {
+    write!(f, "n,energy,period")?;
+    for i in 1..=10 {
+        write!(f, ",k{i}")?;
+    }
+    write!(f, ",u10")?;
+    for i in 0..=10 {
+        write!(f, ",x{i}")?;
+    }
+    writeln!(f, ",clipped,sample")
+}
+
+fn write_csv_row(f: &mut impl Write, n: usize, tap: &Tap) -> std::io::Result<()> {
+    let p = &tap.params;
+    write!(f, "{n},{},{}", p.energy, p.period as f32 / 16.0)?;
+    for k in p.k {
+        write!(f, ",{k}")?;
+    }
+    write!(f, ",{}", tap.u10)?;
+    for x in tap.x {
+        write!(f, ",{x}")?;
+    }
+    writeln!(f, ",{},{}", tap.clipped as u8, tap.sample)
+}
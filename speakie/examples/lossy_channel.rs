@@ -0,0 +1,120 @@
+//! Simulate streaming over a lossy channel.
+//!
+//! The example utterance is split into packets, which are dropped according
+//! to several patterns, then decoded with loss concealment. Each pattern is
+//! written to its own WAV file.
+
+use clap::Parser;
+use speakie::{
+    BitStream, Frame, Frames, Speakie,
+    conceal::Concealer,
+    packet::{Depacketizer, Packetizer},
+    tables::Layout,
+};
+use speakie_enc::data_file::DataFormat;
+
+#[derive(Parser)]
+struct Args {
+    /// Prefix for the output WAV files.
+    #[arg(short, long, default_value = "lossy")]
+    output_prefix: String,
+    /// Number of frames in each packet.
+    #[arg(short, long, default_value_t = 2)]
+    frames_per_packet: usize,
+}
+
+const HELLO: &str = "02 c8 9a 35 59 45 aa 7b 84 e5 66 67 9d ae 95 96 9b 9b 5c a9 4e 49 6d 7e 54 13 94 6d b5 c4 85 74 33 f7 94 56 54 5c 2d 54 b3 56 55 49 8c 48 4f 1e 6d a3 36 aa 79 2b 4d 99 e5 50 d5 c8 b2 46 95 25 91 33 cb 1e 55 35 67 dc 72 47 70 9d 94 79 49 0c de 76 40 44 05 36 24 d5 0d 2c 33 51 93 99 0f 93 94 41 75 96 d9 ec 6e 07 e0 01";
+
+/// A pseudorandom generator for drop decisions, so runs are reproducible.
+struct Lcg(u32);
+
+impl Lcg {
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+        (self.0 >> 8) as f64 / (1 << 24) as f64
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let lpc = DataFormat::Hex.parse(HELLO.as_bytes()).unwrap();
+    let mut frames = Frames::new(&mut BitStream::new(&lpc), &Layout::TMS5220).collect::<Vec<_>>();
+    frames.push(Frame::stop());
+
+    let mut packetizer = Packetizer::default();
+    let mut packets = vec![];
+    for chunk in frames.chunks(args.frames_per_packet) {
+        let mut buf = vec![0; packetizer.max_len(chunk.len())];
+        let len = packetizer.packetize(chunk, &mut buf).unwrap();
+        buf.truncate(len);
+        packets.push(buf);
+    }
+    let n_bytes: usize = packets.iter().map(Vec::len).sum();
+    let duration = frames.len() as f64 * 0.025;
+    println!(
+        "{} frames in {} packets, {} bytes, {:.0} bits/s",
+        frames.len(),
+        packets.len(),
+        n_bytes,
+        n_bytes as f64 * 8.0 / duration
+    );
+
+    let n = packets.len();
+    let mut lcg = Lcg(1);
+    let patterns = [
+        ("none", vec![false; n]),
+        ("every_5th", (0..n).map(|i| i % 5 == 4).collect()),
+        ("burst", (0..n).map(|i| (6..9).contains(&i)).collect()),
+        ("random_10", (0..n).map(|_| lcg.next_f64() < 0.1).collect()),
+        ("random_30", (0..n).map(|_| lcg.next_f64() < 0.3).collect()),
+    ];
+    for (name, dropped) in patterns {
+        let received = packets
+            .iter()
+            .zip(dropped)
+            .filter(|(_, dropped)| !dropped)
+            .map(|(packet, _)| packet)
+            .collect::<Vec<_>>();
+        let mut depacketizer = Depacketizer::default();
+        let mut concealer = Concealer::default();
+        let mut decoded = vec![];
+        let mut n_concealed = 0;
+        for packet in received {
+            let packet = depacketizer.receive(packet).unwrap();
+            for _ in 0..packet.lost_frames {
+                decoded.push(concealer.conceal());
+                n_concealed += 1;
+            }
+            for frame in packet.frames() {
+                concealer.receive(&frame);
+                decoded.push(frame);
+            }
+        }
+        if decoded.last().is_none_or(|frame| !frame.is_stop()) {
+            decoded.push(Frame::stop());
+        }
+        let filename = format!("{}_{name}.wav", args.output_prefix);
+        render(&decoded, &filename);
+        println!("{name}: {n_concealed} frames concealed, wrote {filename}");
+    }
+}
+
+fn render(frames: &[Frame], filename: &str) {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(filename, spec).unwrap();
+    let mut speakie = Speakie::new();
+    for frame in frames {
+        if speakie.apply_frame(frame) {
+            break;
+        }
+        for _ in 0..speakie.frame_len() {
+            writer.write_sample(speakie.get_sample()).unwrap();
+        }
+    }
+    writer.finalize().unwrap();
+}
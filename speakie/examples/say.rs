@@ -0,0 +1,120 @@
+//! Speak a number, time or date from a directory of words.
+//!
+//! Each word is a file of hex bytes named after its token, such as
+//! `twenty.hex` or `o'clock.hex`. Without a directory, the tokens are printed.
+
+use clap::{Parser, ValueEnum};
+use speakie::{
+    Speakie,
+    say::{English, Queue, Tokens, Verbalizer, WordBank},
+};
+use speakie_enc::data_file::DataFormat;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Kind {
+    Integer,
+    Decimal,
+    Ordinal,
+    Time,
+    Date,
+}
+
+#[derive(Parser)]
+struct Args {
+    kind: Kind,
+    /// The value: `-42`, `3.14`, `21`, `19:05` or `2024-03-15`.
+    #[arg(allow_hyphen_values = true)]
+    value: String,
+    /// A directory of words, as `TOKEN.hex` files.
+    #[arg(short, long)]
+    words: Option<String>,
+    #[arg(short, long)]
+    output_file: Option<String>,
+}
+
+fn bad(value: &str) -> ! {
+    eprintln!("bad value {value}");
+    std::process::exit(1);
+}
+
+fn main() {
+    let args = Args::parse();
+    let e = English;
+    let mut tokens = Tokens::new();
+    let numbers = |sep: char| {
+        args.value
+            .split(sep)
+            .map(|s| s.parse::<u16>().unwrap_or_else(|_| bad(&args.value)))
+            .collect::<Vec<_>>()
+    };
+    match args.kind {
+        Kind::Integer => e.integer(
+            args.value.parse().unwrap_or_else(|_| bad(&args.value)),
+            &mut tokens,
+        ),
+        Kind::Decimal => {
+            let places = args.value.split_once('.').map_or(0, |(_, f)| f.len());
+            let value = args
+                .value
+                .replace('.', "")
+                .parse()
+                .unwrap_or_else(|_| bad(&args.value));
+            e.decimal(value, places as u8, &mut tokens);
+        }
+        Kind::Ordinal => e.ordinal(
+            args.value.parse().unwrap_or_else(|_| bad(&args.value)),
+            &mut tokens,
+        ),
+        Kind::Time => match numbers(':')[..] {
+            [hour, minute] if hour < 24 && minute < 60 => {
+                e.time(hour as u8, minute as u8, &mut tokens)
+            }
+            _ => bad(&args.value),
+        },
+        Kind::Date => match numbers('-')[..] {
+            [year, month, day] if (1..=12).contains(&month) && (1..=31).contains(&day) => {
+                e.date(year, month as u8, day as u8, &mut tokens)
+            }
+            _ => bad(&args.value),
+        },
+    }
+    println!("{}", tokens.as_slice().join(" "));
+
+    let Some(dir) = &args.words else {
+        return;
+    };
+    let words = e
+        .vocabulary()
+        .iter()
+        .filter_map(|token| {
+            let text = std::fs::read(format!("{dir}/{token}.hex")).ok()?;
+            Some((*token, DataFormat::Hex.parse(&text).expect("bad hex")))
+        })
+        .collect::<Vec<_>>();
+    let words = words
+        .iter()
+        .map(|(token, word)| (*token, word.as_slice()))
+        .collect::<Vec<_>>();
+    let mut queue = Queue::new(WordBank::new(&words));
+    if let Err(e) = queue.say(tokens.as_slice()) {
+        eprintln!("{e:?}");
+        std::process::exit(1);
+    }
+    let Some(output_file) = &args.output_file else {
+        return;
+    };
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(output_file, spec).unwrap();
+    let mut speakie = Speakie::new();
+    while !queue.process_frame(&mut speakie) {
+        for _ in 0..speakie.frame_len() {
+            writer.write_sample(speakie.get_sample()).unwrap();
+        }
+    }
+    writer.finalize().unwrap();
+}
@@ -0,0 +1,553 @@
+//! A compact, entropy-coded container for frame data.
+//!
+//! The frames of an utterance are re-coded losslessly with an adaptive binary
+//! range coder, in the style of LZMA. Energy, period and coefficient codes are
+//! coded as differences from their previous values, so the slowly changing
+//! parameters of speech cost few bits. Utterances are short, so rather than
+//! starting from uniform probabilities, the models start out expecting small
+//! differences. Decoding needs no allocation and about 2.5kB of state.
+//!
+//! The container is a version byte followed by the range coder's output. It
+//! ends after the stop frame. Up to [`MAX_PAST_END`] zero bytes at the end
+//! of the output are left out, and read back as zeros. A decompressor that
+//! needs more than that has been given truncated or corrupt data, and stops.
+
+use crate::{Frame, Speakie, tables::Layout};
+
+/// The container format version.
+pub const VERSION: u8 = 1;
+
+const PROB_BITS: u32 = 11;
+const PROB_INIT: u16 = 1 << (PROB_BITS - 1);
+const MOVE_BITS: u32 = 4;
+const TOP: u32 = 1 << 24;
+
+/// How many bytes past the end the decoder may read. The decoder reads as
+/// many bytes as the encoder wrote, so this is how many trailing zeros the
+/// encoder may leave out.
+pub const MAX_PAST_END: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompactError {
+    /// The output buffer can't hold the compressed data.
+    BufferTooSmall,
+    /// The data doesn't start with a supported version.
+    BadVersion,
+}
+
+/// The adaptive probabilities, shared by compressor and decompressor.
+struct Model {
+    // Energy, in the context of whether the previous frame was silent
+    energy: [[u16; 16]; 2],
+    // Repeat and voicing flags, in the context of the previous voicing
+    repeat: [u16; 2],
+    voiced: [u16; 2],
+    period: [u16; 64],
+    // K1..K4 in the context of voicing, and K5..K10
+    k_lo: [[[u16; 64]; 4]; 2],
+    k_hi: [[u16; 64]; 6],
+    last_energy: u8,
+    last_voiced: bool,
+    last_period: u8,
+    last_k: [u8; 10],
+}
+
+/// Either side of the range coder.
+trait Coder {
+    /// Code one bit. The encoder takes `bit` and returns it, the decoder
+    /// ignores it and returns the decoded bit.
+    fn code_bit(&mut self, prob: &mut u16, bit: bool) -> bool;
+
+    /// Code the difference of a value of `bits` bits from `last`, returning
+    /// the value.
+    ///
+    /// The difference is wrapped to `bits` bits and mapped so that small
+    /// magnitudes are small values: 0, -1, 1, -2...
+    fn code_delta(&mut self, probs: &mut [u16], bits: u8, last: u8, value: u8) -> u8 {
+        let mask = (1u8 << bits) - 1;
+        let half = 1 << (bits - 1);
+        // Signed difference in -half..half.
+        let delta = (value.wrapping_sub(last).wrapping_add(half) & mask) as i8 - half as i8;
+        let zigzag = if delta < 0 {
+            (-delta * 2 - 1) as u8
+        } else {
+            (delta * 2) as u8
+        };
+        let zigzag = self.code_tree(probs, bits, zigzag);
+        let delta = if zigzag & 1 != 0 {
+            -((zigzag as i8 + 1) / 2)
+        } else {
+            (zigzag / 2) as i8
+        };
+        last.wrapping_add(delta as u8) & mask
+    }
+
+    /// Code a value of `bits` bits with a binary tree of probabilities.
+    fn code_tree(&mut self, probs: &mut [u16], bits: u8, value: u8) -> u8 {
+        let mut node = 1;
+        for i in (0..bits).rev() {
+            let bit = self.code_bit(&mut probs[node], (value >> i) & 1 != 0);
+            node = (node << 1) | bit as usize;
+        }
+        (node - (1 << bits)) as u8
+    }
+}
+
+struct RangeEncoder<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: usize,
+    skip_first: bool,
+    overflow: bool,
+}
+
+struct RangeDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    code: u32,
+    range: u32,
+}
+
+/// Streaming decompressor.
+pub struct Decompressor<'a> {
+    layout: Layout,
+    rc: RangeDecoder<'a>,
+    model: Model,
+    done: bool,
+    truncated: bool,
+}
+
+impl Model {
+    fn new(layout: &Layout) -> Self {
+        let k = |i: usize, q| prior(layout.k_bits[i], q);
+        Self {
+            energy: [prior(4, 0.9), prior(4, 0.6)],
+            repeat: [PROB_INIT; 2],
+            voiced: [PROB_INIT; 2],
+            period: prior(layout.period_bits, 0.8),
+            k_lo: [0, 1].map(|_| core::array::from_fn(|i| k(i, 0.75))),
+            k_hi: core::array::from_fn(|i| k(i + 4, 0.7)),
+            last_energy: 0,
+            last_voiced: false,
+            last_period: 0,
+            last_k: [0; 10],
+        }
+    }
+
+    /// Code a frame, returning the frame as decoded.
+    fn code_frame(&mut self, c: &mut impl Coder, frame: &Frame, layout: &Layout) -> Frame {
+        let probs = &mut self.energy[(self.last_energy != 0) as usize];
+        let mut out = Frame {
+            energy: c.code_delta(probs, 4, self.last_energy, frame.energy),
+            ..Frame::default()
+        };
+        self.last_energy = out.energy;
+        if out.is_silence() || out.is_stop() {
+            return out;
+        }
+        let ctx = self.last_voiced as usize;
+        out.repeat = c.code_bit(&mut self.repeat[ctx], frame.repeat);
+        let voiced = c.code_bit(&mut self.voiced[ctx], frame.is_voiced());
+        self.last_voiced = voiced;
+        if voiced {
+            out.period = c.code_delta(
+                &mut self.period,
+                layout.period_bits,
+                self.last_period,
+                frame.period,
+            );
+            self.last_period = out.period;
+        }
+        let n_k = out.n_k();
+        for (i, bits) in layout.k_bits.into_iter().enumerate().take(n_k) {
+            let probs = match i {
+                0..4 => &mut self.k_lo[voiced as usize][i],
+                _ => &mut self.k_hi[i - 4],
+            };
+            out.k[i] = c.code_delta(probs, bits, self.last_k[i], frame.k[i]);
+            self.last_k[i] = out.k[i];
+        }
+        out
+    }
+}
+
+/// Probabilities for a tree of `bits` bits, for values distributed
+/// geometrically with ratio `q`.
+fn prior<const N: usize>(bits: u8, q: f32) -> [u16; N] {
+    let n = 1 << bits;
+    // Cumulative weights.
+    let mut cum = [0f32; 65];
+    let mut w = 1.0;
+    for z in 0..n {
+        cum[z + 1] = cum[z] + w;
+        w *= q;
+    }
+    let mut probs = [PROB_INIT; N];
+    for depth in 0..bits {
+        let width = n >> depth;
+        for prefix in 0..1 << depth {
+            let lo = prefix * width;
+            let p0 = (cum[lo + width / 2] - cum[lo]) / (cum[lo + width] - cum[lo]);
+            let p0 = (p0 * (1 << PROB_BITS) as f32) as u16;
+            probs[(1 << depth) + prefix] = p0.clamp(31, (1 << PROB_BITS) - 31);
+        }
+    }
+    probs
+}
+
+impl Coder for RangeEncoder<'_> {
+    fn code_bit(&mut self, prob: &mut u16, bit: bool) -> bool {
+        let bound = (self.range >> PROB_BITS) * *prob as u32;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+            *prob -= *prob >> MOVE_BITS;
+        } else {
+            self.range = bound;
+            *prob += ((1 << PROB_BITS) - *prob) >> MOVE_BITS;
+        }
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+        bit
+    }
+}
+
+impl<'a> RangeEncoder<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+            skip_first: true,
+            overflow: false,
+        }
+    }
+
+    fn put_byte(&mut self, byte: u8) {
+        // The first byte is always zero, so the decoder assumes it.
+        if self.skip_first {
+            self.skip_first = false;
+            return;
+        }
+        match self.buf.get_mut(self.pos) {
+            Some(b) => *b = byte,
+            None => self.overflow = true,
+        }
+        self.pos += 1;
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low >= 1 << 32 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            while self.cache_size > 0 {
+                self.put_byte(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.cache_size -= 1;
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    fn finish(mut self) -> Result<usize, CompactError> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        if self.overflow {
+            return Err(CompactError::BufferTooSmall);
+        }
+        // The decoder reads zeros past the end, so a few needn't be stored.
+        for _ in 0..MAX_PAST_END {
+            if self.pos == 0 || self.buf[self.pos - 1] != 0 {
+                break;
+            }
+            self.pos -= 1;
+        }
+        Ok(self.pos)
+    }
+}
+
+impl Coder for RangeDecoder<'_> {
+    fn code_bit(&mut self, prob: &mut u16, _bit: bool) -> bool {
+        let bound = (self.range >> PROB_BITS) * *prob as u32;
+        let bit = if self.code < bound {
+            self.range = bound;
+            *prob += ((1 << PROB_BITS) - *prob) >> MOVE_BITS;
+            false
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            *prob -= *prob >> MOVE_BITS;
+            true
+        };
+        if self.range < TOP {
+            self.range <<= 8;
+            self.code = (self.code << 8) | self.next_byte() as u32;
+        }
+        bit
+    }
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        let mut rc = Self {
+            data,
+            pos: 0,
+            code: 0,
+            range: u32::MAX,
+        };
+        for _ in 0..4 {
+            rc.code = (rc.code << 8) | rc.next_byte() as u32;
+        }
+        rc
+    }
+
+    /// Whether more bytes have been read past the end of the data than a
+    /// complete container needs.
+    fn is_exhausted(&self) -> bool {
+        self.pos > self.data.len() + MAX_PAST_END
+    }
+
+    /// Bytes past the end of the data read as zero.
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.data.get(self.pos).copied().unwrap_or_default();
+        self.pos += 1;
+        byte
+    }
+}
+
+/// Compress frames into `buf`, returning the length.
+///
+/// Frames up to the first stop frame are coded, and a stop frame is added if
+/// there is none.
+pub fn compress(frames: &[Frame], layout: &Layout, buf: &mut [u8]) -> Result<usize, CompactError> {
+    let (version, buf) = buf.split_first_mut().ok_or(CompactError::BufferTooSmall)?;
+    *version = VERSION;
+    let mut rc = RangeEncoder::new(buf);
+    let mut model = Model::new(layout);
+    let stop = Frame::stop();
+    let end = frames.iter().position(Frame::is_stop);
+    let frames = frames[..end.unwrap_or(frames.len())].iter().chain([&stop]);
+    for frame in frames {
+        model.code_frame(&mut rc, frame, layout);
+    }
+    Ok(rc.finish()? + 1)
+}
+
+impl<'a> Decompressor<'a> {
+    pub fn new(data: &'a [u8], layout: &Layout) -> Result<Self, CompactError> {
+        match data.split_first() {
+            Some((&VERSION, data)) => Ok(Self {
+                layout: *layout,
+                rc: RangeDecoder::new(data),
+                model: Model::new(layout),
+                done: false,
+                truncated: false,
+            }),
+            _ => Err(CompactError::BadVersion),
+        }
+    }
+
+    /// Decode the next frame, or `None` after the stop frame or when the
+    /// data runs out.
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        if self.done {
+            return None;
+        }
+        let frame = self
+            .model
+            .code_frame(&mut self.rc, &Frame::default(), &self.layout);
+        if self.rc.is_exhausted() {
+            self.done = true;
+            self.truncated = true;
+            return None;
+        }
+        self.done = frame.is_stop();
+        Some(frame)
+    }
+
+    /// Whether the data ran out before the stop frame.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Decode the next frame into the decoder, like
+    /// [`Speakie::process_frame`].
+    ///
+    /// Returns true on "stop" frame, or after it, or when the data runs out.
+    pub fn process_frame(&mut self, speakie: &mut Speakie) -> bool {
+        self.next_frame()
+            .is_none_or(|frame| speakie.apply_frame(&frame))
+    }
+}
+
+impl Iterator for Decompressor<'_> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.next_frame()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{vec, vec::Vec};
+
+    use super::*;
+    use crate::{BitStream, BitWriter, Frames};
+
+    const CORPUS: &str = include_str!("../testdata/corpus.txt");
+
+    /// An utterance of the corpus.
+    struct Utterance {
+        name: &'static str,
+        /// The least compression ratio expected.
+        min_ratio: f64,
+        lpc: Vec<u8>,
+    }
+
+    fn corpus() -> impl Iterator<Item = Utterance> {
+        CORPUS
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+            .map(|line| {
+                let (head, hex) = line.split_once(':').unwrap();
+                let (name, min_ratio) = head.split_once(' ').unwrap();
+                let lpc = hex
+                    .split_ascii_whitespace()
+                    .map(|word| u8::from_str_radix(word, 16).unwrap())
+                    .collect();
+                Utterance {
+                    name,
+                    min_ratio: min_ratio.parse().unwrap(),
+                    lpc,
+                }
+            })
+    }
+
+    /// Read frames up to and including the stop frame, returning them and
+    /// the length in bits.
+    fn read_frames(lpc: &[u8]) -> (Vec<Frame>, usize) {
+        let mut bs = BitStream::new(lpc);
+        let mut frames = Frames::new(&mut bs, &Layout::TMS5220);
+        let mut all = frames.by_ref().collect::<Vec<_>>();
+        assert!(!frames.is_truncated());
+        all.push(Frame::stop());
+        (all, bs.bit_pos())
+    }
+
+    fn compress_vec(frames: &[Frame]) -> Vec<u8> {
+        let mut buf = vec![0; frames.len() * 8 + 16];
+        let len = compress(frames, &Layout::TMS5220, &mut buf).unwrap();
+        buf.truncate(len);
+        buf
+    }
+
+    fn render(mut process_frame: impl FnMut(&mut Speakie) -> bool) -> Vec<i16> {
+        let mut speakie = Speakie::new();
+        let mut samples = vec![];
+        while !process_frame(&mut speakie) {
+            samples.extend((0..speakie.frame_len()).map(|_| speakie.get_sample()));
+        }
+        samples
+    }
+
+    #[test]
+    fn round_trip() {
+        let layout = Layout::TMS5220;
+        for Utterance { name, lpc, .. } in corpus() {
+            let (frames, bits) = read_frames(&lpc);
+            let data = compress_vec(&frames);
+
+            let mut decompressor = Decompressor::new(&data, &layout).unwrap();
+            let decoded = decompressor.by_ref().collect::<Vec<_>>();
+            assert!(!decompressor.is_truncated(), "{name}: truncated");
+            assert_eq!(decoded, frames, "{name}: frames differ");
+
+            let raw = bits.div_ceil(8);
+            let mut rewritten = vec![0; raw];
+            let mut w = BitWriter::new(&mut rewritten);
+            for frame in &decoded {
+                frame.write(&mut w, &layout);
+            }
+            // Compare the bits of the frames, not the padding after them.
+            let mask = |bytes: &[u8]| {
+                let mut bytes = bytes[..raw].to_vec();
+                if bits % 8 != 0 {
+                    bytes[raw - 1] &= (1 << (bits % 8)) - 1;
+                }
+                bytes
+            };
+            assert_eq!(mask(&rewritten), mask(&lpc), "{name}: bits differ");
+
+            let mut bs = BitStream::new(&lpc);
+            let direct = render(|speakie| speakie.process_frame(&mut bs));
+            let mut decompressor = Decompressor::new(&data, &layout).unwrap();
+            let streamed = render(|speakie| decompressor.process_frame(speakie));
+            assert_eq!(direct, streamed, "{name}: audio differs");
+        }
+    }
+
+    /// Prints a table of the compression ratios, with `--nocapture`.
+    #[test]
+    fn corpus_ratio() {
+        std::println!("utterance       raw  compact  ratio");
+        let mut low = vec![];
+        for utterance in corpus() {
+            let (frames, bits) = read_frames(&utterance.lpc);
+            let raw = bits.div_ceil(8);
+            let compact = compress_vec(&frames).len();
+            let ratio = raw as f64 / compact as f64;
+            std::println!("{:14} {raw:4} {compact:8} {ratio:6.3}", utterance.name);
+            if ratio < utterance.min_ratio {
+                low.push(utterance.name);
+            }
+        }
+        assert!(low.is_empty(), "compressed less than expected: {low:?}");
+    }
+
+    #[test]
+    fn truncated_data_stops() {
+        let lpc = corpus().next().unwrap().lpc;
+        let (frames, _) = read_frames(&lpc);
+        let data = compress_vec(&frames);
+        for len in 1..data.len() - MAX_PAST_END {
+            let mut decompressor = Decompressor::new(&data[..len], &Layout::TMS5220).unwrap();
+            let decoded = decompressor
+                .by_ref()
+                .take(frames.len() * 2)
+                .collect::<Vec<_>>();
+            assert!(decoded.len() < frames.len(), "{len} bytes: {decoded:?}");
+            // The zeros past the end can happen to decode as a stop frame.
+            let stopped = decoded.last().is_some_and(Frame::is_stop);
+            assert!(decompressor.is_truncated() != stopped, "{len} bytes");
+            assert!(decompressor.next_frame().is_none());
+        }
+    }
+
+    #[test]
+    fn corrupt_data_stops() {
+        let lpc = corpus().next().unwrap().lpc;
+        let (frames, _) = read_frames(&lpc);
+        let mut data = compress_vec(&frames);
+        for byte in &mut data[8..] {
+            *byte ^= 0x5a;
+        }
+        let mut decompressor = Decompressor::new(&data, &Layout::TMS5220).unwrap();
+        let n = decompressor.by_ref().take(1000).count();
+        assert!(n < 1000);
+        assert!(decompressor.next_frame().is_none());
+    }
+}
@@ -0,0 +1,181 @@
+//! Concealment of lost frames.
+//!
+//! Substitute frames continue the trajectory of the coefficients, damping the
+//! slope by half each frame, while the energy fades toward silence one code
+//! step (about 3dB) per frame.
+
+use crate::{Frame, tables::Layout};
+
+/// Generates substitute frames for frames lost in transmission.
+pub struct Concealer {
+    last: Frame,
+    k: [u8; 10],
+    slope: [i8; 10],
+    k_max: [u8; 10],
+}
+
+impl Concealer {
+    pub fn new(layout: &Layout) -> Self {
+        Self {
+            last: Frame::silence(),
+            k: [0; 10],
+            slope: [0; 10],
+            k_max: layout.k_max(),
+        }
+    }
+
+    /// Record a frame that was received correctly.
+    ///
+    /// The slope is only measured between frames of the same voicing, so
+    /// concealment holds the coefficients after the first frame, or after
+    /// silence or a change of voicing.
+    pub fn receive(&mut self, frame: &Frame) {
+        if frame.n_k() > 0 {
+            let last = &self.last;
+            if last.is_silence() || last.is_stop() || last.is_voiced() != frame.is_voiced() {
+                self.slope = [0; 10];
+            } else {
+                for i in 0..10 {
+                    self.slope[i] = (frame.k[i] as i8 - self.k[i] as i8) / 2;
+                }
+            }
+            self.k = frame.k;
+        } else if frame.repeat {
+            self.slope = [0; 10];
+        }
+        self.last = *frame;
+    }
+
+    /// Generate a frame to stand in for a lost one.
+    pub fn conceal(&mut self) -> Frame {
+        let last = self.last;
+        if last.is_silence() || last.is_stop() || last.energy <= 1 {
+            self.last = Frame::silence();
+            return self.last;
+        }
+        for ((k, slope), max) in self.k.iter_mut().zip(&mut self.slope).zip(self.k_max) {
+            *k = (*k as i8 + *slope).clamp(0, max as i8) as u8;
+            *slope /= 2;
+        }
+        self.last = Frame {
+            energy: last.energy - 1,
+            repeat: false,
+            period: last.period,
+            k: self.k,
+        };
+        self.last
+    }
+}
+
+impl Default for Concealer {
+    fn default() -> Self {
+        Self::new(&Layout::TMS5220)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{vec, vec::Vec};
+
+    use super::*;
+    use crate::packet::{Depacketizer, Packetizer};
+
+    fn voiced(energy: u8, k1: u8) -> Frame {
+        Frame {
+            energy,
+            repeat: false,
+            period: 40,
+            k: [k1, 12, 5, 6, 7, 8, 9, 3, 2, 1],
+        }
+    }
+
+    #[test]
+    fn energy_fades_to_silence() {
+        let mut concealer = Concealer::default();
+        concealer.receive(&voiced(10, 20));
+        let energies = (0..12)
+            .map(|_| concealer.conceal().energy)
+            .collect::<Vec<_>>();
+        assert_eq!(energies, [9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 0]);
+        assert!(concealer.conceal().is_silence());
+    }
+
+    #[test]
+    fn coefficients_follow_damped_slope() {
+        let mut concealer = Concealer::default();
+        concealer.receive(&voiced(10, 10));
+        concealer.receive(&voiced(10, 18));
+        let k1 = (0..5).map(|_| concealer.conceal().k[0]).collect::<Vec<_>>();
+        // The slope starts at half of the last step, then halves each frame.
+        assert_eq!(k1, [22, 24, 25, 25, 25]);
+    }
+
+    #[test]
+    fn first_frame_and_voicing_change_hold() {
+        let mut concealer = Concealer::default();
+        concealer.receive(&voiced(10, 20));
+        assert_eq!(concealer.conceal().k, voiced(10, 20).k);
+
+        let mut concealer = Concealer::default();
+        concealer.receive(&Frame {
+            period: 0,
+            k: [10, 5, 3, 2, 0, 0, 0, 0, 0, 0],
+            ..voiced(10, 10)
+        });
+        concealer.receive(&voiced(10, 20));
+        assert_eq!(concealer.conceal().k, voiced(10, 20).k);
+    }
+
+    #[test]
+    fn silence_stays_silent() {
+        let mut concealer = Concealer::default();
+        assert!(concealer.conceal().is_silence());
+        concealer.receive(&voiced(10, 20));
+        concealer.receive(&Frame::silence());
+        assert!(concealer.conceal().is_silence());
+    }
+
+    /// Send `frames` in packets of `per_packet`, dropping the packets in
+    /// `lost`, and conceal the frames they carried.
+    fn channel(frames: &[Frame], per_packet: usize, lost: &[usize]) -> Vec<Frame> {
+        let mut packetizer = Packetizer::default();
+        let mut depacketizer = Depacketizer::default();
+        let mut concealer = Concealer::default();
+        let mut received = vec![];
+        for (i, frames) in frames.chunks(per_packet).enumerate() {
+            let mut buf = vec![0; packetizer.max_len(frames.len())];
+            let len = packetizer.packetize(frames, &mut buf).unwrap();
+            if lost.contains(&i) {
+                continue;
+            }
+            let packet = depacketizer.receive(&buf[..len]).unwrap();
+            for _ in 0..packet.lost_frames {
+                received.push(concealer.conceal());
+            }
+            for frame in packet.frames() {
+                concealer.receive(&frame);
+                received.push(frame);
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn conceals_lost_packets() {
+        let received = channel(&[voiced(12, 20); 8], 2, &[1, 2]);
+        let energies = received.iter().map(|f| f.energy).collect::<Vec<_>>();
+        assert_eq!(energies, [12, 12, 11, 10, 9, 8, 12, 12]);
+        assert!(received.iter().all(|f| f.k == voiced(12, 20).k));
+    }
+
+    #[test]
+    fn conceals_after_one_frame() {
+        let frames = [voiced(12, 20), voiced(12, 26), voiced(12, 26)];
+        let received = channel(&frames, 1, &[1]);
+        let k1 = received.iter().map(|f| f.k[0]).collect::<Vec<_>>();
+        assert_eq!(k1, [20, 20, 26]);
+        assert_eq!(received[1].energy, 11);
+    }
+}
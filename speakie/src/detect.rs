@@ -0,0 +1,198 @@
+//! Detection of the chip variant and bit order of unknown bitstreams.
+//!
+//! Each combination of built-in tables and bit order is tried, and scored on
+//! three criteria, each from 0 to 1:
+//!
+//! - structure: whether the data ends with a stop frame, followed by no more
+//!   than padding.
+//! - codes: whether the codes follow patterns typical of encoded speech, such
+//!   as negative K1 in voiced frames and smooth energy and pitch.
+//! - spectral: whether the decoded audio is plausible, meaning voiced frames
+//!   are mostly lowpass and no frames clip.
+
+use crate::{BitOrder, BitStream, Frame, Speakie, tables::Tables};
+
+/// A chip variant with built-in tables.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Tms5220,
+    Tms5100,
+}
+
+/// The result of trying one variant and bit order.
+#[derive(Clone, Copy, Debug)]
+pub struct Candidate {
+    pub variant: Variant,
+    pub bit_order: BitOrder,
+    /// The number of frames, up to the stop frame or the end of the data.
+    pub n_frames: usize,
+    pub has_stop: bool,
+    /// The number of bits of data after the stop frame.
+    pub trailing_bits: usize,
+    pub structure: f32,
+    pub codes: f32,
+    pub spectral: f32,
+    /// The overall score, from 0 to 1.
+    pub score: f32,
+}
+
+/// The scored candidates.
+#[derive(Clone, Copy, Debug)]
+pub struct Report {
+    /// All candidates, best first.
+    pub candidates: [Candidate; 4],
+}
+
+/// Limit on the number of frames analyzed.
+const MAX_FRAMES: usize = 4000;
+
+const ORDERS: [BitOrder; 2] = [BitOrder::LsbFirst, BitOrder::MsbFirst];
+
+impl Variant {
+    pub const ALL: [Variant; 2] = [Variant::Tms5220, Variant::Tms5100];
+
+    pub fn tables(self) -> Tables<'static> {
+        match self {
+            Variant::Tms5220 => crate::tables::TMS5220,
+            Variant::Tms5100 => crate::tables::TMS5100,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Tms5220 => "TMS5220",
+            Variant::Tms5100 => "TMS5100",
+        }
+    }
+}
+
+impl Report {
+    pub fn best(&self) -> &Candidate {
+        &self.candidates[0]
+    }
+
+    /// Confidence in the best candidate, from 0 to 1.
+    ///
+    /// This is the best candidate's share of the total score, with scores
+    /// raised to the 8th power to sharpen the distinction, then weighted by its
+    /// own score.
+    pub fn confidence(&self) -> f32 {
+        fn sharpen(x: f32) -> f32 {
+            let x2 = x * x;
+            let x4 = x2 * x2;
+            x4 * x4
+        }
+
+        let total: f32 = self.candidates.iter().map(|c| sharpen(c.score)).sum();
+        if total == 0.0 {
+            return 0.0;
+        }
+        self.best().score * sharpen(self.best().score) / total
+    }
+}
+
+/// Score each variant and bit order for the data.
+pub fn analyze(data: &[u8]) -> Report {
+    let candidates = core::array::from_fn(|i| {
+        let variant = Variant::ALL[i / 2];
+        score(data, variant, ORDERS[i % 2])
+    });
+    let mut report = Report { candidates };
+    report
+        .candidates
+        .sort_unstable_by(|a, b| b.score.total_cmp(&a.score));
+    report
+}
+
+fn score(data: &[u8], variant: Variant, bit_order: BitOrder) -> Candidate {
+    let tables = variant.tables();
+    let layout = tables.layout();
+    let mut bs = BitStream::with_bit_order(data, bit_order);
+    let mut speakie = Speakie::with_tables(tables);
+    let mut n_frames = 0;
+    let mut has_stop = false;
+    let mut last: Option<Frame> = None;
+    let mut seen_k = false;
+    let (mut code_checks, mut code_passes) = (0, 0);
+    let (mut n_sounding, mut n_good) = (0, 0);
+    while n_frames < MAX_FRAMES {
+        let frame = Frame::read(&mut bs, &layout);
+        if bs.is_overrun() {
+            break;
+        }
+        n_frames += 1;
+        if speakie.apply_frame(&frame) {
+            has_stop = true;
+            break;
+        }
+
+        let mut check = |pass: bool| {
+            code_checks += 1;
+            code_passes += pass as usize;
+        };
+        if frame.repeat {
+            check(seen_k);
+        }
+        seen_k |= frame.n_k() > 0;
+        if frame.is_voiced() && frame.n_k() > 0 {
+            check(tables.k[0][frame.k[0] as usize] < 0);
+        }
+        if let Some(last) = last.filter(|last| !last.is_silence() && !frame.is_silence()) {
+            check(frame.energy.abs_diff(last.energy) <= 5);
+            if last.is_voiced() && frame.is_voiced() {
+                let p0 = tables.period[last.period as usize] as u32;
+                let p1 = tables.period[frame.period as usize] as u32;
+                check(p0.abs_diff(p1) * 10 <= p0 * 3);
+            }
+        }
+        last = Some(frame);
+
+        let (mut e0, mut e1, mut clipped) = (0i64, 0i64, false);
+        let mut prev = 0i64;
+        for _ in 0..speakie.frame_len() {
+            let sample = speakie.get_sample();
+            clipped |= sample == i16::MAX || sample == i16::MIN;
+            let s = sample as i64;
+            e0 += s * s;
+            e1 += (s - prev) * (s - prev);
+            prev = s;
+        }
+        if !frame.is_silence() {
+            n_sounding += 1;
+            n_good += (!clipped && (!frame.is_voiced() || e1 < e0)) as usize;
+        }
+    }
+
+    let trailing_bits = (data.len() * 8).saturating_sub(bs.bit_pos());
+    let trailing_zero = data[bs.bit_pos().div_ceil(8).min(data.len())..]
+        .iter()
+        .all(|b| *b == 0);
+    let structure = match (has_stop, trailing_bits) {
+        (true, 0..8) => 1.0,
+        (true, _) if trailing_zero => 0.9,
+        (true, 8..16) => 0.7,
+        (true, _) => 0.3 * bs.bit_pos() as f32 / (data.len() * 8) as f32,
+        (false, _) => 0.2,
+    };
+    let codes = if code_checks == 0 {
+        0.0
+    } else {
+        code_passes as f32 / code_checks as f32
+    };
+    let spectral = if n_sounding == 0 {
+        0.0
+    } else {
+        n_good as f32 / n_sounding as f32
+    };
+    Candidate {
+        variant,
+        bit_order,
+        n_frames,
+        has_stop,
+        trailing_bits,
+        structure,
+        codes,
+        spectral,
+        score: 0.4 * structure + 0.3 * codes + 0.3 * spectral,
+    }
+}
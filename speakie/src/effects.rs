@@ -0,0 +1,268 @@
+//! Voice effects, applied to decoded parameters before the lattice.
+//!
+//! Effects act once per frame, on the parameters a frame decodes to, so they
+//! cost little even on small devices and can be changed while speaking with
+//! [`Speakie::set_effects`](crate::Speakie::set_effects).
+//!
+//! The formant shift warps the frequency axis of the filter's spectral
+//! envelope: the envelope is sampled at warped frequencies, turned back into
+//! an autocorrelation and refitted with the Levinson-Durbin recursion. Scaling
+//! formants up sounds like a shorter vocal tract.
+
+use crate::{Params, hq};
+
+/// Number of bands the spectral envelope is sampled in, from 0 to Nyquist.
+const BANDS: usize = 64;
+
+/// Largest warped reflection coefficient, scaled by 512, keeping the
+/// lattice stable.
+const K_MAX: f32 = 511.0;
+
+/// The longest period, in 1/16 samples, as in the HQ format.
+const MAX_PERIOD: u16 = (1 << hq::PERIOD_BITS) - 1;
+
+const PI: f32 = core::f32::consts::PI;
+
+/// A set of effects.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Effects {
+    /// Formant frequency scale, 1 for none. Above 1 is a shorter vocal tract.
+    pub formant_scale: f32,
+    /// Pitch period scale, 1 for none. Above 1 is a lower voice.
+    pub period_scale: f32,
+    /// Replace the period of voiced frames with this constant, in 1/16
+    /// samples. Periods are limited to 4095, as in the HQ format.
+    pub robot_period: Option<u16>,
+    /// Replace voiced excitation with noise.
+    pub whisper: bool,
+}
+
+/// A built-in combination of effects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// No effects.
+    Normal,
+    Whisper,
+    /// A monotone at 100Hz.
+    Robot,
+    /// Formants up by a quarter, pitch up by two thirds.
+    Child,
+    /// Formants down by a fifth, pitch down by a third.
+    Giant,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+impl Effects {
+    pub const NONE: Self = Self {
+        formant_scale: 1.0,
+        period_scale: 1.0,
+        robot_period: None,
+        whisper: false,
+    };
+
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Apply the effects to decoded parameters.
+    pub fn apply(&self, params: &Params) -> Params {
+        let mut out = *params;
+        if self.is_none() || params.energy == 0 {
+            return out;
+        }
+        if self.formant_scale != 1.0 {
+            out.k = warp(&params.k, self.formant_scale);
+        }
+        if params.period != 0 {
+            if self.whisper {
+                out.energy = params.noise_energy();
+                out.period = 0;
+            } else if let Some(period) = self.robot_period {
+                out.period = period.clamp(16, MAX_PERIOD);
+            } else {
+                let period = params.period as f32 * self.period_scale;
+                out.period = period.clamp(16.0, MAX_PERIOD as f32) as u16;
+            }
+        }
+        out
+    }
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::Normal,
+        Preset::Whisper,
+        Preset::Robot,
+        Preset::Child,
+        Preset::Giant,
+    ];
+
+    pub fn effects(self) -> Effects {
+        let none = Effects::NONE;
+        match self {
+            Preset::Normal => none,
+            Preset::Whisper => Effects {
+                whisper: true,
+                ..none
+            },
+            Preset::Robot => Effects {
+                robot_period: Some(80 << 4),
+                ..none
+            },
+            Preset::Child => Effects {
+                formant_scale: 1.25,
+                period_scale: 0.6,
+                ..none
+            },
+            Preset::Giant => Effects {
+                formant_scale: 0.8,
+                period_scale: 1.5,
+                ..none
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Normal => "normal",
+            Preset::Whisper => "whisper",
+            Preset::Robot => "robot",
+            Preset::Child => "child",
+            Preset::Giant => "giant",
+        }
+    }
+
+    /// Look up a preset by [name](Self::name).
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+/// Warp the spectral envelope of reflection coefficients scaled by 512, so
+/// that a formant at `f` moves to `f * scale`.
+fn warp(k: &[i16; 10], scale: f32) -> [i16; 10] {
+    // Step up to the predictor polynomial A(z).
+    let mut a = [0f32; 11];
+    a[0] = 1.0;
+    for (m, k) in k.iter().enumerate() {
+        let k = *k as f32 / 512.0;
+        let prev = a;
+        for j in 1..=m {
+            a[j] += k * prev[m + 1 - j];
+        }
+        a[m + 1] = k;
+    }
+
+    // Autocorrelation of the warped power spectrum 1 / |A|^2.
+    let mut r = [0f32; 11];
+    for i in 0..=BANDS {
+        let w = PI * i as f32 / BANDS as f32;
+        // Beyond Nyquist, hold the envelope at Nyquist.
+        let (sin, cos) = sin_cos((w / scale).min(PI));
+        // A(e^jw), with powers of e^-jw by rotation.
+        let (mut re, mut im) = (0.0, 0.0);
+        let (mut zr, mut zi) = (1.0, 0.0);
+        for aj in a {
+            re += aj * zr;
+            im += aj * zi;
+            (zr, zi) = (zr * cos + zi * sin, zi * cos - zr * sin);
+        }
+        let mut power = 1.0 / (re * re + im * im).max(1e-9);
+        if i == 0 || i == BANDS {
+            power *= 0.5;
+        }
+        let (sin, cos) = sin_cos(w);
+        let (mut cr, mut ci) = (1.0, 0.0);
+        for r in &mut r {
+            *r += power * cr;
+            (cr, ci) = (cr * cos - ci * sin, cr * sin + ci * cos);
+        }
+    }
+    // A little white noise keeps the fit well conditioned.
+    r[0] *= 1.0001;
+
+    // Levinson-Durbin, back to reflection coefficients.
+    let mut out = [0; 10];
+    let mut a = [0f32; 11];
+    a[0] = 1.0;
+    let mut error = r[0];
+    for m in 1..=10 {
+        let acc = (0..m).map(|j| a[j] * r[m - j]).sum::<f32>();
+        let k = -acc / error;
+        let prev = a;
+        for j in 1..m {
+            a[j] += k * prev[m - j];
+        }
+        a[m] = k;
+        error *= 1.0 - k * k;
+        out[m - 1] = (k * 512.0).clamp(-K_MAX, K_MAX) as i16;
+    }
+    out
+}
+
+/// Sine and cosine of an angle from 0 to pi.
+fn sin_cos(x: f32) -> (f32, f32) {
+    // With y in -pi/2..pi/2, sin x = cos y and cos x = -sin y.
+    let y = x - PI / 2.0;
+    let y2 = y * y;
+    let sin_y = y * (1.0 - y2 / 6.0 * (1.0 - y2 / 20.0 * (1.0 - y2 / 42.0 * (1.0 - y2 / 72.0))));
+    let cos_y = 1.0 - y2 / 2.0 * (1.0 - y2 / 12.0 * (1.0 - y2 / 30.0 * (1.0 - y2 / 56.0)));
+    (cos_y, -sin_y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BitStream, BitWriter, Frame, Speakie, tables};
+
+    /// Decode long enough voiced frames to pass 4096 samples, then a stop
+    /// frame.
+    fn decode(effects: Effects) -> usize {
+        let layout = tables::TMS5220.layout();
+        let mut data = [0; 256];
+        let mut w = BitWriter::new(&mut data);
+        let frame = Frame {
+            energy: 10,
+            repeat: false,
+            period: 60,
+            k: [20, 20, 8, 8, 8, 8, 8, 4, 4, 4],
+        };
+        for _ in 0..25 {
+            frame.write(&mut w, &layout);
+        }
+        Frame::stop().write(&mut w, &layout);
+        let mut bs = BitStream::new(&data);
+        let mut speakie = Speakie::new();
+        speakie.set_effects(effects);
+        let mut n_samples = 0;
+        while !speakie.process_frame(&mut bs) {
+            for _ in 0..speakie.frame_len() {
+                speakie.get_sample();
+                n_samples += 1;
+            }
+        }
+        n_samples
+    }
+
+    #[test]
+    fn long_periods() {
+        let effects = [
+            Effects {
+                period_scale: 1000.0,
+                ..Effects::NONE
+            },
+            Effects {
+                robot_period: Some(u16::MAX),
+                ..Effects::NONE
+            },
+        ];
+        for effects in effects {
+            assert_eq!(decode(effects), 25 * 200);
+        }
+    }
+}
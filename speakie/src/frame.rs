@@ -0,0 +1,173 @@
+use crate::{BitStream, tables::Layout};
+
+/// One frame of TMS5220 data, as raw codes.
+///
+/// An energy code of 0 is a silence frame, and 0xf is a stop frame. For those,
+/// no other fields are coded. When `repeat` is set, the K codes are not coded
+/// and the coefficients of the previous frame are reused. Unvoiced frames
+/// (period code 0) only code K1..K4.
+///
+/// The widths of the period and K codes are given by a [`Layout`].
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub energy: u8,
+    pub repeat: bool,
+    pub period: u8,
+    pub k: [u8; 10],
+}
+
+/// An iterator over the frames of a bitstream, up to the stop frame, which
+/// isn't returned, or up to the end of the data.
+///
+/// Frames are returned as coded, with repeat frames as they are.
+pub struct Frames<'a, T: AsRef<[u8]>> {
+    bs: &'a mut BitStream<T>,
+    layout: Layout,
+    done: bool,
+}
+
+/// A sink for LPC encoded data, writing into a fixed buffer.
+///
+/// The bit order is the same as [`BitStream`].
+pub struct BitWriter<'a> {
+    buf: &'a mut [u8],
+    bit_addr: usize,
+}
+
+impl Frame {
+    /// The energy code of a stop frame.
+    pub const STOP: u8 = 0xf;
+
+    /// A silence frame.
+    pub fn silence() -> Self {
+        Self::default()
+    }
+
+    /// A stop frame.
+    pub fn stop() -> Self {
+        Self {
+            energy: Self::STOP,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_silence(&self) -> bool {
+        self.energy == 0
+    }
+
+    pub fn is_stop(&self) -> bool {
+        self.energy == Self::STOP
+    }
+
+    pub fn is_voiced(&self) -> bool {
+        self.period != 0
+    }
+
+    /// The number of K codes present in the encoded frame.
+    pub fn n_k(&self) -> usize {
+        if self.is_silence() || self.is_stop() || self.repeat {
+            0
+        } else if self.is_voiced() {
+            10
+        } else {
+            4
+        }
+    }
+
+    /// The length of the encoded frame, in bits.
+    pub fn bit_len(&self, layout: &Layout) -> usize {
+        if self.is_silence() || self.is_stop() {
+            4
+        } else {
+            let k_bits = &layout.k_bits[..self.n_k()];
+            5 + layout.period_bits as usize + k_bits.iter().map(|&b| b as usize).sum::<usize>()
+        }
+    }
+
+    /// Read one frame from the bitstream.
+    pub fn read(bs: &mut BitStream<impl AsRef<[u8]>>, layout: &Layout) -> Self {
+        let mut frame = Frame {
+            energy: bs.get_bits(4) as u8,
+            ..Default::default()
+        };
+        if frame.is_silence() || frame.is_stop() {
+            return frame;
+        }
+        frame.repeat = bs.get_bits(1) != 0;
+        frame.period = bs.get_bits(layout.period_bits as usize) as u8;
+        let n_k = frame.n_k();
+        for (k, bits) in frame.k.iter_mut().zip(layout.k_bits).take(n_k) {
+            *k = bs.get_bits(bits as usize) as u8;
+        }
+        frame
+    }
+
+    /// Write the frame.
+    ///
+    /// Panics if the frame doesn't fit in the writer's buffer.
+    pub fn write(&self, w: &mut BitWriter, layout: &Layout) {
+        w.put_bits(self.energy as usize, 4);
+        if self.is_silence() || self.is_stop() {
+            return;
+        }
+        w.put_bits(self.repeat as usize, 1);
+        w.put_bits(self.period as usize, layout.period_bits as usize);
+        for (k, bits) in self.k.iter().zip(layout.k_bits).take(self.n_k()) {
+            w.put_bits(*k as usize, bits as usize);
+        }
+    }
+}
+
+impl<'a, T: AsRef<[u8]>> Frames<'a, T> {
+    pub fn new(bs: &'a mut BitStream<T>, layout: &Layout) -> Self {
+        Self {
+            bs,
+            layout: *layout,
+            done: false,
+        }
+    }
+
+    /// Whether the data ended before a stop frame.
+    pub fn is_truncated(&self) -> bool {
+        self.bs.is_overrun()
+    }
+}
+
+impl<T: AsRef<[u8]>> Iterator for Frames<'_, T> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.done {
+            return None;
+        }
+        let frame = Frame::read(self.bs, &self.layout);
+        self.done = frame.is_stop() || self.bs.is_overrun();
+        (!self.done).then_some(frame)
+    }
+}
+
+impl<'a> BitWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, bit_addr: 0 }
+    }
+
+    /// Write the low `len` bits of `val`, most significant first.
+    ///
+    /// Panics if the buffer is full.
+    pub fn put_bits(&mut self, val: usize, len: usize) {
+        for i in (0..len).rev() {
+            let byte_addr = self.bit_addr / 8;
+            let bit = self.bit_addr % 8;
+            if bit == 0 {
+                self.buf[byte_addr] = 0;
+            }
+            self.buf[byte_addr] |= (((val >> i) & 1) as u8) << bit;
+            self.bit_addr += 1;
+        }
+    }
+
+    /// The number of bytes written, including a partial last byte.
+    pub fn byte_len(&self) -> usize {
+        self.bit_addr.div_ceil(8)
+    }
+}
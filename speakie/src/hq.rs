@@ -0,0 +1,112 @@
+//! The "speakie HQ" extended bitstream format.
+//!
+//! The TMS5220 format quantizes parameters coarsely through small tables. The
+//! HQ format instead codes the values the lattice uses directly, at the cost
+//! of roughly 5kbps. The layout of a frame is:
+//!
+//! | field     | bits    | meaning                                          |
+//! |-----------|---------|--------------------------------------------------|
+//! | energy    | 13      | 0 is silence, 0x1fff is stop                     |
+//! | repeat    | 1       | reuse the previous coefficients                  |
+//! | period    | 12      | pitch period in 1/16 samples, 0 for unvoiced     |
+//! | k1..k10   | 10 each | signed reflection coefficients, scaled by 512    |
+//!
+//! As in the TMS5220 format, silence and stop frames only have the energy
+//! field, and repeat frames have no coefficients. Unlike it, unvoiced frames
+//! code all ten coefficients. Bits are packed in the same order as
+//! [`BitStream`].
+
+use crate::{BitStream, BitWriter};
+
+pub const ENERGY_BITS: usize = 13;
+pub const PERIOD_BITS: usize = 12;
+pub const K_BITS: usize = 10;
+
+/// One frame of HQ data.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct HqFrame {
+    pub energy: u16,
+    pub repeat: bool,
+    /// Pitch period in 1/16 samples, 0 for unvoiced.
+    pub period: u16,
+    pub k: [i16; 10],
+}
+
+impl HqFrame {
+    /// The energy value of a stop frame.
+    pub const STOP: u16 = (1 << ENERGY_BITS) - 1;
+
+    /// A silence frame.
+    pub fn silence() -> Self {
+        Self::default()
+    }
+
+    /// A stop frame.
+    pub fn stop() -> Self {
+        Self {
+            energy: Self::STOP,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_silence(&self) -> bool {
+        self.energy == 0
+    }
+
+    pub fn is_stop(&self) -> bool {
+        self.energy == Self::STOP
+    }
+
+    pub fn is_voiced(&self) -> bool {
+        self.period != 0
+    }
+
+    /// The length of the encoded frame, in bits.
+    pub fn bit_len(&self) -> usize {
+        if self.is_silence() || self.is_stop() {
+            ENERGY_BITS
+        } else if self.repeat {
+            ENERGY_BITS + 1 + PERIOD_BITS
+        } else {
+            ENERGY_BITS + 1 + PERIOD_BITS + 10 * K_BITS
+        }
+    }
+
+    /// Read one frame from the bitstream.
+    pub fn read(bs: &mut BitStream<impl AsRef<[u8]>>) -> Self {
+        let mut frame = HqFrame {
+            energy: bs.get_bits(ENERGY_BITS) as u16,
+            ..Default::default()
+        };
+        if frame.is_silence() || frame.is_stop() {
+            return frame;
+        }
+        frame.repeat = bs.get_bits(1) != 0;
+        frame.period = bs.get_bits(PERIOD_BITS) as u16;
+        if !frame.repeat {
+            for k in &mut frame.k {
+                // Sign extend.
+                let shift = 16 - K_BITS;
+                *k = ((bs.get_bits(K_BITS) as i16) << shift) >> shift;
+            }
+        }
+        frame
+    }
+
+    /// Write the frame.
+    ///
+    /// Panics if the frame doesn't fit in the writer's buffer.
+    pub fn write(&self, w: &mut BitWriter) {
+        w.put_bits(self.energy as usize, ENERGY_BITS);
+        if self.is_silence() || self.is_stop() {
+            return;
+        }
+        w.put_bits(self.repeat as usize, 1);
+        w.put_bits(self.period as usize, PERIOD_BITS);
+        if !self.repeat {
+            for k in self.k {
+                w.put_bits(k as usize & ((1 << K_BITS) - 1), K_BITS);
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Intonation contours.
+//!
+//! A [`Contour`] is a pitch curve over an utterance, as breakpoints of a
+//! position from 0 (start) to 1 (end) and a pitch offset in semitones,
+//! linearly interpolated. It can rewrite the period codes of frames, so that
+//! a bitstream carries the new intonation, or scale the period while decoding
+//! through [`Effects::period_scale`](crate::effects::Effects::period_scale).
+
+use crate::{Frame, tables::Tables};
+
+/// The maximum number of breakpoints in a contour.
+pub const MAX_POINTS: usize = 8;
+
+/// The largest pitch offset, in semitones either way: two octaves.
+pub const MAX_SEMITONES: f32 = 24.0;
+
+/// A point on a contour.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Breakpoint {
+    /// Position in the utterance, from 0 to 1.
+    pub pos: f32,
+    /// Pitch offset in semitones, up to [`MAX_SEMITONES`] either way.
+    pub semitones: f32,
+}
+
+/// A pitch contour.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Contour {
+    points: [Breakpoint; MAX_POINTS],
+    n_points: usize,
+}
+
+const fn point(pos: f32, semitones: f32) -> Breakpoint {
+    Breakpoint { pos, semitones }
+}
+
+/// Level, then rising at the end.
+pub const QUESTION: [Breakpoint; 3] = [point(0.0, 0.0), point(0.6, 0.0), point(1.0, 5.0)];
+
+/// Starting a little high and falling.
+pub const STATEMENT: [Breakpoint; 2] = [point(0.0, 1.0), point(1.0, -3.0)];
+
+/// A peak early in the utterance.
+pub const EMPHASIS: [Breakpoint; 4] = [
+    point(0.0, 0.0),
+    point(0.3, 4.0),
+    point(0.6, 0.0),
+    point(1.0, -1.0),
+];
+
+impl Contour {
+    /// Create a contour from breakpoints, in order of position.
+    ///
+    /// Panics if there are more than [`MAX_POINTS`] breakpoints.
+    pub fn new(points: &[Breakpoint]) -> Self {
+        assert!(points.len() <= MAX_POINTS, "too many breakpoints");
+        let mut contour = Self {
+            n_points: points.len(),
+            ..Self::default()
+        };
+        contour.points[..points.len()].copy_from_slice(points);
+        contour
+    }
+
+    /// Parse a preset name (`question`, `statement` or `emphasis`), or
+    /// breakpoints as `pos:semitones` separated by commas, such as
+    /// `0:0,0.5:2,1:-3`. Positions must increase, and offsets be within
+    /// [`MAX_SEMITONES`].
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "question" => return Some(Self::new(&QUESTION)),
+            "statement" => return Some(Self::new(&STATEMENT)),
+            "emphasis" => return Some(Self::new(&EMPHASIS)),
+            _ => {}
+        }
+        let mut contour = Self::default();
+        for item in text.split(',') {
+            let (pos, semitones) = item.trim().split_once(':')?;
+            let point = point(pos.parse().ok()?, semitones.parse().ok()?);
+            let last = contour.points().last();
+            if !point.pos.is_finite() || last.is_some_and(|last| point.pos <= last.pos) {
+                return None;
+            }
+            if !point.semitones.is_finite() || point.semitones.abs() > MAX_SEMITONES {
+                return None;
+            }
+            *contour.points.get_mut(contour.n_points)? = point;
+            contour.n_points += 1;
+        }
+        Some(contour)
+    }
+
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points[..self.n_points]
+    }
+
+    /// The pitch offset at a position, in semitones, held past the ends.
+    pub fn semitones_at(&self, pos: f32) -> f32 {
+        let points = self.points();
+        let next = points.partition_point(|p| p.pos <= pos);
+        match (next.checked_sub(1).map(|i| &points[i]), points.get(next)) {
+            (Some(a), Some(b)) => {
+                a.semitones + (b.semitones - a.semitones) * (pos - a.pos) / (b.pos - a.pos)
+            }
+            (Some(p), None) | (None, Some(p)) => p.semitones,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// The factor to scale pitch periods by at a position. Higher pitch is a
+    /// shorter period. The offset is limited to [`MAX_SEMITONES`].
+    pub fn period_scale_at(&self, pos: f32) -> f32 {
+        let semitones = self.semitones_at(pos).clamp(-MAX_SEMITONES, MAX_SEMITONES);
+        1.0 / exp2(semitones / 12.0)
+    }
+
+    /// Rewrite the period codes of voiced frames, up to the first stop frame,
+    /// to the nearest entries of the period table.
+    ///
+    /// A frame's position is its index over the number of frames.
+    pub fn apply(&self, frames: &mut [Frame], tables: &Tables) {
+        let n = frames
+            .iter()
+            .position(Frame::is_stop)
+            .unwrap_or(frames.len());
+        for (i, frame) in frames[..n].iter_mut().enumerate() {
+            if !frame.is_voiced() || frame.is_silence() {
+                continue;
+            }
+            let pos = i as f32 / (n - 1).max(1) as f32;
+            let period = tables.period[frame.period as usize] as f32 * self.period_scale_at(pos);
+            frame.period = nearest_period(tables.period, period);
+        }
+    }
+}
+
+/// The nonzero period code closest to a period in samples.
+fn nearest_period(table: &[u8], period: f32) -> u8 {
+    let mut best = 1;
+    let mut best_err = f32::MAX;
+    for (code, value) in table.iter().enumerate().skip(1) {
+        let err = (*value as f32 - period).abs();
+        if err < best_err {
+            best = code;
+            best_err = err;
+        }
+    }
+    best as u8
+}
+
+/// 2 to the power of `x`, to within about 0.02%.
+fn exp2(x: f32) -> f32 {
+    let mut int = x as i32;
+    if (int as f32) > x {
+        int -= 1;
+    }
+    let f = (x - int as f32) * core::f32::consts::LN_2;
+    // e^f by Taylor series, f in 0..ln 2.
+    let frac = 1.0 + f * (1.0 + f / 2.0 * (1.0 + f / 3.0 * (1.0 + f / 4.0 * (1.0 + f / 5.0))));
+    let mut scale = 1.0;
+    for _ in 0..int.unsigned_abs() {
+        scale *= 2.0;
+    }
+    if int < 0 { frac / scale } else { frac * scale }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Contour::parse("question"), Some(Contour::new(&QUESTION)));
+        let contour = Contour::parse("0:0, 0.5:2,1:-3").unwrap();
+        assert_eq!(
+            contour.points(),
+            [point(0.0, 0.0), point(0.5, 2.0), point(1.0, -3.0)]
+        );
+        assert_eq!(contour.semitones_at(0.25), 1.0);
+        assert_eq!(contour.semitones_at(2.0), -3.0);
+        for bad in [
+            "",
+            "0",
+            "0:x",
+            "0:0,0.5",
+            // One breakpoint too many.
+            "0:0,0.1:0,0.2:0,0.3:0,0.4:0,0.5:0,0.6:0,0.7:0,0.8:0",
+        ] {
+            assert_eq!(Contour::parse(bad), None, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn parse_rejects_unordered_positions() {
+        for bad in [
+            "0.5:2,0:0",
+            "0:0,0.5:2,0.5:3",
+            "0:0,1:1,0.5:2",
+            "nan:0",
+            "0:0,inf:1",
+        ] {
+            assert_eq!(Contour::parse(bad), None, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn parse_rejects_extreme_semitones() {
+        for bad in [
+            "0:nan",
+            "0:inf",
+            "0:0,1:-inf",
+            "0:-60",
+            "0:0,1:24.5",
+            "0:1e30",
+        ] {
+            assert_eq!(Contour::parse(bad), None, "{bad:?}");
+        }
+        let contour = Contour::parse("0:-24,1:24").unwrap();
+        assert_eq!(contour.period_scale_at(0.0), 4.0);
+        assert!((contour.period_scale_at(1.0) - 0.25).abs() < 1e-6);
+        // Contours built directly are limited when applied.
+        let contour = Contour::new(&[point(0.0, 1e9)]);
+        assert_eq!(contour.period_scale_at(0.0), 0.25);
+    }
+}
@@ -1,225 +1,612 @@
 #![no_std]
 
+pub mod compact;
+pub mod conceal;
+pub mod detect;
+pub mod effects;
+mod frame;
+pub mod hq;
+pub mod intonation;
+pub mod lpc10;
+pub mod packet;
+pub mod postfilter;
+pub mod say;
+pub mod tables;
+
+use effects::Effects;
+pub use frame::{BitWriter, Frame, Frames};
+use tables::Tables;
+
 /// A source of LPC-10 encoded data.
 pub struct BitStream<T: AsRef<[u8]>> {
     buf: T,
     bit_addr: usize,
+    order: BitOrder,
+}
+
+/// The frame rate setting of the TMS5220C.
+///
+/// The TMS5220C can shorten frames to 6, 4 or 2 of the usual 8 interpolation
+/// steps of 25 samples each. The rate is either fixed, or given by a 2 bit
+/// code at the start of each frame.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct FrameRate {
+    /// Rate code 0..=3, for frames of 8, 6, 4 or 2 interpolation steps.
+    pub code: u8,
+    /// Whether each frame starts with a 2 bit rate code.
+    pub variable: bool,
+}
+
+/// The length of an interpolation step, in samples.
+const STEP_LEN: usize = 25;
+
+/// The length of the fade-out at the end of speech, in samples.
+const FADE_LEN: u16 = 200;
+
+/// Carriers below this RMS level don't excite the lattice, see
+/// [`Speakie::get_sample_with_carrier`].
+pub const MIN_CARRIER_RMS: u16 = 64;
+
+/// The order of bits within each byte of a bitstream.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    /// The first bit is the least significant, as the chip reads it.
+    #[default]
+    LsbFirst,
+    /// The first bit is the most significant.
+    MsbFirst,
 }
 
 /// The main decoder object.
-pub struct Speakie {
+pub struct Speakie<'a> {
+    tables: Tables<'a>,
+    // Parameters of the last frame, before effects
+    decoded: Params,
+    effects: Effects,
     last_params: Params,
     new_params: Params,
     // Result of interpolation
     params: Params,
     interp_minor: usize,
     interp_major: i32,
-    period_counter: u8,
+    rate: FrameRate,
+    // Number of interpolation steps in the current frame
+    steps: i32,
+    smoothing: bool,
+    // Excitation energy at the start of the current interpolation step
+    step_energy: u16,
+    // Samples left in the fade-out, if fading
+    fade: Option<u16>,
+    // Position in the pitch period, in 1/16 samples
+    period_counter: u16,
     rand: u16,
+    // Running mean square of an external carrier
+    carrier_power: u32,
+    lattice: Lattice,
+}
+
+/// The lattice filter, shared by the decoders.
+#[derive(Clone, Copy, Default, Debug)]
+struct Lattice {
     x: [i16; 11],
 }
 
+/// Synthesis parameters, as decoded from a frame or interpolated.
 #[derive(Clone, Copy, Default, Debug)]
-struct Params {
-    energy: u16,
-    period: u8,
-    k: [i16; 10],
+pub struct Params {
+    pub energy: u16,
+    /// Pitch period in 1/16 samples, 0 for unvoiced.
+    pub period: u16,
+    /// Reflection coefficients, scaled by 512.
+    pub k: [i16; 10],
     is_stop: bool,
 }
 
+/// The internal state behind one output sample, for debugging.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Tap {
+    /// The interpolated parameters.
+    pub params: Params,
+    /// The excitation fed into the lattice.
+    pub u10: i16,
+    /// The lattice state, `x[0]` being the output before any fade.
+    pub x: [i16; 11],
+    /// Whether the lattice output was clipped to 16 bits.
+    pub clipped: bool,
+    /// The output sample, as returned by [`get_sample`](Speakie::get_sample).
+    pub sample: i16,
+}
+
 impl<T: AsRef<[u8]>> BitStream<T> {
     pub fn new(buf: T) -> Self {
+        Self::with_bit_order(buf, BitOrder::LsbFirst)
+    }
+
+    pub fn with_bit_order(buf: T, order: BitOrder) -> Self {
         let bit_addr = 0;
-        Self { buf, bit_addr }
+        Self {
+            buf,
+            bit_addr,
+            order,
+        }
     }
 
-    fn get_bits(&mut self, len: usize) -> usize {
+    /// Read `len` bits, such as a field of a frame, as an unsigned value.
+    pub fn get_bits(&mut self, len: usize) -> usize {
+        if len > 8 {
+            let hi = self.get_bits(len - 8);
+            return (hi << 8) | self.get_bits(8);
+        }
         let buf = self.buf.as_ref();
         let bit = self.bit_addr % 8;
         let byte_addr = self.bit_addr / 8;
         self.bit_addr += len;
-        let mut word = (buf[byte_addr].reverse_bits() as u16) << 8;
+        // Bytes past the end of the buffer read as zero.
+        let byte = |i: usize| {
+            let byte = buf.get(i).copied().unwrap_or_default();
+            match self.order {
+                BitOrder::LsbFirst => byte.reverse_bits(),
+                BitOrder::MsbFirst => byte,
+            }
+        };
+        let mut word = (byte(byte_addr) as u16) << 8;
         if bit + len > 8 {
-            word |= buf[byte_addr + 1].reverse_bits() as u16;
+            word |= byte(byte_addr + 1) as u16;
         }
         ((word << bit) >> (16 - len)) as usize
     }
+
+    /// The number of bits read so far.
+    pub fn bit_pos(&self) -> usize {
+        self.bit_addr
+    }
+
+    /// Whether reads have gone past the end of the buffer.
+    pub fn is_overrun(&self) -> bool {
+        self.bit_addr > self.buf.as_ref().len() * 8
+    }
+
+    /// Move to a bit position.
+    pub fn seek(&mut self, bit_pos: usize) {
+        self.bit_addr = bit_pos;
+    }
 }
 
-const ENERGY: [u16; 0x10] = [
-    0, 52, 87, 123, 174, 246, 348, 491, 694, 981, 1385, 1957, 2764, 3904, 5514, 7789,
-];
-const PERIOD: [u8; 0x40] = [
-    0, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37,
-    38, 39, 40, 41, 42, 44, 46, 48, 50, 52, 53, 56, 58, 60, 62, 65, 68, 70, 72, 76, 78, 80, 84, 86,
-    91, 94, 98, 101, 105, 109, 114, 118, 122, 127, 132, 137, 142, 148, 153, 159,
-];
-const K1: [i16; 0x20] = [
-    -501, -498, -497, -495, -493, -491, -488, -482, -478, -474, -469, -464, -459, -452, -445, -437,
-    -412, -380, -339, -288, -227, -158, -81, -1, 80, 157, 226, 287, 337, 379, 411, 436,
-];
-const K2: [i16; 0x20] = [
-    -328, -303, -274, -244, -211, -175, -138, -99, -59, -18, 24, 64, 105, 143, 180, 215, 248, 278,
-    306, 331, 354, 374, 392, 408, 422, 435, 445, 455, 463, 470, 476, 506,
-];
-const K3: [i16; 0x10] = [
-    -441, -387, -333, -279, -225, -171, -117, -63, -9, 45, 98, 152, 206, 260, 314, 368,
-];
-const K4: [i16; 0x10] = [
-    -328, -273, -217, -161, -106, -50, 5, 61, 116, 172, 228, 283, 339, 394, 450, 506,
-];
-const K5: [i16; 0x10] = [
-    -328, -282, -235, -189, -142, -96, -50, -3, 43, 90, 136, 182, 229, 275, 322, 368,
-];
-const K6: [i16; 0x10] = [
-    -256, -212, -168, -123, -79, -35, 10, 54, 98, 143, 187, 232, 276, 320, 365, 409,
-];
-const K7: [i16; 0x10] = [
-    -308, -260, -212, -164, -117, -69, -21, 27, 75, 122, 170, 218, 266, 314, 361, 409,
-];
-const K8: [i16; 0x08] = [-256, -161, -66, 29, 124, 219, 314, 409];
-const K9: [i16; 0x08] = [-256, -176, -96, -15, 65, 146, 226, 307];
-const K10: [i16; 0x08] = [-205, -132, -59, 14, 87, 160, 234, 307];
-
-const CHIRP: [u8; 52] = [
-    0x00, 0x03, 0x0f, 0x28, 0x4c, 0x6c, 0x71, 0x50, 0x25, 0x26, 0x4c, 0x44, 0x1a, 0x32, 0x3b, 0x13,
-    0x37, 0x1a, 0x25, 0x1f, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00,
-];
-
-//const CHIRP2: [u8; 9] = [0x7f, 0x40, 0x20, 0x10, 0x8, 4, 2, 1, 0];
-
-impl Speakie {
-    /// Create a new decoder.
+impl FrameRate {
+    /// Decode a TMS5220C "load frame rate" command byte.
+    ///
+    /// These are 0x00..=0x0f and 0x20..=0x2f. The low 2 bits are the rate code
+    /// and bit 2 selects variable rate. Other commands return `None`.
+    pub fn from_command(cmd: u8) -> Option<Self> {
+        matches!(cmd & 0x70, 0x00 | 0x20).then_some(FrameRate {
+            code: cmd & 3,
+            variable: cmd & 4 != 0,
+        })
+    }
+
+    /// The "load frame rate" command byte for this setting: the rate code in
+    /// the low 2 bits, and bit 2 set for variable rate.
+    pub fn command(self) -> u8 {
+        self.code | ((self.variable as u8) << 2)
+    }
+
+    /// The number of interpolation steps in a frame with the given rate code.
+    pub fn steps(code: u8) -> usize {
+        8 - 2 * (code & 3) as usize
+    }
+
+    /// The length of a frame at the fixed rate, in samples.
+    pub fn frame_len(self) -> usize {
+        Self::steps(self.code) * STEP_LEN
+    }
+}
+
+impl Default for Speakie<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Speakie<'static> {
+    /// Create a new decoder, with the TMS5220 tables.
     pub fn new() -> Self {
+        Self::with_tables(tables::TMS5220)
+    }
+}
+
+impl<'a> Speakie<'a> {
+    /// Create a new decoder with the given tables.
+    ///
+    /// The tables should be [validated](Tables::validate), otherwise decoding
+    /// may panic.
+    pub fn with_tables(tables: Tables<'a>) -> Self {
         Self {
+            tables,
+            decoded: Params::default(),
+            effects: Effects::NONE,
             last_params: Params::default(),
             new_params: Params::default(),
             params: Params::default(),
             interp_major: 0,
             interp_minor: 0,
+            rate: FrameRate::default(),
+            steps: 8,
+            smoothing: false,
+            step_energy: 0,
+            fade: None,
             period_counter: 0,
             rand: 1,
-            x: [0; 11],
+            carrier_power: 0,
+            lattice: Lattice::default(),
+        }
+    }
+
+    /// Set the frame rate, for example at the start of an utterance.
+    pub fn set_rate(&mut self, rate: FrameRate) {
+        self.rate = rate;
+    }
+
+    pub fn rate(&self) -> FrameRate {
+        self.rate
+    }
+
+    /// Enable click-free transitions.
+    ///
+    /// The excitation energy then ramps per sample instead of changing at each
+    /// interpolation step, which also softens jumps where interpolation is
+    /// inhibited. After a stop frame, [`get_sample`](Self::get_sample)
+    /// keeps returning the lattice ringing down, faded out over 200 samples,
+    /// until [`is_finished`](Self::is_finished).
+    ///
+    /// This doesn't change how the bitstream is interpreted.
+    pub fn set_smoothing(&mut self, smoothing: bool) {
+        self.smoothing = smoothing;
+    }
+
+    /// Set [voice effects](effects), from the current frame on.
+    ///
+    /// This can be called while speaking.
+    pub fn set_effects(&mut self, effects: Effects) {
+        self.effects = effects;
+        self.new_params = effects.apply(&self.decoded);
+    }
+
+    pub fn effects(&self) -> Effects {
+        self.effects
+    }
+
+    /// Interrupt speech, fading out over 200 samples.
+    ///
+    /// Stop processing frames, and call [`get_sample`](Self::get_sample)
+    /// until [`is_finished`](Self::is_finished).
+    pub fn abort_with_fade(&mut self) {
+        if self.fade.is_none() {
+            self.fade = Some(FADE_LEN);
         }
     }
 
+    /// Whether a fade-out has completed.
+    ///
+    /// Processing another frame starts speech again.
+    pub fn is_finished(&self) -> bool {
+        self.fade == Some(0)
+    }
+
+    /// The length of the current frame, in samples.
+    ///
+    /// This is 200 at the nominal speech rate.
+    pub fn frame_len(&self) -> usize {
+        self.steps as usize * STEP_LEN
+    }
+
     /// Process one frame.
     ///
-    /// This method should be called at the start of each frame, which is 200 samples
-    /// at the nominal speech rate.
+    /// This method should be called at the start of each frame, which is
+    /// [`frame_len`](Self::frame_len) samples.
     ///
-    /// Returns true on "stop" frame.
+    /// Returns true on "stop" frame, or when the bitstream runs out in the
+    /// middle of a frame, which is treated as one.
     pub fn process_frame(&mut self, bs: &mut BitStream<impl AsRef<[u8]>>) -> bool {
+        let steps = self.read_rate(bs);
+        let mut frame = Frame::read(bs, &self.tables.layout());
+        if bs.is_overrun() {
+            frame = Frame::stop();
+        }
+        let stop = self.apply_frame(&frame);
+        self.steps = steps;
+        stop
+    }
+
+    /// Process one frame that has already been parsed.
+    ///
+    /// This is the same as [`process_frame`](Self::process_frame), for frames
+    /// that come from somewhere other than a bitstream, such as a packet or
+    /// loss concealment.
+    pub fn apply_frame(&mut self, frame: &Frame) -> bool {
+        self.last_params = self.new_params;
+        self.decoded.update(frame, &self.tables);
+        self.finish_frame()
+    }
+
+    /// The parameters decoded from the last frame, which the current frame
+    /// interpolates towards, before any effects.
+    pub fn frame_params(&self) -> Params {
+        self.decoded
+    }
+
+    /// Process one frame of the [HQ](hq) format.
+    ///
+    /// Returns true on "stop" frame, or when the bitstream runs out.
+    pub fn process_hq_frame(&mut self, bs: &mut BitStream<impl AsRef<[u8]>>) -> bool {
+        let steps = self.read_rate(bs);
+        let mut frame = hq::HqFrame::read(bs);
+        if bs.is_overrun() {
+            frame = hq::HqFrame::stop();
+        }
+        let stop = self.apply_hq_frame(&frame);
+        self.steps = steps;
+        stop
+    }
+
+    /// Process one frame of the [HQ](hq) format that has already been parsed.
+    pub fn apply_hq_frame(&mut self, frame: &hq::HqFrame) -> bool {
         self.last_params = self.new_params;
-        self.new_params.read(bs);
+        self.decoded.update_hq(frame);
+        self.finish_frame()
+    }
+
+    /// The number of interpolation steps in the next frame of a bitstream.
+    fn read_rate(&mut self, bs: &mut BitStream<impl AsRef<[u8]>>) -> i32 {
+        if self.rate.variable {
+            FrameRate::steps(bs.get_bits(2) as u8) as i32
+        } else {
+            FrameRate::steps(self.rate.code) as i32
+        }
+    }
+
+    fn finish_frame(&mut self) -> bool {
+        self.new_params = self.effects.apply(&self.decoded);
         if self.last_params.inhibit_interp(&self.new_params) {
             self.last_params = self.new_params;
         }
+        self.steps = FrameRate::steps(self.rate.code) as i32;
         self.interp_major = 0;
         self.interp_minor = 0;
+        self.fade = (self.smoothing && self.new_params.is_stop).then_some(FADE_LEN);
         self.new_params.is_stop
     }
 
     /// Get one sample.
     pub fn get_sample(&mut self) -> i16 {
+        self.synthesize(None, None)
+    }
+
+    /// Get one sample, recording the state behind it.
+    pub fn get_sample_tap(&mut self) -> Tap {
+        let mut tap = Tap::default();
+        tap.sample = self.synthesize(Some(&mut tap), None);
+        tap
+    }
+
+    /// Get one sample, exciting the lattice with a sample of an external
+    /// carrier instead of pulses or noise, as in a vocoder.
+    ///
+    /// The carrier is normalized by its running RMS level, so that it
+    /// excites the lattice about as strongly as the noise of the same frame
+    /// would. Carriers quieter than [`MIN_CARRIER_RMS`] are treated as
+    /// silence rather than amplified.
+    pub fn get_sample_with_carrier(&mut self, carrier: i16) -> i16 {
+        self.synthesize(None, Some(carrier))
+    }
+
+    fn synthesize(&mut self, tap: Option<&mut Tap>, carrier: Option<i16>) -> i16 {
+        if self.is_finished() {
+            return 0;
+        }
         if self.interp_minor == 0 {
-            self.interp_major = (self.interp_major + 1).min(8);
-            self.params = self
-                .last_params
-                .interpolate(&self.new_params, self.interp_major);
+            self.step_energy = self.params.energy;
+            self.interp_major = (self.interp_major + 1).min(self.steps);
+            self.params =
+                self.last_params
+                    .interpolate(&self.new_params, self.interp_major, self.steps);
             //println!("last_params = {:?}", self.last_params);
             //println!("new_params = {:?}", self.new_params);
             //println!("interp result = {:?} {}", self.params, self.interp_major);
         }
         self.interp_minor += 1;
-        // TODO: make rate adjustable
-        if self.interp_minor == 25 {
+        let mut energy = self.params.energy as i32;
+        if self.smoothing {
+            let e0 = self.step_energy as i32;
+            energy = e0 + (energy - e0) * self.interp_minor as i32 / STEP_LEN as i32;
+        }
+        if self.interp_minor == STEP_LEN {
             self.interp_minor = 0;
         }
         let u10;
-        if self.params.is_voiced() {
-            let chirp = CHIRP
-                .get(self.period_counter as usize)
+        if let Some(carrier) = carrier {
+            let c = carrier as i32;
+            // Mean square over about 256 samples.
+            self.carrier_power =
+                self.carrier_power - (self.carrier_power >> 8) + ((c * c) as u32 >> 8);
+            let rms = self.carrier_power.isqrt() as i32;
+            let energy =
+                energy * self.params.noise_energy() as i32 / (self.params.energy as i32).max(1);
+            u10 = if rms < MIN_CARRIER_RMS as i32 {
+                0
+            } else {
+                (c * energy / rms).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+            };
+        } else if self.params.is_voiced() {
+            let chirp = self
+                .tables
+                .chirp
+                .get((self.period_counter >> 4) as usize)
                 .cloned()
                 .unwrap_or_default() as i8;
-            u10 = (((chirp as i32) * (self.params.energy as i32)) >> 6) as i16;
-            self.period_counter += 1;
+            u10 = (((chirp as i32) * energy) >> 6) as i16;
+            self.period_counter += 16;
             if self.period_counter >= self.params.period {
-                self.period_counter = 0;
+                // Carry a fractional period over to the next pulse.
+                let rem = self.period_counter - self.params.period;
+                self.period_counter = if rem < 16 { rem } else { 0 };
             }
         } else {
             self.rand = (self.rand >> 1) ^ if (self.rand & 1) != 0 { 0xb800 } else { 0 };
             u10 = if (self.rand & 1) != 0 {
-                self.params.energy as i16
+                energy as i16
             } else {
-                -(self.params.energy as i16)
+                -(energy as i16)
             };
         }
-        let mut u = u10 as i32;
+        let (mut u, clipped) = self.lattice.filter(&self.params.k, u10 as i32);
+        if let Some(tap) = tap {
+            tap.params = self.params;
+            tap.u10 = u10;
+            tap.x = self.lattice.x;
+            tap.clipped = clipped;
+        }
+        if let Some(fade) = &mut self.fade {
+            u = u * *fade as i32 / FADE_LEN as i32;
+            *fade -= 1;
+            if *fade == 0 {
+                self.lattice = Lattice::default();
+                self.params.energy = 0;
+                self.new_params.energy = 0;
+                self.decoded.energy = 0;
+            }
+        }
+        u as i16
+    }
+}
+
+impl Lattice {
+    /// Filter one sample of excitation, with coefficients scaled by 512.
+    ///
+    /// Returns the output, and whether it was clipped to 16 bits.
+    fn filter(&mut self, k: &[i16; 10], u: i32) -> (i32, bool) {
+        let mut u = u;
         for i in (0..10).rev() {
-            u = u.wrapping_sub((self.params.k[i] as i32).wrapping_mul(self.x[i] as i32) >> 9);
-            self.x[i + 1] = self.x[i].wrapping_add(((self.params.k[i] as i32 * u) >> 9) as i16);
+            u = u.wrapping_sub((k[i] as i32).wrapping_mul(self.x[i] as i32) >> 9);
+            self.x[i + 1] = self.x[i].wrapping_add(((k[i] as i32 * u) >> 9) as i16);
         }
+        let unclipped = u;
         // TODO: maybe change this
         u = u.clamp(-32768, 32767);
         self.x[0] = u as i16;
-        u as i16
+        (u, u != unclipped)
     }
 }
 
 impl Params {
-    fn read(&mut self, bs: &mut BitStream<impl AsRef<[u8]>>) {
-        let energy = bs.get_bits(4);
-        if energy == 0 {
-            self.energy = 0;
-        } else if energy == 0xf {
+    fn update(&mut self, frame: &Frame, tables: &Tables) {
+        self.is_stop = frame.is_stop();
+        if frame.is_silence() || frame.is_stop() {
             self.energy = 0;
-            self.is_stop = true;
         } else {
-            self.energy = ENERGY[energy] as u16;
-            let repeat = bs.get_bits(1);
-            self.period = PERIOD[bs.get_bits(6)];
-            if repeat == 0 {
-                self.k[0] = K1[bs.get_bits(5)];
-                self.k[1] = K2[bs.get_bits(5)];
-                self.k[2] = K3[bs.get_bits(4)];
-                self.k[3] = K4[bs.get_bits(4)];
-                if self.period != 0 {
-                    self.k[4] = K5[bs.get_bits(4)];
-                    self.k[5] = K6[bs.get_bits(4)];
-                    self.k[6] = K7[bs.get_bits(4)];
-                    self.k[7] = K8[bs.get_bits(3)];
-                    self.k[8] = K9[bs.get_bits(3)];
-                    self.k[9] = K10[bs.get_bits(3)];
-                } else {
+            self.energy = tables.energy[frame.energy as usize];
+            self.period = (tables.period[frame.period as usize] as u16) << 4;
+            if !frame.repeat {
+                for i in 0..frame.n_k() {
+                    self.k[i] = tables.k[i][frame.k[i] as usize];
+                }
+                if !frame.is_voiced() {
                     self.k[4..].fill(0);
                 }
             }
         }
     }
 
-    fn interpolate(&self, new_params: &Self, t: i32) -> Self {
-        fn lerp(x0: i32, x1: i32, t: i32) -> i32 {
-            (x0 * 8 + (x1 - x0) * t) / 8
+    fn update_hq(&mut self, frame: &hq::HqFrame) {
+        self.is_stop = frame.is_stop();
+        if frame.is_silence() || frame.is_stop() {
+            self.energy = 0;
+        } else {
+            self.energy = frame.energy;
+            self.period = frame.period;
+            if !frame.repeat {
+                self.k = frame.k;
+            }
         }
+    }
+
+    /// Interpolate at step `t` of `n`.
+    fn interpolate(&self, new_params: &Self, t: i32, n: i32) -> Self {
+        let lerp = |x0: i32, x1: i32, t: i32| (x0 * n + (x1 - x0) * t) / n;
 
+        let mut period = lerp(self.period as i32, new_params.period as i32, t);
+        // Whole sample periods, as in TMS5220 data, interpolate in whole samples.
+        if (self.period | new_params.period) & 15 == 0 {
+            period &= !15;
+        }
         Params {
             energy: lerp(self.energy as i32, new_params.energy as i32, t) as u16,
-            period: lerp(self.period as i32, new_params.period as i32, t) as u8,
+            period: period as u16,
             k: core::array::from_fn(|i| lerp(self.k[i] as i32, new_params.k[i] as i32, t) as i16),
             is_stop: false,
         }
     }
 
+    /// The energy of noise excitation about as loud as this frame.
+    ///
+    /// Noise excites every sample and chirps once a period, so the encoder
+    /// scales the energy of voiced frames up by about period / 30.
+    fn noise_energy(&self) -> u16 {
+        if self.is_voiced() {
+            let period = self.period as u32 >> 4;
+            (self.energy as u32 * 30 / period.max(30)) as u16
+        } else {
+            self.energy
+        }
+    }
+
     fn is_voiced(&self) -> bool {
         self.period != 0
     }
 
-    fn inhibit_interp(&self, new_params: &Self) -> bool {
+    /// Whether the decoder jumps to `new_params` at the start of a frame
+    /// instead of interpolating towards them, as the TMS5220 does between
+    /// voiced and unvoiced frames and coming out of silence.
+    pub fn inhibit_interp(&self, new_params: &Self) -> bool {
         self.is_voiced() != new_params.is_voiced()
             || (self.energy == 0 && new_params.energy != 0)
             || (!self.is_voiced() && new_params.energy == 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_stream_stops() {
+        // A voiced frame cut off after 32 bits, with no stop frame.
+        let data = [0x02, 0xc8, 0x9a, 0x35];
+        let mut bs = BitStream::new(&data);
+        let mut speakie = Speakie::new();
+        let mut n_frames = 0;
+        while !speakie.process_frame(&mut bs) {
+            n_frames += 1;
+            assert!(n_frames < 10, "decoding didn't stop");
+        }
+        assert!(bs.is_overrun());
+
+        let mut bs = BitStream::new(&data);
+        let mut speakie = Speakie::new();
+        assert!(speakie.process_hq_frame(&mut bs) || speakie.process_hq_frame(&mut bs));
+    }
+
+    #[test]
+    fn rate_commands() {
+        for cmd in 0..8 {
+            let rate = FrameRate::from_command(cmd).unwrap();
+            assert_eq!((rate.code, rate.variable), (cmd & 3, cmd & 4 != 0));
+            assert_eq!(rate.command(), cmd);
+            // The upper half of each range gives the same setting.
+            assert_eq!(FrameRate::from_command(cmd | 0x08), Some(rate));
+            assert_eq!(FrameRate::from_command(cmd | 0x20), Some(rate));
+        }
+        for cmd in [0x10, 0x40, 0x50, 0x60, 0x70, 0xff] {
+            assert_eq!(FrameRate::from_command(cmd), None);
+        }
+    }
+}
@@ -0,0 +1,735 @@
+//! Decoder for the US federal standard FS-1015 "LPC-10" vocoder at 2400bps.
+//!
+//! Each frame is 54 bits for 22.5ms (180 samples) of speech: a 7 bit pitch
+//! and voicing code, a 5 bit RMS code, ten reflection coefficients and a sync
+//! bit that alternates between frames. The bits of the parameters are
+//! interleaved in the order given by the standard. RC1 and RC2 are coded as
+//! log area ratios, the rest linearly.
+//!
+//! Frames that aren't fully voiced only carry RC1..RC4, and the bits of
+//! RC5..RC10 hold Hamming (8,4) parity for the high bits of RC1..RC4 and the
+//! RMS. The decoder corrects single bit errors in these, and falls back to the
+//! previous frame's value on double errors.
+//!
+//! Parameter decoding follows the reference implementation. The synthesis has
+//! the same structure, with the standard's 25 sample excitation pulse, noise,
+//! an all-pole filter and de-emphasis, but runs on speakie's fixed-point
+//! lattice and is not bit-exact with it.
+
+use crate::{BitOrder, BitStream, BitWriter, Lattice};
+
+/// The length of a frame, in samples.
+pub const FRAME_LEN: usize = 180;
+
+/// The length of a frame, in bits.
+pub const FRAME_BITS: usize = 54;
+
+/// Bits of the reflection coefficients in voiced frames.
+pub const RC_BITS: [usize; 10] = [5, 5, 5, 5, 4, 4, 4, 4, 3, 2];
+
+const STEPS: usize = 6;
+const STEP_LEN: usize = FRAME_LEN / STEPS;
+
+// Parameters of the first 53 bits of a frame, in transmission order: 0 is the
+// pitch, 1 the RMS and 2.. RC1..RC10. Each parameter is sent MSB first.
+const BIT_ORDER: [u8; 53] = [
+    9, 10, 8, 7, 6, 10, 0, 9, 11, 7, 6, 0, 10, 8, 7, 6, 9, 11, 8, 7, 6, 5, 0, 9, 8, 3, 0, 1, 5, 4,
+    3, 2, 1, 5, 4, 3, 2, 5, 0, 1, 4, 5, 2, 1, 0, 4, 3, 2, 1, 0, 4, 3, 2,
+];
+
+// Pitch and voicing codes of voiced frames, for each of the 60 pitch periods.
+const ENTAU: [u8; 60] = [
+    19, 11, 27, 25, 29, 21, 23, 22, 30, 14, 15, 7, 39, 38, 46, 42, 43, 41, 45, 37, 53, 49, 51, 50,
+    54, 52, 60, 56, 58, 26, 90, 88, 92, 84, 86, 82, 83, 81, 85, 69, 77, 73, 75, 74, 78, 70, 71, 67,
+    99, 97, 113, 112, 114, 98, 106, 104, 108, 100, 101, 76,
+];
+
+// Hamming (8,4) parity of each 4 bit value.
+const ENCTAB: [u8; 16] = [0, 7, 11, 12, 13, 10, 6, 1, 14, 9, 5, 2, 3, 4, 8, 15];
+
+const RMST: [u16; 64] = [
+    1024, 936, 856, 784, 718, 656, 600, 550, 502, 460, 420, 384, 352, 328, 294, 270, 246, 226, 206,
+    188, 172, 158, 144, 132, 120, 110, 102, 92, 84, 78, 70, 64, 60, 54, 50, 46, 42, 38, 34, 32, 30,
+    26, 24, 22, 20, 18, 17, 16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+];
+
+// Log area ratio coding of RC1 and RC2.
+const ENTAB6: [u8; 64] = [
+    0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4,
+    4, 4, 5, 5, 5, 5, 5, 6, 6, 6, 6, 6, 7, 7, 7, 7, 7, 8, 8, 8, 8, 9, 9, 9, 10, 10, 11, 11, 12, 13,
+    14, 15,
+];
+const DETAB7: [i32; 32] = [
+    4, 11, 18, 25, 32, 39, 46, 53, 60, 66, 72, 77, 82, 87, 92, 96, 101, 104, 108, 111, 114, 115,
+    117, 119, 121, 122, 123, 124, 125, 126, 127, 127,
+];
+
+// Linear coding of RC3..RC10, the encoder's tables in reverse order.
+const ENADD: [i32; 8] = [1920, -768, 2432, 1280, 3584, 1536, 2816, -1152];
+const ENSCL: [f32; 8] = [
+    0.0204, 0.0167, 0.0145, 0.0147, 0.0143, 0.0135, 0.0125, 0.0112,
+];
+const ENBITS: [u32; 8] = [6, 5, 4, 4, 4, 4, 3, 3];
+const DESCL: [f32; 8] = [
+    0.6953, 0.625, 0.5781, 0.5469, 0.5312, 0.5391, 0.4688, 0.3828,
+];
+const DEADD: [i32; 8] = [1152, -2816, -1536, -3584, -1280, -2432, 768, -1920];
+const QB: [i32; 8] = [511, 511, 1023, 1023, 1023, 1023, 2047, 4095];
+const NBIT: [u32; 10] = [8, 8, 5, 5, 4, 4, 4, 4, 3, 2];
+
+// The voiced excitation pulse.
+const KEXC: [i16; 25] = [
+    8, -16, 26, -48, 86, -162, 294, -502, 718, -728, 184, 672, -610, -672, 184, 728, 718, 502, 294,
+    162, 86, 48, 26, 16, 8,
+];
+
+/// One frame of LPC-10 data, as transmitted.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Lpc10Frame {
+    /// Pitch and voicing code.
+    pub pitch: u8,
+    pub rms: u8,
+    /// Reflection coefficient codes, in two's complement of [`RC_BITS`]
+    /// bits. In frames that aren't fully voiced, RC5..RC10 hold parity.
+    pub rc: [u8; 10],
+    pub sync: bool,
+}
+
+/// The voicing of a frame, from its pitch code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Voicing {
+    Unvoiced,
+    /// Voicing changes within the frame.
+    Transition,
+    /// Voiced, with the pitch period in samples.
+    Voiced(u8),
+    /// Not a valid code, so there are bit errors.
+    Invalid,
+}
+
+/// Decoded parameters of a frame.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Lpc10Params {
+    /// Voicing of each half of the frame.
+    pub voiced: [bool; 2],
+    /// Pitch period in samples, kept from the last voiced frame.
+    pub period: u8,
+    pub rms: u16,
+    /// Reflection coefficients, scaled by 512 and with the sign the lattice
+    /// uses, which is the opposite of the standard's.
+    pub k: [i16; 10],
+}
+
+/// Counts of transmission errors seen by the decoder.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ErrorCounts {
+    /// Frames whose sync bit didn't alternate.
+    pub sync: usize,
+    /// Invalid pitch and voicing codes.
+    pub pitch: usize,
+    /// Parameters corrected by the Hamming code.
+    pub corrected: usize,
+    /// Parameters with uncorrectable errors.
+    pub uncorrectable: usize,
+}
+
+/// The LPC-10 decoder.
+pub struct Lpc10Decoder {
+    last: Lpc10Params,
+    new: Lpc10Params,
+    // Result of interpolation
+    params: Lpc10Params,
+    // The last frame, after error correction
+    last_frame: Lpc10Frame,
+    next_sync: Option<bool>,
+    errors: ErrorCounts,
+    // Position in the frame
+    pos: usize,
+    // The gain of the pulse excitation for unit RMS, of the last and new
+    // frames, measured once per frame
+    last_pulse_scale: i64,
+    new_pulse_scale: i64,
+    // Excitation gains for the current step, pulse scale in 1/256
+    noise_gain: i64,
+    pulse_gain: i64,
+    period_counter: u8,
+    rand: u16,
+    lattice: Lattice,
+    deemph: Deemphasis,
+}
+
+/// The de-emphasis filter of the reference decoder, which undoes the
+/// encoder's pre-emphasis and removes DC.
+#[derive(Clone, Copy, Default, Debug)]
+struct Deemphasis {
+    x: [i32; 2],
+    y: [i32; 3],
+}
+
+/// The pitch period of each of the 60 pitch codes.
+fn lag(i: usize) -> u8 {
+    match i {
+        0..20 => 20 + i as u8,
+        20..40 => 40 + 2 * (i - 20) as u8,
+        _ => 80 + 4 * (i - 40) as u8,
+    }
+}
+
+/// Sign extend a code of `bits` bits.
+fn sign_extend(code: u8, bits: usize) -> i32 {
+    let shift = 32 - bits;
+    ((code as i32) << shift) >> shift
+}
+
+/// Correct 4 data bits with their Hamming parity.
+///
+/// Returns `None` if there is more than one bit error.
+fn ham84(data: u8, parity: u8) -> Option<u8> {
+    (0..16).find(|&d| ((d ^ data) | ((ENCTAB[d as usize] ^ parity) << 4)).count_ones() <= 1)
+}
+
+impl Lpc10Frame {
+    /// The pitch code of unvoiced frames.
+    pub const UNVOICED: u8 = 0;
+    /// The pitch code of frames where voicing changes.
+    pub const TRANSITION: u8 = 127;
+
+    /// Quantize parameters as the reference encoder does.
+    ///
+    /// `rms` is in the units of the decoder's output divided by 8, and `rc`
+    /// are the reflection coefficients scaled by 32768, with the standard's
+    /// sign. Voiced periods are rounded to the nearest coded one. Frames that
+    /// aren't voiced are [protected](Self::protect).
+    ///
+    /// Panics on [`Voicing::Invalid`].
+    pub fn new(voicing: Voicing, rms: u16, rc: &[i16; 10]) -> Self {
+        let pitch = match voicing {
+            Voicing::Unvoiced => Self::UNVOICED,
+            Voicing::Transition => Self::TRANSITION,
+            Voicing::Voiced(period) => {
+                let i = (0..60).min_by_key(|&i| lag(i).abs_diff(period)).unwrap();
+                ENTAU[i]
+            }
+            Voicing::Invalid => panic!("invalid voicing"),
+        };
+        let rms = (0..32)
+            .min_by_key(|&code| RMST[(31 - code) * 2].abs_diff(rms))
+            .unwrap() as u8;
+        let rc = core::array::from_fn(|i| {
+            let irc = rc[i] as i32;
+            let code = if i < 2 {
+                let code = ENTAB6[(irc.unsigned_abs() / 512).min(63) as usize] as i32;
+                if irc < 0 { -code } else { code }
+            } else {
+                let j = 9 - i;
+                let code = (((irc / 2 + ENADD[j]) as f32 * ENSCL[j]) as i32).clamp(-127, 127);
+                let scaled = code / (1 << ENBITS[j]);
+                if code < 0 { scaled - 1 } else { scaled }
+            };
+            (code & ((1 << RC_BITS[i]) - 1)) as u8
+        });
+        let mut frame = Lpc10Frame {
+            pitch,
+            rms,
+            rc,
+            sync: false,
+        };
+        if !matches!(voicing, Voicing::Voiced(_)) {
+            frame.protect();
+        }
+        frame
+    }
+
+    /// The pitch code of a voiced frame, if the period is one of the 60
+    /// coded periods from 20 to 156 samples.
+    pub fn pitch_code(period: u8) -> Option<u8> {
+        (0..60).find(|&i| lag(i) == period).map(|i| ENTAU[i])
+    }
+
+    pub fn voicing(&self) -> Voicing {
+        match self.pitch {
+            Self::UNVOICED => Voicing::Unvoiced,
+            Self::TRANSITION => Voicing::Transition,
+            code => match ENTAU.iter().position(|&c| c == code) {
+                Some(i) => Voicing::Voiced(lag(i)),
+                None => Voicing::Invalid,
+            },
+        }
+    }
+
+    /// Fill RC5..RC10 with the parity bits of a frame that isn't fully
+    /// voiced, as the encoder does.
+    pub fn protect(&mut self) {
+        let parity = |code: u8| ENCTAB[((code >> 1) & 15) as usize];
+        self.rc[4] = parity(self.rc[0]);
+        self.rc[5] = parity(self.rc[1]);
+        self.rc[6] = parity(self.rc[2]);
+        self.rc[7] = parity(self.rms);
+        self.rc[8] = parity(self.rc[3]) >> 1;
+        self.rc[9] = parity(self.rc[3]) & 1;
+    }
+
+    /// Read one frame from the bitstream.
+    pub fn read(bs: &mut BitStream<impl AsRef<[u8]>>) -> Self {
+        let mut values = [0u8; 12];
+        for param in BIT_ORDER {
+            let value = &mut values[param as usize];
+            *value = (*value << 1) | bs.get_bits(1) as u8;
+        }
+        Lpc10Frame {
+            pitch: values[0],
+            rms: values[1],
+            rc: core::array::from_fn(|i| values[i + 2]),
+            sync: bs.get_bits(1) != 0,
+        }
+    }
+
+    /// Write the frame.
+    ///
+    /// Panics if the frame doesn't fit in the writer's buffer.
+    pub fn write(&self, w: &mut BitWriter) {
+        let mut values = [0u8; 12];
+        values[0] = self.pitch;
+        values[1] = self.rms;
+        values[2..].copy_from_slice(&self.rc);
+        // Parameters are sent MSB first, so collect bits from the end.
+        let mut bits = [0u8; 53];
+        for (bit, param) in bits.iter_mut().zip(BIT_ORDER).rev() {
+            let value = &mut values[param as usize];
+            *bit = *value & 1;
+            *value >>= 1;
+        }
+        for bit in bits {
+            w.put_bits(bit as usize, 1);
+        }
+        w.put_bits(self.sync as usize, 1);
+    }
+}
+
+/// Find the start of the first whole frame, by the alternating sync bit.
+///
+/// Returns the bit position, or `None` if no position has the sync bit
+/// alternating over the whole data, or there are fewer than 2 frames.
+pub fn find_sync(data: &[u8], order: BitOrder) -> Option<usize> {
+    /// Limit on the number of frames checked.
+    const MAX_FRAMES: usize = 16;
+
+    let mut bs = BitStream::with_bit_order(data, order);
+    let mut sync_bit = |pos: usize| {
+        bs.seek(pos);
+        bs.get_bits(1)
+    };
+    (0..FRAME_BITS).find(|&offset| {
+        let n_frames = ((data.len() * 8).saturating_sub(offset) / FRAME_BITS).min(MAX_FRAMES);
+        let mut last = None;
+        n_frames >= 2
+            && (0..n_frames).all(|i| {
+                let bit = sync_bit(offset + i * FRAME_BITS + FRAME_BITS - 1);
+                last.replace(bit) != Some(bit)
+            })
+    })
+}
+
+impl Default for Lpc10Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lpc10Decoder {
+    pub fn new() -> Self {
+        Self {
+            last: Lpc10Params::default(),
+            new: Lpc10Params::default(),
+            params: Lpc10Params::default(),
+            last_frame: Lpc10Frame::default(),
+            next_sync: None,
+            errors: ErrorCounts::default(),
+            pos: 0,
+            last_pulse_scale: 0,
+            new_pulse_scale: 0,
+            noise_gain: 0,
+            pulse_gain: 0,
+            period_counter: 0,
+            rand: 1,
+            lattice: Lattice::default(),
+            deemph: Deemphasis::default(),
+        }
+    }
+
+    /// Process one frame.
+    ///
+    /// This method should be called at the start of each frame, which is
+    /// [`FRAME_LEN`] samples. Returns false if the sync bit didn't alternate.
+    pub fn process_frame(&mut self, bs: &mut BitStream<impl AsRef<[u8]>>) -> bool {
+        let frame = Lpc10Frame::read(bs);
+        let in_sync = self.next_sync.is_none_or(|sync| sync == frame.sync);
+        self.next_sync = Some(!frame.sync);
+        self.errors.sync += !in_sync as usize;
+        self.apply_frame(&frame);
+        in_sync
+    }
+
+    /// Process one frame that has already been parsed.
+    pub fn apply_frame(&mut self, frame: &Lpc10Frame) {
+        let mut frame = *frame;
+        let voicing = match frame.voicing() {
+            Voicing::Invalid => {
+                self.errors.pitch += 1;
+                // Single bit errors in the unvoiced and transition codes are
+                // recognizable by weight. Otherwise assume the last pitch.
+                match frame.pitch.count_ones() {
+                    0..=2 => Voicing::Unvoiced,
+                    5.. => Voicing::Transition,
+                    _ if self.new.period != 0 => Voicing::Voiced(self.new.period),
+                    _ => Voicing::Unvoiced,
+                }
+            }
+            voicing => voicing,
+        };
+        let voiced = match voicing {
+            Voicing::Voiced(period) => {
+                self.new.period = period;
+                [true, true]
+            }
+            // Voicing starts or ends within the frame.
+            Voicing::Transition if self.new.period != 0 => {
+                let was_voiced = self.new.voiced[1];
+                [was_voiced, !was_voiced]
+            }
+            _ => [false, false],
+        };
+        if !matches!(voicing, Voicing::Voiced(_)) {
+            self.correct(&mut frame);
+        }
+        self.last_frame = frame;
+
+        self.last = self.new;
+        self.new.voiced = voiced;
+        self.new.rms = RMST[(31 - (frame.rms & 31) as usize) * 2];
+        for (i, k) in self.new.k.iter_mut().enumerate() {
+            let code = sign_extend(frame.rc[i], RC_BITS[i]);
+            let rc = if i < 2 {
+                let mag = match code.unsigned_abs() {
+                    16 => DETAB7[0],
+                    mag => DETAB7[mag as usize * 2],
+                };
+                (if code < 0 { -mag } else { mag }) << (15 - NBIT[i])
+            } else {
+                let code = (code << (15 - NBIT[i])) + QB[i - 2];
+                (code as f32 * DESCL[i - 2]) as i32 + DEADD[i - 2]
+            };
+            *k = (-(rc >> 5)).clamp(-511, 511) as i16;
+        }
+        if !matches!(voicing, Voicing::Voiced(_)) {
+            self.new.k[4..].fill(0);
+        }
+        self.last_pulse_scale = self.new_pulse_scale;
+        self.new_pulse_scale = pulse_scale(&self.new.k, self.new.period);
+        if self.last.voiced[1] != self.new.voiced[0] {
+            self.last = self.new;
+            self.last_pulse_scale = self.new_pulse_scale;
+        }
+        self.pos = 0;
+    }
+
+    /// Correct the protected bits of a frame that isn't fully voiced.
+    fn correct(&mut self, frame: &mut Lpc10Frame) {
+        let rc = frame.rc;
+        let parity = [rc[4], rc[5], rc[6], rc[7], (rc[8] << 1) | (rc[9] & 1)];
+        let protected = |f: &Lpc10Frame| [f.rc[0], f.rc[1], f.rc[2], f.rms, f.rc[3]];
+        let mut codes = protected(frame);
+        let last_codes = protected(&self.last_frame);
+        for ((code, last_code), parity) in codes.iter_mut().zip(last_codes).zip(parity) {
+            let data = (*code >> 1) & 15;
+            match ham84(data, parity) {
+                Some(fixed) => {
+                    self.errors.corrected +=
+                        (fixed != data || ENCTAB[fixed as usize] != parity) as usize;
+                    *code = (*code & 1) | (fixed << 1);
+                }
+                None => {
+                    self.errors.uncorrectable += 1;
+                    *code = last_code;
+                }
+            }
+        }
+        [
+            frame.rc[0],
+            frame.rc[1],
+            frame.rc[2],
+            frame.rms,
+            frame.rc[3],
+        ] = codes;
+    }
+
+    /// The parameters of the current frame.
+    pub fn frame_params(&self) -> &Lpc10Params {
+        &self.new
+    }
+
+    pub fn errors(&self) -> ErrorCounts {
+        self.errors
+    }
+
+    /// Get one sample.
+    pub fn get_sample(&mut self) -> i16 {
+        if self.pos.is_multiple_of(STEP_LEN) {
+            self.start_step();
+        }
+        let voiced = self.new.voiced[(self.pos >= FRAME_LEN / 2) as usize];
+        self.pos = (self.pos + 1).min(FRAME_LEN);
+        let u = if voiced {
+            let n = self.period_counter as usize;
+            self.period_counter += 1;
+            if self.period_counter >= self.params.period {
+                self.period_counter = 0;
+            }
+            KEXC.get(n)
+                .map_or(0, |&e| ((e as i64 * self.pulse_gain) >> 8) as i32)
+        } else {
+            self.rand = (self.rand >> 1) ^ if (self.rand & 1) != 0 { 0xb800 } else { 0 };
+            if (self.rand & 1) != 0 {
+                self.noise_gain as i32
+            } else {
+                -self.noise_gain as i32
+            }
+        };
+        let (sample, _) = self.lattice.filter(&self.params.k, u);
+        self.deemph.filter(sample)
+    }
+
+    /// Interpolate the parameters, and set the excitation gain so that the
+    /// output has the coded RMS.
+    fn start_step(&mut self) {
+        let t = (self.pos / STEP_LEN + 1) as i32;
+        let lerp = |x0: i32, x1: i32| x0 + (x1 - x0) * t / STEPS as i32;
+        let (last, new) = (&self.last, &self.new);
+        self.params = Lpc10Params {
+            voiced: new.voiced,
+            period: if last.period == 0 {
+                new.period
+            } else {
+                lerp(last.period as i32, new.period as i32) as u8
+            },
+            rms: lerp(last.rms as i32, new.rms as i32) as u16,
+            k: core::array::from_fn(|i| lerp(last.k[i] as i32, new.k[i] as i32) as i16),
+        };
+        // The filter's gain for white noise is 1 / sqrt(prod(1 - k^2)).
+        let residual = self.params.k.iter().fold(1u64 << 16, |r, &k| {
+            (r * ((1 << 18) - (k as i64 * k as i64) as u64)) >> 18
+        });
+        // Output samples are scaled by 8 relative to the RMS.
+        let rms = self.params.rms as i64 * 8;
+        self.noise_gain = (rms * residual.isqrt() as i64) >> 8;
+        let scale = if last.period == 0 {
+            self.new_pulse_scale
+        } else {
+            let (x0, x1) = (self.last_pulse_scale, self.new_pulse_scale);
+            x0 + (x1 - x0) * t as i64 / STEPS as i64
+        };
+        self.pulse_gain = (rms * scale) >> 8;
+    }
+}
+
+/// The gain that gives pulses of a period unit RMS through the lattice and
+/// de-emphasis, in 1/256.
+///
+/// The harmonics of the pulse train fall on the formants and the de-emphasis
+/// differently for each period, so the gain is measured instead, over the
+/// second of two periods from rest. This runs the filters for up to 312
+/// samples, so it's done once per frame, and interpolated between frames.
+fn pulse_scale(k: &[i16; 10], period: u8) -> i64 {
+    let period = period as u64;
+    let mut lattice = Lattice::default();
+    let mut deemph = Deemphasis::default();
+    let mut energy = 0;
+    for n in 0..2 * period {
+        let u = KEXC.get((n % period) as usize).map_or(0, |&e| e as i32);
+        let (sample, _) = lattice.filter(k, u);
+        let y = deemph.filter(sample) as i64;
+        if n >= period {
+            energy += (y * y) as u64;
+        }
+    }
+    ((period << 32) / energy.max(1)).isqrt() as i64
+}
+
+impl Deemphasis {
+    fn filter(&mut self, x: i32) -> i16 {
+        let [x1, x2] = self.x;
+        let [y1, y2, y3] = self.y;
+        let acc = ((x as i64) << 12) - 8191 * x1 as i64 + ((x2 as i64) << 12) + 10240 * y1 as i64
+            - 8571 * y2 as i64
+            + 2396 * y3 as i64;
+        let y = (acc >> 12).clamp(-(1 << 20), 1 << 20) as i32;
+        self.x = [x, x1];
+        self.y = [y, y1, y2];
+        y.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{vec, vec::Vec};
+
+    use super::*;
+
+    /// Number of junk bits before the first frame.
+    const JUNK_BITS: usize = 23;
+
+    /// Parameters of a frame: voicing, RMS and reflection coefficients.
+    type Params = (Voicing, u16, [f32; 10]);
+
+    fn utterance() -> Vec<Params> {
+        let s = [-0.5, 0.3, -0.2, 0.1, 0., 0., 0., 0., 0., 0.];
+        let a = [0.8, -0.5, 0.3, -0.25, 0.15, -0.1, 0.05, 0., 0., 0.];
+        let mut params = vec![(Voicing::Unvoiced, 60, s); 4];
+        params.push((Voicing::Transition, 150, a));
+        for i in 0..20 {
+            params.push((Voicing::Voiced(80 - 2 * i as u8), 300, a));
+        }
+        params.push((Voicing::Transition, 100, a));
+        params.extend([(Voicing::Unvoiced, 10, s); 4]);
+        params
+    }
+
+    fn frames(params: &[Params]) -> Vec<Lpc10Frame> {
+        params
+            .iter()
+            .enumerate()
+            .map(|(i, (voicing, rms, rc))| Lpc10Frame {
+                sync: i % 2 == 1,
+                ..Lpc10Frame::new(*voicing, *rms, &rc.map(|rc| (rc * 32768.) as i16))
+            })
+            .collect()
+    }
+
+    /// Write frames after some junk bits.
+    fn encode(frames: &[Lpc10Frame]) -> Vec<u8> {
+        let mut buf = vec![0; (JUNK_BITS + frames.len() * FRAME_BITS).div_ceil(8)];
+        let mut w = BitWriter::new(&mut buf);
+        w.put_bits(0x5a5a5a, JUNK_BITS);
+        for frame in frames {
+            frame.write(&mut w);
+        }
+        buf
+    }
+
+    fn flip(data: &mut [u8], bit: usize) {
+        // Bits are packed LSB first.
+        data[bit / 8] ^= 1 << (bit % 8);
+    }
+
+    /// Decode frames, returning the decoder, the audio and whether each
+    /// frame was in sync.
+    fn decode(data: &[u8], n_frames: usize) -> (Lpc10Decoder, Vec<i16>, Vec<bool>) {
+        let mut bs = BitStream::new(data);
+        bs.seek(JUNK_BITS);
+        let mut decoder = Lpc10Decoder::new();
+        let mut samples = vec![];
+        let mut in_sync = vec![];
+        for _ in 0..n_frames {
+            in_sync.push(decoder.process_frame(&mut bs));
+            samples.extend((0..FRAME_LEN).map(|_| decoder.get_sample()));
+        }
+        (decoder, samples, in_sync)
+    }
+
+    #[test]
+    fn framing() {
+        let frames = frames(&utterance());
+        let data = encode(&frames);
+        assert_eq!(find_sync(&data, BitOrder::LsbFirst), Some(JUNK_BITS));
+        let mut bs = BitStream::new(&data);
+        bs.seek(JUNK_BITS);
+        for frame in &frames {
+            assert_eq!(Lpc10Frame::read(&mut bs), *frame);
+        }
+    }
+
+    #[test]
+    fn parameter_coding() {
+        let params = utterance();
+        let mut decoder = Lpc10Decoder::new();
+        for ((voicing, rms, rc), frame) in params.iter().zip(frames(&params)) {
+            decoder.apply_frame(&frame);
+            let decoded = decoder.frame_params();
+            assert!(decoded.rms.abs_diff(*rms) * 10 <= *rms, "rms {rms}");
+            let n_k = if matches!(voicing, Voicing::Voiced(_)) {
+                10
+            } else {
+                4
+            };
+            for (k, rc) in decoded.k.iter().zip(rc).take(n_k) {
+                let error = (*k as f32 / 512. + rc).abs();
+                assert!(error < 0.1, "k {k} for rc {rc}");
+            }
+            if let Voicing::Voiced(period) = voicing {
+                assert_eq!(decoded.period, *period);
+            }
+        }
+    }
+
+    #[test]
+    fn error_correction() {
+        let frames = frames(&utterance());
+        let data = encode(&frames);
+        let (clean, samples, in_sync) = decode(&data, frames.len());
+        assert!(in_sync.iter().all(|ok| *ok));
+        assert_eq!(clean.errors(), ErrorCounts::default());
+
+        // Single bit errors in the protected bits of an unvoiced frame are
+        // corrected.
+        let frame_start = JUNK_BITS + FRAME_BITS;
+        let mut corrected = 0;
+        for bit in 0..FRAME_BITS - 1 {
+            let mut damaged = data.clone();
+            flip(&mut damaged, frame_start + bit);
+            let (decoder, damaged_samples, _) = decode(&damaged, 2);
+            if decoder.errors().corrected == 1 {
+                assert_eq!(damaged_samples, samples[..2 * FRAME_LEN]);
+                corrected += 1;
+            }
+        }
+        // 4 protected bits in each of RC1..RC4 and RMS, and their parity bits.
+        assert_eq!(corrected, 5 * 4 * 2);
+
+        // Double errors are detected. Bits 31 and 36 are the two most
+        // significant bits of RC1.
+        let mut damaged = data.clone();
+        flip(&mut damaged, frame_start + 31);
+        flip(&mut damaged, frame_start + 36);
+        let (decoder, _, _) = decode(&damaged, 2);
+        assert_eq!(decoder.errors().uncorrectable, 1);
+    }
+
+    #[test]
+    fn sync_error() {
+        let frames = frames(&utterance());
+        let mut data = encode(&frames);
+        flip(&mut data, JUNK_BITS + 2 * FRAME_BITS - 1);
+        let (decoder, _, in_sync) = decode(&data, 3);
+        assert_eq!(in_sync, [true, false, false]);
+        assert_eq!(decoder.errors().sync, 2);
+    }
+
+    #[test]
+    fn output_level() {
+        let params = utterance();
+        let frames = frames(&params);
+        let (_, samples, _) = decode(&encode(&frames), frames.len());
+        for ((voicing, rms, _), chunk) in params.iter().zip(samples.chunks(FRAME_LEN)) {
+            if !matches!(voicing, Voicing::Voiced(_)) {
+                continue;
+            }
+            let sum = chunk.iter().map(|&s| (s as f64).powi(2)).sum::<f64>();
+            let out = (sum / FRAME_LEN as f64).sqrt();
+            // Output samples are scaled by 8 relative to the RMS.
+            let ratio = out / (*rms as f64 * 8.);
+            assert!((0.8..1.2).contains(&ratio), "{voicing:?}: ratio {ratio:.2}");
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Packetization of frames for transmission over a lossy channel.
+//!
+//! A packet is a 3 byte header followed by the frames, bit-packed in the same
+//! order as a [`BitStream`] and padded to a byte boundary. The header is a
+//! big-endian 16 bit sequence number and a frame count.
+//!
+//! The first frame of a packet is not a repeat frame when it can be avoided,
+//! so that a packet can be decoded without the one before it. Missing packets
+//! are reported by the [`Depacketizer`], and can be filled in by a
+//! [`Concealer`](crate::conceal::Concealer).
+
+use crate::{BitStream, BitWriter, Frame, tables::Layout};
+
+/// The length of the packet header, in bytes.
+pub const HEADER_LEN: usize = 3;
+
+/// Errors from packetizing and depacketizing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketError {
+    /// The output buffer can't hold the packet.
+    BufferTooSmall,
+    /// More than 255 frames were given for one packet.
+    TooManyFrames,
+    /// The packet is shorter than its header says.
+    Truncated,
+    /// The packet is older than one already received, or a duplicate.
+    Stale,
+}
+
+/// Groups frames into sequence-numbered packets.
+pub struct Packetizer {
+    layout: Layout,
+    seq: u16,
+    last_k: [u8; 10],
+    last_voiced: bool,
+}
+
+/// Tracks sequence numbers of received packets.
+pub struct Depacketizer {
+    layout: Layout,
+    next_seq: Option<u16>,
+    last_n_frames: usize,
+}
+
+/// A received packet.
+pub struct Packet<'a> {
+    /// The sequence number.
+    pub seq: u16,
+    /// The number of packets lost since the previous one received.
+    pub lost_packets: usize,
+    /// An estimate of the number of frames lost since the previous packet.
+    ///
+    /// This assumes the lost packets had the same number of frames as the
+    /// last one received.
+    pub lost_frames: usize,
+    layout: Layout,
+    n_frames: usize,
+    payload: &'a [u8],
+}
+
+/// An iterator over the frames in a packet.
+pub struct Frames<'a> {
+    layout: Layout,
+    bs: BitStream<&'a [u8]>,
+    remaining: usize,
+}
+
+impl Packetizer {
+    pub fn new(layout: &Layout) -> Self {
+        Self {
+            layout: *layout,
+            seq: 0,
+            last_k: [0; 10],
+            last_voiced: false,
+        }
+    }
+
+    /// The largest packet that `n_frames` frames can produce, in bytes.
+    pub fn max_len(&self, n_frames: usize) -> usize {
+        HEADER_LEN + (n_frames * self.layout.max_frame_bits()).div_ceil(8)
+    }
+
+    /// Write one packet containing `frames` into `buf`.
+    ///
+    /// Returns the length of the packet in bytes.
+    pub fn packetize(&mut self, frames: &[Frame], buf: &mut [u8]) -> Result<usize, PacketError> {
+        if frames.len() > 255 {
+            return Err(PacketError::TooManyFrames);
+        }
+        let first = frames.first().map(|frame| self.standalone(frame));
+        let rest = frames.get(1..).unwrap_or_default();
+        let n_bits: usize = first
+            .iter()
+            .chain(rest)
+            .map(|frame| frame.bit_len(&self.layout))
+            .sum();
+        let len = HEADER_LEN + n_bits.div_ceil(8);
+        if buf.len() < len {
+            return Err(PacketError::BufferTooSmall);
+        }
+        buf[..2].copy_from_slice(&self.seq.to_be_bytes());
+        buf[2] = frames.len() as u8;
+        let mut w = BitWriter::new(&mut buf[HEADER_LEN..]);
+        for frame in first.iter().chain(rest) {
+            frame.write(&mut w, &self.layout);
+        }
+        for frame in frames {
+            self.track(frame);
+        }
+        self.seq = self.seq.wrapping_add(1);
+        Ok(len)
+    }
+
+    /// Expand a repeat frame so it doesn't depend on the previous packet.
+    ///
+    /// An unvoiced frame zeroes K5..K10 while a repeat keeps them, so this is
+    /// only possible when voicing is unchanged.
+    fn standalone(&self, frame: &Frame) -> Frame {
+        let mut frame = *frame;
+        if frame.n_k() == 0 && frame.repeat && frame.is_voiced() == self.last_voiced {
+            frame.repeat = false;
+            frame.k = self.last_k;
+        }
+        frame
+    }
+
+    fn track(&mut self, frame: &Frame) {
+        if frame.n_k() > 0 {
+            self.last_k = frame.k;
+            self.last_voiced = frame.is_voiced();
+        }
+    }
+}
+
+impl Default for Packetizer {
+    fn default() -> Self {
+        Self::new(&Layout::TMS5220)
+    }
+}
+
+impl Depacketizer {
+    pub fn new(layout: &Layout) -> Self {
+        Self {
+            layout: *layout,
+            next_seq: None,
+            last_n_frames: 0,
+        }
+    }
+
+    /// Parse a packet and account for any packets lost before it.
+    pub fn receive<'a>(&mut self, packet: &'a [u8]) -> Result<Packet<'a>, PacketError> {
+        if packet.len() < HEADER_LEN {
+            return Err(PacketError::Truncated);
+        }
+        let seq = u16::from_be_bytes([packet[0], packet[1]]);
+        let n_frames = packet[2] as usize;
+        let payload = &packet[HEADER_LEN..];
+        let mut bs = BitStream::new(payload);
+        for _ in 0..n_frames {
+            Frame::read(&mut bs, &self.layout);
+        }
+        if bs.is_overrun() {
+            return Err(PacketError::Truncated);
+        }
+        let lost_packets = match self.next_seq {
+            Some(next_seq) => {
+                let delta = seq.wrapping_sub(next_seq) as i16;
+                if delta < 0 {
+                    return Err(PacketError::Stale);
+                }
+                delta as usize
+            }
+            None => 0,
+        };
+        let lost_frames = lost_packets * self.last_n_frames;
+        self.next_seq = Some(seq.wrapping_add(1));
+        self.last_n_frames = n_frames;
+        Ok(Packet {
+            seq,
+            lost_packets,
+            lost_frames,
+            layout: self.layout,
+            n_frames,
+            payload,
+        })
+    }
+}
+
+impl Default for Depacketizer {
+    fn default() -> Self {
+        Self::new(&Layout::TMS5220)
+    }
+}
+
+impl<'a> Packet<'a> {
+    /// The number of frames in the packet.
+    pub fn n_frames(&self) -> usize {
+        self.n_frames
+    }
+
+    pub fn frames(&self) -> Frames<'a> {
+        Frames {
+            layout: self.layout,
+            bs: BitStream::new(self.payload),
+            remaining: self.n_frames,
+        }
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(Frame::read(&mut self.bs, &self.layout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{vec, vec::Vec};
+
+    use super::*;
+
+    fn voiced(energy: u8, k1: u8) -> Frame {
+        Frame {
+            energy,
+            repeat: false,
+            period: 40,
+            k: [k1, 12, 5, 6, 7, 8, 9, 3, 2, 1],
+        }
+    }
+
+    /// A repeat frame, which carries no coefficients.
+    fn repeat(frame: Frame) -> Frame {
+        Frame {
+            repeat: true,
+            k: [0; 10],
+            ..frame
+        }
+    }
+
+    /// Packetize groups of frames and deliver those that `keep` passes.
+    fn channel(
+        packetizer: &mut Packetizer,
+        groups: &[&[Frame]],
+        keep: impl Fn(usize) -> bool,
+    ) -> Vec<Vec<u8>> {
+        let mut sent = vec![];
+        for (i, frames) in groups.iter().enumerate() {
+            let mut buf = vec![0; packetizer.max_len(frames.len())];
+            let len = packetizer.packetize(frames, &mut buf).unwrap();
+            buf.truncate(len);
+            if keep(i) {
+                sent.push(buf);
+            }
+        }
+        sent
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let frames = [voiced(10, 20), repeat(voiced(9, 20)), Frame::silence()];
+        let packets = channel(&mut Packetizer::default(), &[&frames], |_| true);
+        let mut depacketizer = Depacketizer::default();
+        let packet = depacketizer.receive(&packets[0]).unwrap();
+        assert_eq!(packet.n_frames(), 3);
+        assert_eq!(packet.frames().collect::<Vec<_>>(), frames);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        let mut packetizer = Packetizer {
+            seq: u16::MAX - 1,
+            ..Packetizer::default()
+        };
+        let frames: &[Frame] = &[voiced(10, 20)];
+        let packets = channel(&mut packetizer, &[frames; 4], |_| true);
+        let mut depacketizer = Depacketizer::default();
+        let mut seqs = vec![];
+        for packet in &packets {
+            let packet = depacketizer.receive(packet).unwrap();
+            assert_eq!(packet.lost_packets, 0);
+            seqs.push(packet.seq);
+        }
+        assert_eq!(seqs, [u16::MAX - 1, u16::MAX, 0, 1]);
+
+        // Losses across the wrap are counted, and late packets rejected.
+        let mut depacketizer = Depacketizer::default();
+        depacketizer.receive(&packets[0]).unwrap();
+        assert_eq!(depacketizer.receive(&packets[3]).unwrap().lost_packets, 2);
+        assert_eq!(
+            depacketizer.receive(&packets[1]).err(),
+            Some(PacketError::Stale)
+        );
+        assert_eq!(
+            depacketizer.receive(&packets[3]).err(),
+            Some(PacketError::Stale)
+        );
+    }
+
+    #[test]
+    fn lost_frames() {
+        let frames: &[Frame] = &[voiced(10, 20); 3];
+        let short: &[Frame] = &[voiced(10, 20); 2];
+        let groups = [
+            frames, frames, frames, short, frames, frames, frames, frames, frames,
+        ];
+        // An isolated drop of packet 1, and a burst of 4 to 6 after a short
+        // packet.
+        let packets = channel(&mut Packetizer::default(), &groups, |i| {
+            !matches!(i, 1 | 4..=6)
+        });
+        let mut depacketizer = Depacketizer::default();
+        let lost = packets
+            .iter()
+            .map(|packet| {
+                let packet = depacketizer.receive(packet).unwrap();
+                (packet.lost_packets, packet.lost_frames)
+            })
+            .collect::<Vec<_>>();
+        // The estimate uses the size of the last packet received.
+        assert_eq!(lost, [(0, 0), (1, 3), (0, 0), (3, 6), (0, 0)]);
+    }
+
+    #[test]
+    fn truncated_packet() {
+        let frames = [voiced(10, 20); 3];
+        let packets = channel(&mut Packetizer::default(), &[&frames], |_| true);
+        let mut depacketizer = Depacketizer::default();
+        let packet = &packets[0];
+        for len in 0..packet.len() - 1 {
+            assert_eq!(
+                depacketizer.receive(&packet[..len]).err(),
+                Some(PacketError::Truncated)
+            );
+        }
+    }
+
+    #[test]
+    fn leading_repeat_is_expanded() {
+        let first = voiced(10, 20);
+        let groups: [&[Frame]; 2] = [&[first], &[repeat(voiced(8, 0)), repeat(voiced(7, 0))]];
+        let packets = channel(&mut Packetizer::default(), &groups, |i| i == 1);
+        let mut depacketizer = Depacketizer::default();
+        let frames = depacketizer
+            .receive(&packets[0])
+            .unwrap()
+            .frames()
+            .collect::<Vec<_>>();
+        // The first frame carries the coefficients of the packet before, and
+        // the second still repeats them.
+        assert_eq!(frames[0], Frame { energy: 8, ..first });
+        assert!(frames[1].repeat);
+        assert_eq!(frames[1].energy, 7);
+    }
+
+    #[test]
+    fn repeat_after_voicing_change_is_kept() {
+        // Expanding an unvoiced repeat after a voiced frame would zero K5 to
+        // K10, so it stays a repeat.
+        let unvoiced = Frame {
+            period: 0,
+            ..repeat(voiced(8, 0))
+        };
+        let groups: [&[Frame]; 2] = [&[voiced(10, 20)], &[unvoiced]];
+        let packets = channel(&mut Packetizer::default(), &groups, |_| true);
+        let mut depacketizer = Depacketizer::default();
+        depacketizer.receive(&packets[0]).unwrap();
+        let packet = depacketizer.receive(&packets[1]).unwrap();
+        assert_eq!(packet.frames().next(), Some(unvoiced));
+    }
+}
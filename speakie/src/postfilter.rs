@@ -0,0 +1,181 @@
+//! Emulation of the analog output stage.
+//!
+//! On real boards the DAC output goes through a reconstruction low-pass,
+//! coupling capacitors and a small speaker, and their response is a large part
+//! of the familiar sound. A [`PostFilter`] models these as a chain of biquad
+//! sections in fixed point, applied to the output of
+//! [`get_sample`](crate::Speakie::get_sample).
+//!
+//! The presets are designed for the 8kHz output rate.
+
+/// Fractional bits of the biquad coefficients.
+const COEFF_BITS: u32 = 14;
+
+/// The maximum number of biquad sections in a chain.
+pub const MAX_STAGES: usize = 4;
+
+/// Coefficients of one biquad section, scaled by 2^14 with `a0` normalized
+/// to 1.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Coeffs {
+    pub b: [i32; 3],
+    pub a: [i32; 2],
+}
+
+/// A built-in filter chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// No filtering.
+    Flat,
+    /// An approximation of the Echo II: a 4th order Butterworth low-pass at
+    /// 3.3kHz and the coupling capacitor's high-pass at 80Hz.
+    EchoII,
+    /// A small speaker in a toy or terminal: cone resonance around 350Hz
+    /// with little bass below it, and a presence peak at 2.5kHz.
+    SmallSpeaker,
+    /// A telephone channel band limited to 300..3400Hz.
+    Telephone,
+}
+
+/// One biquad section, in direct form 1.
+#[derive(Clone, Copy, Default, Debug)]
+struct Biquad {
+    coeffs: Coeffs,
+    x: [i32; 2],
+    y: [i32; 2],
+}
+
+/// A chain of biquad sections.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PostFilter {
+    stages: [Biquad; MAX_STAGES],
+    n_stages: usize,
+}
+
+impl Coeffs {
+    /// Convert from floating point coefficients, normalized so that `a0` is 1.
+    pub fn from_f32(b: [f32; 3], a: [f32; 2]) -> Self {
+        let q = |x: f32| {
+            let x = x * (1 << COEFF_BITS) as f32;
+            (if x < 0.0 { x - 0.5 } else { x + 0.5 }) as i32
+        };
+        Coeffs {
+            b: b.map(q),
+            a: a.map(q),
+        }
+    }
+}
+
+const fn coeffs(b: [i32; 3], a: [i32; 2]) -> Coeffs {
+    Coeffs { b, a }
+}
+
+const ECHO_II: [Coeffs; 3] = [
+    // High-pass, 80Hz, Q 0.707
+    coeffs([15672, -31344, 15672], [-31313, 14991]),
+    // Low-pass, 3.3kHz, Q 0.541 and 1.307
+    coeffs([10235, 20469, 10235], [18841, 5713]),
+    coeffs([12649, 25297, 12649], [23285, 10925]),
+];
+
+const SMALL_SPEAKER: [Coeffs; 3] = [
+    // High-pass, 350Hz, Q 1.4
+    coeffs([14656, -29311, 14656], [-28751, 13488]),
+    // Peak, 2.5kHz, Q 1.5, +5dB
+    coeffs([18776, 10187, 7844], [10187, 10236]),
+    // Low-pass, 3.6kHz, Q 0.707
+    coeffs([13117, 26233, 13117], [25575, 10507]),
+];
+
+const TELEPHONE: [Coeffs; 2] = [
+    // High-pass, 300Hz, Q 0.707
+    coeffs([13868, -27736, 13868], [-27348, 11741]),
+    // Low-pass, 3.4kHz, Q 0.707
+    coeffs([11726, 23452, 11726], [22101, 8420]),
+];
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [
+        Preset::Flat,
+        Preset::EchoII,
+        Preset::SmallSpeaker,
+        Preset::Telephone,
+    ];
+
+    /// The biquad sections of the preset.
+    pub fn coeffs(self) -> &'static [Coeffs] {
+        match self {
+            Preset::Flat => &[],
+            Preset::EchoII => &ECHO_II,
+            Preset::SmallSpeaker => &SMALL_SPEAKER,
+            Preset::Telephone => &TELEPHONE,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Flat => "flat",
+            Preset::EchoII => "echo-ii",
+            Preset::SmallSpeaker => "small-speaker",
+            Preset::Telephone => "telephone",
+        }
+    }
+
+    /// Look up a preset by [name](Self::name).
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+impl Biquad {
+    fn process(&mut self, x: i32) -> i32 {
+        let Coeffs { b, a } = self.coeffs;
+        let acc = b[0] as i64 * x as i64
+            + b[1] as i64 * self.x[0] as i64
+            + b[2] as i64 * self.x[1] as i64
+            - a[0] as i64 * self.y[0] as i64
+            - a[1] as i64 * self.y[1] as i64;
+        // Keep the state bounded, so that an unstable section can't overflow.
+        let y = (acc >> COEFF_BITS).clamp(-(1 << 20), 1 << 20) as i32;
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+impl PostFilter {
+    /// Create a chain from biquad sections.
+    ///
+    /// Panics if there are more than [`MAX_STAGES`] sections.
+    pub fn new(coeffs: &[Coeffs]) -> Self {
+        assert!(coeffs.len() <= MAX_STAGES, "too many biquad sections");
+        let mut filter = Self {
+            n_stages: coeffs.len(),
+            ..Self::default()
+        };
+        for (stage, coeffs) in filter.stages.iter_mut().zip(coeffs) {
+            stage.coeffs = *coeffs;
+        }
+        filter
+    }
+
+    pub fn from_preset(preset: Preset) -> Self {
+        Self::new(preset.coeffs())
+    }
+
+    /// Filter one sample.
+    pub fn process(&mut self, sample: i16) -> i16 {
+        let y = self.stages[..self.n_stages]
+            .iter_mut()
+            .fold(sample as i32, |x, stage| stage.process(x));
+        y.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+
+    /// Clear the filter state.
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.x = [0; 2];
+            stage.y = [0; 2];
+        }
+    }
+}
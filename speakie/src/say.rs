@@ -0,0 +1,570 @@
+//! Speaking numbers, times and dates from a bank of words.
+//!
+//! A [`WordBank`] maps tokens such as `"one"`, `"twenty"`, `"hundred"`,
+//! `"o'clock"` and `"point"` to encoded utterances. A [`Verbalizer`] turns
+//! a value into [`Tokens`] by the rules of a language, and a [`Queue`] looks
+//! the tokens up and plays the words one after another. In [`English`]:
+//!
+//! ```text
+//! integer 1984         one thousand nine hundred eighty four
+//! decimal 3.05         three point zero five
+//! ordinal 21           twenty first
+//! time 19:05           seven oh five pm
+//! date 2024-03-15      march fifteenth twenty twenty four
+//! ```
+//!
+//! [`English`] uses lower case tokens: the numbers `"zero"` to `"nineteen"`,
+//! the tens `"twenty"` to `"ninety"`, the scales `"hundred"` to
+//! `"quintillion"`, their ordinals (`"first"`, `"twentieth"`,
+//! `"hundredth"`...), the months, and `"minus"`, `"point"`, `"oh"`,
+//! `"o'clock"`, `"am"` and `"pm"`. [`Verbalizer::vocabulary`] lists them.
+
+use crate::{BitStream, Speakie};
+
+/// The most tokens that [`Tokens`] holds, enough for any 64 bit number.
+pub const MAX_TOKENS: usize = 40;
+
+/// The most words a [`Queue`] holds.
+pub const QUEUE_LEN: usize = 64;
+
+/// Words by token, each an encoded utterance ending with a stop frame.
+#[derive(Clone, Copy, Debug)]
+pub struct WordBank<'a> {
+    words: &'a [(&'a str, &'a [u8])],
+}
+
+impl<'a> WordBank<'a> {
+    pub fn new(words: &'a [(&'a str, &'a [u8])]) -> Self {
+        Self { words }
+    }
+
+    pub fn get(&self, token: &str) -> Option<&'a [u8]> {
+        self.words
+            .iter()
+            .find(|(name, _)| *name == token)
+            .map(|(_, word)| *word)
+    }
+}
+
+/// A sequence of tokens, of fixed capacity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tokens {
+    tokens: [&'static str; MAX_TOKENS],
+    len: usize,
+}
+
+impl Default for Tokens {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tokens {
+    pub fn new() -> Self {
+        Self {
+            tokens: [""; MAX_TOKENS],
+            len: 0,
+        }
+    }
+
+    /// Add a token.
+    ///
+    /// Panics if there are already [`MAX_TOKENS`] tokens.
+    pub fn push(&mut self, token: &'static str) {
+        assert!(self.len < MAX_TOKENS, "too many tokens");
+        self.tokens[self.len] = token;
+        self.len += 1;
+    }
+
+    pub fn as_slice(&self) -> &[&'static str] {
+        &self.tokens[..self.len]
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn last_mut(&mut self) -> Option<&mut &'static str> {
+        self.tokens[..self.len].last_mut()
+    }
+}
+
+/// The rules for speaking values in a language.
+pub trait Verbalizer {
+    /// Every token the verbalizer may produce, for building a word bank.
+    fn vocabulary(&self) -> &'static [&'static str];
+
+    /// A whole number.
+    fn integer(&self, n: i64, out: &mut Tokens);
+
+    /// A decimal number of `value / 10^places`, so that `(314, 2)` is 3.14.
+    /// Every place is spoken, including trailing zeros.
+    fn decimal(&self, value: i64, places: u8, out: &mut Tokens);
+
+    /// An ordinal number: first, second...
+    fn ordinal(&self, n: u64, out: &mut Tokens);
+
+    /// A time of day, with the hour from 0 to 23.
+    fn time(&self, hour: u8, minute: u8, out: &mut Tokens);
+
+    /// A date, with the month from 1 to 12. Other months are spoken as
+    /// numbers.
+    fn date(&self, year: u16, month: u8, day: u8, out: &mut Tokens);
+}
+
+/// English, as spoken in the US.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct English;
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+/// Scales of each group of three digits, from the thousands up.
+const SCALES: [&str; 6] = [
+    "thousand",
+    "million",
+    "billion",
+    "trillion",
+    "quadrillion",
+    "quintillion",
+];
+
+const HUNDRED: &str = "hundred";
+
+/// Cardinal tokens, and the ordinals that replace them as the last word of
+/// an ordinal number.
+const ORDINALS: [(&str, &str); 35] = [
+    ("zero", "zeroth"),
+    ("one", "first"),
+    ("two", "second"),
+    ("three", "third"),
+    ("four", "fourth"),
+    ("five", "fifth"),
+    ("six", "sixth"),
+    ("seven", "seventh"),
+    ("eight", "eighth"),
+    ("nine", "ninth"),
+    ("ten", "tenth"),
+    ("eleven", "eleventh"),
+    ("twelve", "twelfth"),
+    ("thirteen", "thirteenth"),
+    ("fourteen", "fourteenth"),
+    ("fifteen", "fifteenth"),
+    ("sixteen", "sixteenth"),
+    ("seventeen", "seventeenth"),
+    ("eighteen", "eighteenth"),
+    ("nineteen", "nineteenth"),
+    ("twenty", "twentieth"),
+    ("thirty", "thirtieth"),
+    ("forty", "fortieth"),
+    ("fifty", "fiftieth"),
+    ("sixty", "sixtieth"),
+    ("seventy", "seventieth"),
+    ("eighty", "eightieth"),
+    ("ninety", "ninetieth"),
+    ("hundred", "hundredth"),
+    ("thousand", "thousandth"),
+    ("million", "millionth"),
+    ("billion", "billionth"),
+    ("trillion", "trillionth"),
+    ("quadrillion", "quadrillionth"),
+    ("quintillion", "quintillionth"),
+];
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+const OTHERS: [&str; 6] = ["minus", "point", "oh", "o'clock", "am", "pm"];
+
+const ENGLISH_VOCABULARY: [&str; 2 * ORDINALS.len() + MONTHS.len() + OTHERS.len()] = {
+    let mut vocabulary = [""; 2 * ORDINALS.len() + MONTHS.len() + OTHERS.len()];
+    let mut i = 0;
+    while i < ORDINALS.len() {
+        vocabulary[2 * i] = ORDINALS[i].0;
+        vocabulary[2 * i + 1] = ORDINALS[i].1;
+        i += 1;
+    }
+    let mut j = 0;
+    while j < MONTHS.len() {
+        vocabulary[2 * ORDINALS.len() + j] = MONTHS[j];
+        j += 1;
+    }
+    let mut j = 0;
+    while j < OTHERS.len() {
+        vocabulary[2 * ORDINALS.len() + MONTHS.len() + j] = OTHERS[j];
+        j += 1;
+    }
+    vocabulary
+};
+
+impl English {
+    /// A number from 1 to 999.
+    fn hundreds(n: u64, out: &mut Tokens) {
+        if n >= 100 {
+            out.push(ONES[(n / 100) as usize]);
+            out.push(HUNDRED);
+        }
+        let rest = (n % 100) as usize;
+        if rest >= 20 {
+            out.push(TENS[rest / 10]);
+            if !rest.is_multiple_of(10) {
+                out.push(ONES[rest % 10]);
+            }
+        } else if rest > 0 {
+            out.push(ONES[rest]);
+        }
+    }
+
+    fn cardinal(n: u64, out: &mut Tokens) {
+        if n == 0 {
+            out.push(ONES[0]);
+            return;
+        }
+        // The highest group of three digits, and its index in SCALES plus 1.
+        let (mut scale, mut group) = (1, 0);
+        while n / scale >= 1000 {
+            scale *= 1000;
+            group += 1;
+        }
+        loop {
+            let digits = n / scale % 1000;
+            if digits > 0 {
+                Self::hundreds(digits, out);
+                if group > 0 {
+                    out.push(SCALES[group - 1]);
+                }
+            }
+            if group == 0 {
+                return;
+            }
+            scale /= 1000;
+            group -= 1;
+        }
+    }
+}
+
+impl Verbalizer for English {
+    fn vocabulary(&self) -> &'static [&'static str] {
+        &ENGLISH_VOCABULARY
+    }
+
+    fn integer(&self, n: i64, out: &mut Tokens) {
+        if n < 0 {
+            out.push("minus");
+        }
+        Self::cardinal(n.unsigned_abs(), out);
+    }
+
+    fn decimal(&self, value: i64, places: u8, out: &mut Tokens) {
+        let places = places.min(18) as u32;
+        let scale = 10u64.pow(places);
+        let magnitude = value.unsigned_abs();
+        if value < 0 {
+            out.push("minus");
+        }
+        Self::cardinal(magnitude / scale, out);
+        if places > 0 {
+            out.push("point");
+            let fraction = magnitude % scale;
+            for place in (0..places).rev() {
+                out.push(ONES[(fraction / 10u64.pow(place) % 10) as usize]);
+            }
+        }
+    }
+
+    fn ordinal(&self, n: u64, out: &mut Tokens) {
+        Self::cardinal(n, out);
+        if let Some(last) = out.last_mut()
+            && let Some((_, ordinal)) = ORDINALS.iter().find(|(word, _)| word == last)
+        {
+            *last = ordinal;
+        }
+    }
+
+    fn time(&self, hour: u8, minute: u8, out: &mut Tokens) {
+        let hour12 = match hour % 12 {
+            0 => 12,
+            h => h,
+        };
+        Self::cardinal(hour12 as u64, out);
+        match minute {
+            0 => out.push("o'clock"),
+            1..=9 => {
+                out.push("oh");
+                out.push(ONES[minute as usize]);
+            }
+            _ => Self::cardinal(minute as u64, out),
+        }
+        out.push(if hour < 12 { "am" } else { "pm" });
+    }
+
+    fn date(&self, year: u16, month: u8, day: u8, out: &mut Tokens) {
+        match MONTHS.get((month as usize).wrapping_sub(1)) {
+            Some(name) => out.push(name),
+            None => Self::cardinal(month as u64, out),
+        }
+        self.ordinal(day as u64, out);
+        // Years are read in pairs of digits, except for those that read
+        // better as numbers: 2000 to 2009, and whole thousands.
+        let (high, low) = (year / 100, year % 100);
+        if year < 1000 || (2000..2010).contains(&year) || year.is_multiple_of(1000) {
+            Self::cardinal(year as u64, out);
+        } else {
+            Self::cardinal(high as u64, out);
+            match low {
+                0 => out.push(HUNDRED),
+                1..=9 => {
+                    out.push("oh");
+                    out.push(ONES[low as usize]);
+                }
+                _ => Self::cardinal(low as u64, out),
+            }
+        }
+    }
+}
+
+/// Errors from queueing words.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SayError {
+    /// A token isn't in the word bank.
+    UnknownWord(&'static str),
+    /// The queue can't hold all the words.
+    Full,
+}
+
+/// Words waiting to be spoken.
+pub struct Queue<'a> {
+    bank: WordBank<'a>,
+    words: [&'a [u8]; QUEUE_LEN],
+    head: usize,
+    len: usize,
+    current: Option<BitStream<&'a [u8]>>,
+}
+
+impl<'a> Queue<'a> {
+    pub fn new(bank: WordBank<'a>) -> Self {
+        Self {
+            bank,
+            words: [&[]; QUEUE_LEN],
+            head: 0,
+            len: 0,
+            current: None,
+        }
+    }
+
+    /// Queue the words for tokens.
+    ///
+    /// Either all the words are queued, or none are.
+    pub fn say(&mut self, tokens: &[&'static str]) -> Result<(), SayError> {
+        for token in tokens {
+            if self.bank.get(token).is_none() {
+                return Err(SayError::UnknownWord(token));
+            }
+        }
+        if self.len + tokens.len() > QUEUE_LEN {
+            return Err(SayError::Full);
+        }
+        for token in tokens {
+            self.words[(self.head + self.len) % QUEUE_LEN] = self.bank.get(token).unwrap();
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    pub fn say_integer(&mut self, verbalizer: &impl Verbalizer, n: i64) -> Result<(), SayError> {
+        let mut tokens = Tokens::new();
+        verbalizer.integer(n, &mut tokens);
+        self.say(tokens.as_slice())
+    }
+
+    pub fn say_decimal(
+        &mut self,
+        verbalizer: &impl Verbalizer,
+        value: i64,
+        places: u8,
+    ) -> Result<(), SayError> {
+        let mut tokens = Tokens::new();
+        verbalizer.decimal(value, places, &mut tokens);
+        self.say(tokens.as_slice())
+    }
+
+    pub fn say_ordinal(&mut self, verbalizer: &impl Verbalizer, n: u64) -> Result<(), SayError> {
+        let mut tokens = Tokens::new();
+        verbalizer.ordinal(n, &mut tokens);
+        self.say(tokens.as_slice())
+    }
+
+    pub fn say_time(
+        &mut self,
+        verbalizer: &impl Verbalizer,
+        hour: u8,
+        minute: u8,
+    ) -> Result<(), SayError> {
+        let mut tokens = Tokens::new();
+        verbalizer.time(hour, minute, &mut tokens);
+        self.say(tokens.as_slice())
+    }
+
+    pub fn say_date(
+        &mut self,
+        verbalizer: &impl Verbalizer,
+        year: u16,
+        month: u8,
+        day: u8,
+    ) -> Result<(), SayError> {
+        let mut tokens = Tokens::new();
+        verbalizer.date(year, month, day, &mut tokens);
+        self.say(tokens.as_slice())
+    }
+
+    /// Whether every queued word has been spoken.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0 && self.current.is_none()
+    }
+
+    /// Drop the queued words, including the one being spoken.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.current = None;
+    }
+
+    /// Process one frame, moving on to the next word at each stop frame.
+    ///
+    /// Returns true when the queue has run out.
+    pub fn process_frame(&mut self, speakie: &mut Speakie) -> bool {
+        loop {
+            let bs = match &mut self.current {
+                Some(bs) => bs,
+                None => {
+                    if self.len == 0 {
+                        return true;
+                    }
+                    let word = self.words[self.head];
+                    self.head = (self.head + 1) % QUEUE_LEN;
+                    self.len -= 1;
+                    self.current.insert(BitStream::new(word))
+                }
+            };
+            // A truncated word ends like one with a stop frame.
+            if !speakie.process_frame(bs) && !bs.is_overrun() {
+                return false;
+            }
+            self.current = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+
+    fn tokens(f: impl FnOnce(&mut Tokens)) -> String {
+        let mut tokens = Tokens::new();
+        f(&mut tokens);
+        tokens.as_slice().join(" ")
+    }
+
+    #[test]
+    fn english() {
+        let e = English;
+        let cases = [
+            (tokens(|t| e.integer(0, t)), "zero"),
+            (tokens(|t| e.integer(-15, t)), "minus fifteen"),
+            (tokens(|t| e.integer(100, t)), "one hundred"),
+            (tokens(|t| e.integer(1_000_017, t)), "one million seventeen"),
+            (
+                tokens(|t| e.integer(i64::MIN, t)),
+                "minus nine quintillion two hundred twenty three quadrillion three hundred \
+                 seventy two trillion thirty six billion eight hundred fifty four million \
+                 seven hundred seventy five thousand eight hundred eight",
+            ),
+            (tokens(|t| e.decimal(314, 2, t)), "three point one four"),
+            (tokens(|t| e.decimal(-5, 1, t)), "minus zero point five"),
+            (tokens(|t| e.ordinal(3, t)), "third"),
+            (tokens(|t| e.ordinal(40, t)), "fortieth"),
+            (tokens(|t| e.ordinal(112, t)), "one hundred twelfth"),
+            (tokens(|t| e.ordinal(2000, t)), "two thousandth"),
+            (tokens(|t| e.time(0, 0, t)), "twelve o'clock am"),
+            (tokens(|t| e.time(13, 7, t)), "one oh seven pm"),
+            (tokens(|t| e.time(11, 45, t)), "eleven forty five am"),
+            (
+                tokens(|t| e.date(1905, 7, 4, t)),
+                "july fourth nineteen oh five",
+            ),
+            (
+                tokens(|t| e.date(2007, 12, 31, t)),
+                "december thirty first two thousand seven",
+            ),
+            (
+                tokens(|t| e.date(1900, 1, 1, t)),
+                "january first nineteen hundred",
+            ),
+        ];
+        for (got, want) in cases {
+            assert_eq!(got, want);
+        }
+        let vocabulary = e.vocabulary();
+        for n in [-1234567, 0, 19, 90, 1_000_000_000] {
+            let mut t = Tokens::new();
+            e.integer(n, &mut t);
+            assert!(t.as_slice().iter().all(|w| vocabulary.contains(w)));
+        }
+    }
+
+    #[test]
+    fn bad_month_is_a_number() {
+        let e = English;
+        assert_eq!(
+            tokens(|t| e.date(2024, 0, 15, t)),
+            "zero fifteenth twenty twenty four"
+        );
+        assert_eq!(
+            tokens(|t| e.date(2024, 13, 1, t)),
+            "thirteen first twenty twenty four"
+        );
+        assert_eq!(
+            tokens(|t| e.date(2024, 255, 1, t)),
+            "two hundred fifty five first twenty twenty four"
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! Coefficient tables.
+//!
+//! The TMS5220 and TMS5100 tables are built in. Other table sets can be defined statically,
+//! or loaded at runtime from a text format like the following:
+//!
+//! ```text
+//! # comments run to the end of the line
+//! energy 0 52 87 123 174 246 348 491 694 981 1385 1957 2764 3904 5514 7789
+//! period 0 15 16 17 ...
+//! k1 -501 -498 -497 ...
+//! ...
+//! k10 -205 -132 -59 14 87 160 234 307
+//! chirp 0x00 0x03 0x0f ...
+//! ```
+//!
+//! Each table is named, followed by its values, which may span several lines.
+//! Values are decimal, or hexadecimal with a `0x` prefix. The bit width of
+//! each code is given by the length of its table, which must be a power of two.
+
+use core::fmt;
+
+const ENERGY: [u16; 0x10] = [
+    0, 52, 87, 123, 174, 246, 348, 491, 694, 981, 1385, 1957, 2764, 3904, 5514, 7789,
+];
+const PERIOD: [u8; 0x40] = [
+    0, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37,
+    38, 39, 40, 41, 42, 44, 46, 48, 50, 52, 53, 56, 58, 60, 62, 65, 68, 70, 72, 76, 78, 80, 84, 86,
+    91, 94, 98, 101, 105, 109, 114, 118, 122, 127, 132, 137, 142, 148, 153, 159,
+];
+const K1: [i16; 0x20] = [
+    -501, -498, -497, -495, -493, -491, -488, -482, -478, -474, -469, -464, -459, -452, -445, -437,
+    -412, -380, -339, -288, -227, -158, -81, -1, 80, 157, 226, 287, 337, 379, 411, 436,
+];
+const K2: [i16; 0x20] = [
+    -328, -303, -274, -244, -211, -175, -138, -99, -59, -18, 24, 64, 105, 143, 180, 215, 248, 278,
+    306, 331, 354, 374, 392, 408, 422, 435, 445, 455, 463, 470, 476, 506,
+];
+const K3: [i16; 0x10] = [
+    -441, -387, -333, -279, -225, -171, -117, -63, -9, 45, 98, 152, 206, 260, 314, 368,
+];
+const K4: [i16; 0x10] = [
+    -328, -273, -217, -161, -106, -50, 5, 61, 116, 172, 228, 283, 339, 394, 450, 506,
+];
+const K5: [i16; 0x10] = [
+    -328, -282, -235, -189, -142, -96, -50, -3, 43, 90, 136, 182, 229, 275, 322, 368,
+];
+const K6: [i16; 0x10] = [
+    -256, -212, -168, -123, -79, -35, 10, 54, 98, 143, 187, 232, 276, 320, 365, 409,
+];
+const K7: [i16; 0x10] = [
+    -308, -260, -212, -164, -117, -69, -21, 27, 75, 122, 170, 218, 266, 314, 361, 409,
+];
+const K8: [i16; 0x08] = [-256, -161, -66, 29, 124, 219, 314, 409];
+const K9: [i16; 0x08] = [-256, -176, -96, -15, 65, 146, 226, 307];
+const K10: [i16; 0x08] = [-205, -132, -59, 14, 87, 160, 234, 307];
+
+const CHIRP: [u8; 52] = [
+    0x00, 0x03, 0x0f, 0x28, 0x4c, 0x6c, 0x71, 0x50, 0x25, 0x26, 0x4c, 0x44, 0x1a, 0x32, 0x3b, 0x13,
+    0x37, 0x1a, 0x25, 0x1f, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00,
+];
+
+//const CHIRP2: [u8; 9] = [0x7f, 0x40, 0x20, 0x10, 0x8, 4, 2, 1, 0];
+
+// TMS5100 (TMC0281) tables, as used in the Speak & Spell. The energy table is
+// shared with the TMS5220.
+const PERIOD_5100: [u8; 0x20] = [
+    0, 41, 43, 45, 47, 49, 51, 53, 55, 58, 60, 63, 66, 70, 73, 76, 79, 83, 87, 90, 94, 99, 103,
+    107, 112, 118, 123, 129, 134, 140, 147, 153,
+];
+const K1_5100: [i16; 0x20] = [
+    -501, -497, -493, -488, -480, -471, -460, -446, -427, -405, -378, -344, -305, -259, -206, -148,
+    -86, -21, 45, 110, 171, 227, 277, 320, 357, 388, 413, 434, 451, 464, 474, 482,
+];
+const K2_5100: [i16; 0x20] = [
+    -349, -328, -305, -280, -252, -223, -192, -158, -124, -88, -51, -14, 23, 60, 97, 133, 167, 199,
+    230, 259, 286, 310, 333, 354, 372, 389, 404, 417, 429, 439, 449, 506,
+];
+const K3_5100: [i16; 0x10] = [
+    -397, -365, -327, -282, -229, -170, -104, -36, 35, 104, 169, 228, 281, 326, 364, 396,
+];
+const K4_5100: [i16; 0x10] = [
+    -369, -334, -293, -245, -191, -131, -67, -1, 64, 128, 188, 243, 291, 332, 367, 397,
+];
+const K5_5100: [i16; 0x10] = [
+    -319, -286, -250, -211, -168, -122, -74, -25, 24, 73, 121, 167, 210, 249, 285, 318,
+];
+const K6_5100: [i16; 0x10] = [
+    -290, -252, -209, -163, -114, -62, -9, 44, 97, 147, 194, 238, 278, 313, 344, 371,
+];
+const K7_5100: [i16; 0x10] = [
+    -291, -256, -216, -174, -128, -80, -31, 19, 69, 117, 163, 206, 246, 283, 316, 345,
+];
+const K8_5100: [i16; 0x08] = [-218, -133, -38, 59, 152, 235, 305, 360];
+const K9_5100: [i16; 0x08] = [-226, -157, -82, -3, 76, 151, 220, 280];
+const K10_5100: [i16; 0x08] = [-179, -122, -61, 1, 62, 123, 179, 231];
+
+const CHIRP_5100: [u8; 41] = [
+    0x00, 0x2a, 0xd4, 0x32, 0xb2, 0x12, 0x25, 0x14, 0x02, 0xe1, 0xc5, 0x02, 0x5f, 0x5a, 0x05, 0x0f,
+    0x26, 0xfc, 0xa5, 0xa5, 0xd6, 0xdd, 0xdc, 0xfc, 0x25, 0x2b, 0x22, 0x21, 0x0f, 0xff, 0xf8, 0xee,
+    0xed, 0xef, 0xf7, 0xf6, 0xfa, 0x00, 0x03, 0x02, 0x01,
+];
+
+/// The TMS5220 (and TMS5200) tables.
+pub const TMS5220: Tables<'static> = Tables {
+    energy: &ENERGY,
+    period: &PERIOD,
+    k: [&K1, &K2, &K3, &K4, &K5, &K6, &K7, &K8, &K9, &K10],
+    chirp: &CHIRP,
+};
+
+/// The TMS5100 tables.
+///
+/// This chip has a 5 bit pitch code, so its frames are laid out differently.
+pub const TMS5100: Tables<'static> = Tables {
+    energy: &ENERGY,
+    period: &PERIOD_5100,
+    k: [
+        &K1_5100, &K2_5100, &K3_5100, &K4_5100, &K5_5100, &K6_5100, &K7_5100, &K8_5100, &K9_5100,
+        &K10_5100,
+    ],
+    chirp: &CHIRP_5100,
+};
+
+/// The largest number of entries in a table loaded into a [`TableBuf`].
+pub const MAX_ENTRIES: usize = 64;
+
+/// A set of coefficient tables, describing a chip variant.
+///
+/// The energy table always has 16 entries, with code 0 for silence and 0xf
+/// for stop. The first entry of the period table is 0, for unvoiced frames.
+/// Chirp values are two's complement, as in the chip ROM.
+#[derive(Clone, Copy, Debug)]
+pub struct Tables<'a> {
+    pub energy: &'a [u16],
+    pub period: &'a [u8],
+    pub k: [&'a [i16]; 10],
+    pub chirp: &'a [u8],
+}
+
+/// The bit widths of the fields of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub period_bits: u8,
+    pub k_bits: [u8; 10],
+}
+
+/// Identifies one table in a set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableId {
+    Energy,
+    Period,
+    /// K1..K10, indexed from 0.
+    K(u8),
+    Chirp,
+}
+
+/// Errors from validating or parsing tables.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableError {
+    /// The table has the wrong number of entries.
+    BadLength(TableId),
+    /// The first entry of the energy or period table isn't zero.
+    NonzeroFirst(TableId),
+    /// An entry is outside the range the decoder can use: an energy above
+    /// 32767, or a reflection coefficient beyond ±511.
+    OutOfRange(TableId),
+    /// A table is missing from the text.
+    Missing(TableId),
+    /// A line of text couldn't be parsed, counting from 1.
+    Syntax { line: usize },
+}
+
+/// Storage for a table set loaded at runtime.
+#[derive(Clone)]
+pub struct TableBuf {
+    energy: [u16; 16],
+    period: [u8; MAX_ENTRIES],
+    k: [[i16; MAX_ENTRIES]; 10],
+    chirp: [u8; MAX_ENTRIES],
+    energy_len: usize,
+    period_len: usize,
+    k_len: [usize; 10],
+    chirp_len: usize,
+}
+
+impl Tables<'_> {
+    /// Check that the tables are usable by the decoder.
+    pub fn validate(&self) -> Result<(), TableError> {
+        if self.energy.len() != 16 {
+            return Err(TableError::BadLength(TableId::Energy));
+        }
+        if self.energy[0] != 0 {
+            return Err(TableError::NonzeroFirst(TableId::Energy));
+        }
+        if self.energy.iter().any(|&e| e > i16::MAX as u16) {
+            return Err(TableError::OutOfRange(TableId::Energy));
+        }
+        check_code_len(self.period.len(), TableId::Period)?;
+        if self.period[0] != 0 {
+            return Err(TableError::NonzeroFirst(TableId::Period));
+        }
+        for (i, k) in self.k.iter().enumerate() {
+            let id = TableId::K(i as u8);
+            check_code_len(k.len(), id)?;
+            if k.iter().any(|k| !(-511..=511).contains(k)) {
+                return Err(TableError::OutOfRange(id));
+            }
+        }
+        if self.chirp.is_empty() || self.chirp.len() > MAX_ENTRIES {
+            return Err(TableError::BadLength(TableId::Chirp));
+        }
+        Ok(())
+    }
+
+    /// The bit widths of the codes indexing these tables.
+    pub fn layout(&self) -> Layout {
+        Layout {
+            period_bits: self.period.len().trailing_zeros() as u8,
+            k_bits: core::array::from_fn(|i| self.k[i].len().trailing_zeros() as u8),
+        }
+    }
+}
+
+fn check_code_len(len: usize, id: TableId) -> Result<(), TableError> {
+    if !(2..=MAX_ENTRIES).contains(&len) || !len.is_power_of_two() {
+        return Err(TableError::BadLength(id));
+    }
+    Ok(())
+}
+
+impl Layout {
+    /// The TMS5220 layout.
+    pub const TMS5220: Layout = Layout {
+        period_bits: 6,
+        k_bits: [5, 5, 4, 4, 4, 4, 4, 3, 3, 3],
+    };
+
+    /// The length of the longest encoded frame, in bits.
+    pub fn max_frame_bits(&self) -> usize {
+        5 + self.period_bits as usize + self.k_bits.iter().map(|&b| b as usize).sum::<usize>()
+    }
+
+    /// The largest code for each K table.
+    pub fn k_max(&self) -> [u8; 10] {
+        self.k_bits.map(|bits| ((1u16 << bits) - 1) as u8)
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::TMS5220
+    }
+}
+
+impl TableBuf {
+    /// Parse tables from the text format.
+    ///
+    /// The result is validated.
+    pub fn parse(text: &str) -> Result<Self, TableError> {
+        let mut buf = TableBuf::empty();
+        let mut seen = [false; 13];
+        let mut current = None;
+        for (i, line) in text.lines().enumerate() {
+            let syntax = TableError::Syntax { line: i + 1 };
+            let line = line.split('#').next().unwrap_or_default();
+            for token in line.split(|c: char| c.is_ascii_whitespace() || c == ',') {
+                if token.is_empty() {
+                    continue;
+                }
+                if let Some(id) = TableId::from_name(token) {
+                    if seen[id.index()] {
+                        return Err(syntax);
+                    }
+                    seen[id.index()] = true;
+                    current = Some(id);
+                    continue;
+                }
+                let id = current.ok_or(syntax)?;
+                let val = parse_int(token).ok_or(syntax)?;
+                buf.push(id, val).ok_or(syntax)??;
+            }
+        }
+        for (i, seen) in seen.iter().enumerate() {
+            if !seen {
+                return Err(TableError::Missing(TableId::from_index(i)));
+            }
+        }
+        buf.tables().validate()?;
+        Ok(buf)
+    }
+
+    /// Copy a table set into owned storage.
+    pub fn from_tables(tables: &Tables) -> Result<Self, TableError> {
+        tables.validate()?;
+        let mut buf = TableBuf::empty();
+        buf.energy_len = 16;
+        buf.period_len = tables.period.len();
+        buf.k_len = tables.k.map(|k| k.len());
+        buf.chirp_len = tables.chirp.len();
+        buf.energy.copy_from_slice(tables.energy);
+        buf.period[..tables.period.len()].copy_from_slice(tables.period);
+        for (dst, src) in buf.k.iter_mut().zip(tables.k) {
+            dst[..src.len()].copy_from_slice(src);
+        }
+        buf.chirp[..tables.chirp.len()].copy_from_slice(tables.chirp);
+        Ok(buf)
+    }
+
+    fn empty() -> Self {
+        TableBuf {
+            energy: [0; 16],
+            period: [0; MAX_ENTRIES],
+            k: [[0; MAX_ENTRIES]; 10],
+            chirp: [0; MAX_ENTRIES],
+            energy_len: 0,
+            period_len: 0,
+            k_len: [0; 10],
+            chirp_len: 0,
+        }
+    }
+
+    /// The loaded tables.
+    pub fn tables(&self) -> Tables<'_> {
+        Tables {
+            energy: &self.energy[..self.energy_len],
+            period: &self.period[..self.period_len],
+            k: core::array::from_fn(|i| &self.k[i][..self.k_len[i]]),
+            chirp: &self.chirp[..self.chirp_len],
+        }
+    }
+
+    /// Append a value to a table.
+    ///
+    /// Returns `None` if the value is out of range for the table.
+    fn push(&mut self, id: TableId, val: i32) -> Option<Result<(), TableError>> {
+        fn push_to<T>(
+            slice: &mut [T],
+            len: &mut usize,
+            val: T,
+            id: TableId,
+        ) -> Result<(), TableError> {
+            *slice.get_mut(*len).ok_or(TableError::BadLength(id))? = val;
+            *len += 1;
+            Ok(())
+        }
+
+        Some(match id {
+            TableId::Energy => push_to(
+                &mut self.energy,
+                &mut self.energy_len,
+                val.try_into().ok()?,
+                id,
+            ),
+            TableId::Period => push_to(
+                &mut self.period,
+                &mut self.period_len,
+                val.try_into().ok()?,
+                id,
+            ),
+            TableId::K(i) => {
+                let i = i as usize;
+                push_to(&mut self.k[i], &mut self.k_len[i], val.try_into().ok()?, id)
+            }
+            TableId::Chirp => {
+                // Accept both signed and unsigned bytes.
+                let val = i8::try_from(val)
+                    .map(|v| v as u8)
+                    .or(u8::try_from(val))
+                    .ok()?;
+                push_to(&mut self.chirp, &mut self.chirp_len, val, id)
+            }
+        })
+    }
+}
+
+fn parse_int(token: &str) -> Option<i32> {
+    if let Some(hex) = token.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()
+    } else {
+        token.parse().ok()
+    }
+}
+
+impl TableId {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "energy" => Some(TableId::Energy),
+            "period" => Some(TableId::Period),
+            "chirp" => Some(TableId::Chirp),
+            _ => {
+                let i: u8 = name.strip_prefix('k')?.parse().ok()?;
+                (1..=10).contains(&i).then_some(TableId::K(i - 1))
+            }
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            TableId::Energy => 0,
+            TableId::Period => 1,
+            TableId::K(i) => 2 + i as usize,
+            TableId::Chirp => 12,
+        }
+    }
+
+    fn from_index(i: usize) -> Self {
+        match i {
+            0 => TableId::Energy,
+            1 => TableId::Period,
+            12 => TableId::Chirp,
+            _ => TableId::K(i as u8 - 2),
+        }
+    }
+}
+
+impl fmt::Display for TableId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableId::Energy => write!(f, "energy"),
+            TableId::Period => write!(f, "period"),
+            TableId::K(i) => write!(f, "k{}", i + 1),
+            TableId::Chirp => write!(f, "chirp"),
+        }
+    }
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::BadLength(id) => write!(f, "{id} table has a bad length"),
+            TableError::NonzeroFirst(id) => write!(f, "first entry of {id} table must be 0"),
+            TableError::OutOfRange(id) => write!(f, "{id} table has values out of range"),
+            TableError::Missing(id) => write!(f, "{id} table is missing"),
+            TableError::Syntax { line } => write!(f, "syntax error on line {line}"),
+        }
+    }
+}
+
+impl core::error::Error for TableError {}
+
+/// Writes the tables in the text format.
+impl fmt::Display for Tables<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn table<T: fmt::Display>(
+            f: &mut fmt::Formatter<'_>,
+            id: TableId,
+            vals: impl Iterator<Item = T>,
+        ) -> fmt::Result {
+            write!(f, "{id}")?;
+            for (i, val) in vals.enumerate() {
+                let sep = if i > 0 && i % 16 == 0 { "\n   " } else { " " };
+                write!(f, "{sep}{val}")?;
+            }
+            writeln!(f)
+        }
+
+        table(f, TableId::Energy, self.energy.iter())?;
+        table(f, TableId::Period, self.period.iter())?;
+        for (i, k) in self.k.iter().enumerate() {
+            table(f, TableId::K(i as u8), k.iter())?;
+        }
+        table(f, TableId::Chirp, self.chirp.iter().map(|c| Hex(*c)))
+    }
+}
+
+struct Hex(u8);
+
+impl fmt::Display for Hex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let text = TMS5220.to_string();
+        let buf = TableBuf::parse(&text).unwrap();
+        assert_eq!(buf.tables().to_string(), text);
+        assert!(TableBuf::parse(&text.replace(" 7789", " 32767")).is_ok());
+    }
+
+    #[test]
+    fn parse_rejects_loud_energy() {
+        let text = TMS5220.to_string().replace(" 7789", " 32768");
+        assert_eq!(
+            TableBuf::parse(&text).err(),
+            Some(TableError::OutOfRange(TableId::Energy))
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Edit a bitstream frame by frame, printing the result in hex.
+
+use clap::Parser;
+use speakie::{
+    BitStream, Frame, Speakie,
+    tables::{self, Tables},
+};
+use speakie_enc::{
+    data_file::DataFormat,
+    edit::{self, Utterance},
+    tts::{self, Inventory, Voice},
+};
+
+#[derive(Parser)]
+struct Args {
+    /// A bitstream file in hex, binary or a C array.
+    filename: String,
+    /// Keep frames from this one on.
+    #[arg(long, default_value_t = 0)]
+    start: usize,
+    /// Keep frames before this one.
+    #[arg(long)]
+    end: Option<usize>,
+    /// Remove silence from the start and end.
+    #[arg(long)]
+    trim_silence: bool,
+    /// Play backwards.
+    #[arg(long)]
+    reverse: bool,
+    /// Shift energy codes by this many steps of about 3dB.
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    gain: i32,
+    /// Shift period codes by this many steps, positive for higher pitch.
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    transpose: i32,
+    /// Append another bitstream file.
+    #[arg(long)]
+    append: Option<String>,
+    /// Silence frames before the appended bitstream.
+    #[arg(long, default_value_t = 0)]
+    gap: usize,
+    /// Also decode the result to a WAV file.
+    #[arg(short, long)]
+    output_file: Option<String>,
+}
+
+fn decode(data: &[u8], tables: Tables) -> Vec<i16> {
+    let mut bs = BitStream::new(data);
+    let mut speakie = Speakie::with_tables(tables);
+    let mut samples = vec![];
+    while !speakie.process_frame(&mut bs) {
+        samples.extend((0..speakie.frame_len()).map(|_| speakie.get_sample()));
+    }
+    samples
+}
+
+fn check() {
+    let t = tables::TMS5220;
+    let data = tts::speak("Hello there.", &Inventory::builtin(t), &Voice::default(), t).unwrap();
+
+    // Serializing re-enables repeats without changing the sound.
+    let hello = Utterance::parse(&data, t);
+    let bytes = hello.to_bytes();
+    assert_eq!(decode(&bytes, t), decode(&data, t));
+    assert_eq!(Utterance::parse(&bytes, t).frames(), hello.frames());
+
+    // A repeat after an unvoiced frame is resolved, and comes back.
+    let voiced = Frame {
+        energy: 9,
+        period: 30,
+        k: [10, 20, 7, 8, 9, 10, 11, 4, 5, 6],
+        ..Frame::default()
+    };
+    let mut frames = vec![voiced, Frame::silence(), voiced, voiced];
+    edit::compress_repeats(&mut frames, &t);
+    assert!(!frames[0].repeat && frames[2].repeat && frames[3].repeat);
+    edit::resolve_repeats(&mut frames);
+    assert_eq!(frames, [voiced, Frame::silence(), voiced, voiced]);
+
+    let mut u = hello.clone();
+    u.reverse();
+    u.reverse();
+    assert_eq!(u.frames(), hello.frames());
+    u.insert_silence(2, 3);
+    u.splice(0, &hello);
+    assert_eq!(u.len(), 2 * hello.len() + 3);
+    u.trim(hello.len()..u.len());
+    assert!(u.frames()[2..5].iter().all(Frame::is_silence));
+
+    let mut louder = hello.clone();
+    louder.scale_energy(2);
+    let mut higher = hello.clone();
+    higher.transpose(3);
+    for ((a, b), c) in hello
+        .frames()
+        .iter()
+        .zip(louder.frames())
+        .zip(higher.frames())
+    {
+        assert_eq!(a.is_silence(), b.is_silence());
+        assert!(a.is_silence() || b.energy == (a.energy + 2).min(14));
+        assert!(!a.is_voiced() || a.is_silence() || c.period == a.period.max(4) - 3);
+    }
+}
+
+fn main() {
+    check();
+    let args = Args::parse();
+    let tables = tables::TMS5220;
+    let read = |filename: &str| {
+        let data = std::fs::read(filename).expect("error reading bitstream");
+        DataFormat::detect(&data)
+            .parse(&data)
+            .expect("error parsing bitstream")
+    };
+    let data = read(&args.filename);
+    let mut u = Utterance::parse(&data, tables);
+    let end = args.end.unwrap_or(u.len()).min(u.len());
+    u.trim(args.start.min(end)..end);
+    if args.trim_silence {
+        u.trim_silence();
+    }
+    if args.reverse {
+        u.reverse();
+    }
+    u.scale_energy(args.gain);
+    u.transpose(args.transpose);
+    if let Some(filename) = &args.append {
+        let other = Utterance::parse(&read(filename), tables);
+        u.insert_silence(u.len(), args.gap);
+        u.append(&other);
+    }
+
+    let bytes = u.to_bytes();
+    print!("[");
+    for byte in &bytes {
+        print!("0x{byte:02x}, ");
+    }
+    println!("]");
+
+    if let Some(output_file) = &args.output_file {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(output_file, spec).unwrap();
+        for sample in decode(&bytes, tables) {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+}
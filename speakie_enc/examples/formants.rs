@@ -0,0 +1,97 @@
+//! Print the formants and line spectral frequencies of each frame, from a
+//! bitstream or from the encoder's analysis of a WAV file.
+
+use clap::Parser;
+use speakie::{BitStream, Speakie};
+use speakie_enc::{
+    data_file::DataFormat,
+    reflector::Reflector,
+    spectral::{AllPole, Formant},
+};
+
+#[derive(Parser)]
+struct Args {
+    /// A bitstream file in hex, binary or a C array, or a WAV file with
+    /// `--wav`.
+    filename: String,
+    /// Analyze 8kHz WAV samples, as the encoder does.
+    #[arg(long)]
+    wav: bool,
+    /// Also print line spectral frequencies.
+    #[arg(long)]
+    lsf: bool,
+}
+
+fn check() {
+    // A single resonance at 1kHz with a 100Hz bandwidth.
+    let r = (-std::f64::consts::PI * 100.0 / 8000.0).exp();
+    let theta = 2.0 * std::f64::consts::PI * 1000.0 / 8000.0;
+    let filter = AllPole::from_lpc(&[-2.0 * r * theta.cos(), r * r]).unwrap();
+    let [f] = filter.formants()[..] else {
+        panic!("expected one formant");
+    };
+    assert!((f.freq - 1000.0).abs() < 1.0 && (f.bandwidth - 100.0).abs() < 1.0);
+    assert!(filter.response_db(1000.0) > filter.response_db(3000.0) + 20.0);
+
+    // The conversions agree with each other.
+    let k = [-0.9, 0.6, -0.3, 0.2, -0.1, 0.1, -0.05, 0.05, 0.0, 0.02];
+    let filter = AllPole::from_k(&k);
+    let back = AllPole::from_lpc(&filter.lpc()).unwrap();
+    assert!(back.k().iter().zip(k).all(|(a, b)| (a - b).abs() < 1e-9));
+    let lsf = filter.lsf();
+    assert_eq!(lsf.len(), 10);
+    assert!(lsf.windows(2).all(|w| w[0] < w[1]));
+    assert!(filter.lar()[0] < 0.0);
+}
+
+fn print_frame(n: usize, filter: &AllPole, lsf: bool) {
+    let formants = filter
+        .formants()
+        .iter()
+        .map(|Formant { freq, bandwidth }| format!("{freq:.0}/{bandwidth:.0}"))
+        .collect::<Vec<_>>();
+    println!("{n:4}: {}", formants.join(" "));
+    if lsf {
+        let lsf = filter
+            .lsf()
+            .iter()
+            .map(|f| format!("{f:.0}"))
+            .collect::<Vec<_>>();
+        println!("      lsf {}", lsf.join(" "));
+    }
+}
+
+fn main() {
+    check();
+    let args = Args::parse();
+    println!("frame: formant Hz/bandwidth Hz...");
+    if args.wav {
+        let mut reader = hound::WavReader::open(&args.filename).expect("error opening WAV");
+        let samples = reader
+            .samples::<i16>()
+            .map(|s| s.unwrap() as f64)
+            .collect::<Vec<_>>();
+        for (n, frame) in samples.chunks_exact(200).enumerate() {
+            let reflector = Reflector::new(frame);
+            if reflector.rms() > 0.0 {
+                print_frame(n, &AllPole::from_reflector(&reflector), args.lsf);
+            }
+        }
+    } else {
+        let data = std::fs::read(&args.filename).expect("error reading bitstream");
+        let data = DataFormat::detect(&data)
+            .parse(&data)
+            .expect("error parsing bitstream");
+        let mut bs = BitStream::new(&data);
+        let mut speakie = Speakie::new();
+        for n in 0.. {
+            if speakie.process_frame(&mut bs) {
+                break;
+            }
+            let params = speakie.frame_params();
+            if params.energy != 0 {
+                print_frame(n, &AllPole::from_params(&params), args.lsf);
+            }
+        }
+    }
+}
@@ -0,0 +1,408 @@
+//! A text format for LPC frames, for editing speech by hand.
+//!
+//! Each line holds one frame, of codes rather than decoded values:
+//!
+//! ```text
+//! ; "hello", from the encoder
+//! hello:
+//!   U e=5 k=[12,9,7,3]           ; unvoiced: K1 to K4
+//!   V e=9 p=38 k=[6,21,4,11,8,9,7,3,4,4]
+//!   R e=7 p=40                   ; repeat: the coefficients don't change
+//!   SIL
+//!   STOP
+//!   PAD 7
+//! ```
+//!
+//! `;` starts a comment to the end of the line. A label, a name followed by
+//! `:`, marks the frame after it. With a variable frame rate, every line
+//! including `SIL` and `STOP` has an `r=` field with the frame's rate code,
+//! and at a fixed rate none do.
+//!
+//! Bits after the last whole frame are kept, as `PAD n` for `n` zero bits or
+//! `BITS 0110...` in bitstream order, so that any bitstream disassembles
+//! and assembles back to the same bytes.
+
+use speakie::{BitStream, Frame, FrameRate, tables::Tables};
+
+use crate::output::Output;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmError {
+    /// A line couldn't be parsed, counting from 1.
+    Syntax { line: usize },
+    /// A field is missing, or out of range for the tables' layout.
+    BadField { line: usize, field: &'static str },
+    /// A label is defined twice.
+    DuplicateLabel { line: usize },
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Syntax { line } => write!(f, "syntax error on line {line}"),
+            Self::BadField { line, field } => write!(f, "bad or missing {field} on line {line}"),
+            Self::DuplicateLabel { line } => write!(f, "duplicate label on line {line}"),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// A label, and where it points.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    /// The index of the frame after the label.
+    pub frame: usize,
+    /// The bit position of that frame, for [`BitStream::seek`].
+    pub bit_pos: usize,
+}
+
+/// The output of the assembler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assembly {
+    pub bytes: Vec<u8>,
+    pub labels: Vec<Label>,
+}
+
+/// The bit at a position, in bitstream order.
+fn bit(data: &[u8], pos: usize) -> u8 {
+    (data[pos / 8] >> (pos % 8)) & 1
+}
+
+fn format_frame(frame: &Frame) -> String {
+    let codes = |k: &[u8]| {
+        k.iter()
+            .map(|k| k.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    if frame.is_stop() {
+        "STOP".to_string()
+    } else if frame.is_silence() {
+        "SIL".to_string()
+    } else if frame.repeat {
+        format!("R e={} p={}", frame.energy, frame.period)
+    } else if frame.is_voiced() {
+        format!(
+            "V e={} p={} k=[{}]",
+            frame.energy,
+            frame.period,
+            codes(&frame.k)
+        )
+    } else {
+        format!("U e={} k=[{}]", frame.energy, codes(&frame.k[..4]))
+    }
+}
+
+/// Disassemble a bitstream, to its end.
+///
+/// Frames are read past stop frames, unless only zero bits follow, so that
+/// banks of several utterances disassemble whole.
+pub fn disassemble(data: &[u8], tables: Tables, rate: FrameRate) -> String {
+    let layout = tables.layout();
+    let n_bits = data.len() * 8;
+    let mut bs = BitStream::new(data);
+    let mut lines = vec![];
+    let mut after_stop = false;
+    loop {
+        let start = bs.bit_pos();
+        if start == n_bits || (after_stop && (start..n_bits).all(|i| bit(data, i) == 0)) {
+            break;
+        }
+        let mut rate_code = None;
+        if rate.variable && start + 2 <= n_bits {
+            rate_code = Some(bit(data, start) << 1 | bit(data, start + 1));
+            bs.seek(start + 2);
+        }
+        let frame = Frame::read(&mut bs, &layout);
+        if bs.is_overrun() || (rate.variable && rate_code.is_none()) {
+            bs.seek(start);
+            break;
+        }
+        after_stop = frame.is_stop();
+        let mut line = format_frame(&frame);
+        if let Some(code) = rate_code {
+            line += &format!(" r={code}");
+        }
+        lines.push(line);
+    }
+    let rest = bs.bit_pos()..n_bits;
+    if rest.clone().all(|i| bit(data, i) == 0) {
+        if !rest.is_empty() {
+            lines.push(format!("PAD {}", rest.len()));
+        }
+    } else {
+        let bits = rest
+            .map(|i| char::from(b'0' + bit(data, i)))
+            .collect::<String>();
+        lines.push(format!("BITS {bits}"));
+    }
+    let mut text = String::new();
+    for line in lines {
+        if !line.starts_with("PAD") && !line.starts_with("BITS") {
+            text.push_str("  ");
+        }
+        text += &line;
+        text.push('\n');
+    }
+    text
+}
+
+/// The fields of a line, as `key=value` with values in brackets kept whole.
+fn fields(text: &str) -> Option<Vec<(&str, &str)>> {
+    let mut fields = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+        let end = if after.starts_with('[') {
+            after.find(']')? + 1
+        } else {
+            after.find(char::is_whitespace).unwrap_or(after.len())
+        };
+        fields.push((key.trim(), &after[..end]));
+        rest = after[end..].trim_start();
+    }
+    Some(fields)
+}
+
+/// Assemble text into a bitstream, with a rate code before each frame if
+/// `rate` is variable.
+pub fn assemble(text: &str, tables: Tables, rate: FrameRate) -> Result<Assembly, AsmError> {
+    let layout = tables.layout();
+    let mut out = Output::new(tables);
+    let mut bit_pos = 0;
+    let mut labels: Vec<Label> = vec![];
+    let mut n_frames = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let syntax = AsmError::Syntax { line: line_no };
+        let mut line = line.split(';').next().unwrap().trim();
+        if let Some((name, rest)) = line.split_once(':')
+            && !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            if labels.iter().any(|l| l.name == name) {
+                return Err(AsmError::DuplicateLabel { line: line_no });
+            }
+            labels.push(Label {
+                name: name.to_string(),
+                frame: n_frames,
+                bit_pos,
+            });
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (op, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match op.to_ascii_uppercase().as_str() {
+            "PAD" => {
+                let n: usize = rest.trim().parse().map_err(|_| syntax.clone())?;
+                for _ in 0..n {
+                    out.pack(0, 1);
+                }
+                bit_pos += n;
+                continue;
+            }
+            "BITS" => {
+                for c in rest.trim().chars() {
+                    match c {
+                        '0' | '1' => out.pack(c as u32 - '0' as u32, 1),
+                        '_' => continue,
+                        _ => return Err(syntax),
+                    }
+                    bit_pos += 1;
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let fields = fields(rest).ok_or(syntax.clone())?;
+        let bad = |field| AsmError::BadField {
+            line: line_no,
+            field,
+        };
+        let get = |key: &'static str, bits: u8| -> Result<Option<u8>, AsmError> {
+            match fields.iter().find(|(k, _)| *k == key) {
+                Some((_, v)) => match v.parse::<u8>() {
+                    Ok(v) if (v as u32) < 1 << bits => Ok(Some(v)),
+                    _ => Err(bad(key)),
+                },
+                None => Ok(None),
+            }
+        };
+        if fields
+            .iter()
+            .any(|(k, _)| !["e", "p", "k", "r"].contains(k))
+        {
+            return Err(syntax);
+        }
+        let sounding = |e: Option<u8>| match e {
+            Some(e @ 1..=14) => Ok(e),
+            _ => Err(bad("e")),
+        };
+        let codes = |n: usize| -> Result<[u8; 10], AsmError> {
+            let (_, list) = fields.iter().find(|(k, _)| *k == "k").ok_or(bad("k"))?;
+            let list = list
+                .strip_prefix('[')
+                .and_then(|l| l.strip_suffix(']'))
+                .ok_or(bad("k"))?;
+            let mut k = [0; 10];
+            let mut count = 0;
+            for (j, code) in list.split(',').enumerate() {
+                let code: u8 = code.trim().parse().map_err(|_| bad("k"))?;
+                if j >= n || code as u32 >= 1 << layout.k_bits[j] {
+                    return Err(bad("k"));
+                }
+                k[j] = code;
+                count += 1;
+            }
+            if count != n {
+                return Err(bad("k"));
+            }
+            Ok(k)
+        };
+        let energy = get("e", 4)?;
+        let period = get("p", layout.period_bits)?;
+        let frame = match op.to_ascii_uppercase().as_str() {
+            "SIL" => Frame::silence(),
+            "STOP" => Frame::stop(),
+            "V" => Frame {
+                energy: sounding(energy)?,
+                period: period.filter(|p| *p > 0).ok_or(bad("p"))?,
+                k: codes(10)?,
+                repeat: false,
+            },
+            "U" => Frame {
+                energy: sounding(energy)?,
+                period: 0,
+                k: codes(4)?,
+                repeat: false,
+            },
+            "R" => Frame {
+                energy: sounding(energy)?,
+                period: period.unwrap_or(0),
+                k: [0; 10],
+                repeat: true,
+            },
+            _ => return Err(syntax),
+        };
+        match get("r", 2)? {
+            Some(code) if rate.variable => {
+                out.pack(code.into(), 2);
+                bit_pos += 2;
+            }
+            None if !rate.variable => {}
+            _ => return Err(bad("r")),
+        }
+        out.write_frame(&frame);
+        bit_pos += frame.bit_len(&layout);
+        n_frames += 1;
+    }
+    Ok(Assembly {
+        bytes: out.reap(),
+        labels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use speakie::tables;
+
+    use super::*;
+
+    /// The README example.
+    const HELLO: [u8; 106] = [
+        0x02, 0xc8, 0x9a, 0x35, 0x59, 0x45, 0xaa, 0x7b, 0x84, 0xe5, 0x66, 0x67, 0x9d, 0xae, 0x95,
+        0x96, 0x9b, 0x9b, 0x5c, 0xa9, 0x4e, 0x49, 0x6d, 0x7e, 0x54, 0x13, 0x94, 0x6d, 0xb5, 0xc4,
+        0x85, 0x74, 0x33, 0xf7, 0x94, 0x56, 0x54, 0x5c, 0x2d, 0x54, 0xb3, 0x56, 0x55, 0x49, 0x8c,
+        0x48, 0x4f, 0x1e, 0x6d, 0xa3, 0x36, 0xaa, 0x79, 0x2b, 0x4d, 0x99, 0xe5, 0x50, 0xd5, 0xc8,
+        0xb2, 0x46, 0x95, 0x25, 0x91, 0x33, 0xcb, 0x1e, 0x55, 0x35, 0x67, 0xdc, 0x72, 0x47, 0x70,
+        0x9d, 0x94, 0x79, 0x49, 0x0c, 0xde, 0x76, 0x40, 0x44, 0x05, 0x36, 0x24, 0xd5, 0x0d, 0x2c,
+        0x33, 0x51, 0x93, 0x99, 0x0f, 0x93, 0x94, 0x41, 0x75, 0x96, 0xd9, 0xec, 0x6e, 0x07, 0xe0,
+        0x01,
+    ];
+
+    const FIXED: FrameRate = FrameRate {
+        code: 0,
+        variable: false,
+    };
+
+    const VARIABLE: FrameRate = FrameRate {
+        code: 0,
+        variable: true,
+    };
+
+    fn round_trip(data: &[u8], rate: FrameRate) -> String {
+        let text = disassemble(data, tables::TMS5220, rate);
+        let assembly = assemble(&text, tables::TMS5220, rate).unwrap();
+        assert_eq!(assembly.bytes, data, "{text}");
+        text
+    }
+
+    #[test]
+    fn round_trip_hello() {
+        let text = round_trip(&HELLO, FIXED);
+        assert!(text.contains("STOP\n"));
+    }
+
+    #[test]
+    fn round_trip_truncated() {
+        for len in 1..HELLO.len() {
+            round_trip(&HELLO[..len], FIXED);
+        }
+        assert!(round_trip(&HELLO[..20], FIXED).contains("BITS "));
+    }
+
+    #[test]
+    fn round_trip_variable_rate() {
+        let voiced = Frame {
+            energy: 9,
+            repeat: false,
+            period: 38,
+            k: [6, 21, 4, 11, 8, 9, 7, 3, 4, 4],
+        };
+        let frames = [
+            (0, Frame::silence()),
+            (2, voiced),
+            (
+                3,
+                Frame {
+                    repeat: true,
+                    ..voiced
+                },
+            ),
+            (1, Frame::stop()),
+        ];
+        let mut out = Output::new(tables::TMS5220);
+        for (code, frame) in &frames {
+            out.pack(*code, 2);
+            out.write_frame(frame);
+        }
+        let data = out.reap();
+        let text = round_trip(&data, VARIABLE);
+        assert_eq!(text.matches(" r=").count(), frames.len());
+
+        // Every frame needs a rate code, and a fixed rate none.
+        let missing = text.replacen(" r=2", "", 1);
+        assert_eq!(
+            assemble(&missing, tables::TMS5220, VARIABLE),
+            Err(AsmError::BadField {
+                line: 2,
+                field: "r"
+            })
+        );
+        assert_eq!(
+            assemble(&text, tables::TMS5220, FIXED),
+            Err(AsmError::BadField {
+                line: 1,
+                field: "r"
+            })
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Subcommands that make new speech: from text, from a formant timeline, from
+//! a script of encoded words, or from an existing bitstream set to a melody
+//! or a pitch contour.
+
+use std::path::Path;
+
+use clap::Args;
+use speakie::{
+    BitStream, BitWriter, Frame, FrameRate, Frames, Speakie,
+    intonation::Contour,
+    tables::{self, TableBuf, Tables},
+};
+use speakie_enc::{
+    formant_synth,
+    script::{self, Words},
+    sing::{self, Note},
+    tts::{self, Inventory, Voice, phonemes},
+};
+
+use crate::{
+    BitstreamInput, BitstreamOutput, Result, StreamArgs, TablesArg, write_output, write_wav,
+};
+
+#[derive(Args)]
+pub struct Tts {
+    /// The text to speak.
+    text: String,
+    /// Print the phonemes instead, with `1` marking stressed vowels.
+    #[arg(long)]
+    phonemes: bool,
+    /// The middle of the pitch range, in Hz.
+    #[arg(long, default_value_t = 110.0)]
+    pitch: f64,
+    /// Speaking rate, 1 for normal.
+    #[arg(long, default_value_t = 1.0)]
+    rate: f64,
+    /// A directory of recorded allophones, `PHONEME.wav` at 8kHz, replacing
+    /// the built-in ones.
+    #[arg(long)]
+    allophones: Option<String>,
+    #[command(flatten)]
+    tables: TablesArg,
+    #[command(flatten)]
+    output: BitstreamOutput,
+    /// Also decode the bitstream to a WAV file.
+    #[arg(long)]
+    wav: Option<String>,
+}
+
+#[derive(Args)]
+pub struct FormantSynth {
+    /// The timeline: lines of `time_ms pitch_hz amplitude freq/bandwidth...`.
+    timeline: String,
+    #[command(flatten)]
+    stream: StreamArgs,
+    #[command(flatten)]
+    output: BitstreamOutput,
+    /// Also decode the bitstream to a WAV file, to preview it.
+    #[arg(long)]
+    wav: Option<String>,
+}
+
+#[derive(Args)]
+pub struct Script {
+    /// A file with the script, such as `HELLO [pause 150ms] WORLD`.
+    #[arg(required_unless_present = "text")]
+    script: Option<String>,
+    /// The script itself, instead of a file.
+    #[arg(short, long, conflicts_with = "script")]
+    text: Option<String>,
+    /// A directory of encoded words, as `NAME.hex` files.
+    #[arg(short, long)]
+    words: String,
+    #[command(flatten)]
+    tables: TablesArg,
+    #[command(flatten)]
+    output: BitstreamOutput,
+    /// Also decode the bitstream to a WAV file.
+    #[arg(long)]
+    wav: Option<String>,
+}
+
+#[derive(Args)]
+pub struct Sing {
+    #[command(flatten)]
+    input: BitstreamInput,
+    /// A MIDI file with the melody.
+    #[arg(long, conflicts_with = "notes", required_unless_present = "notes")]
+    midi: Option<String>,
+    /// A note list with the melody: lines of `note duration_ms`.
+    #[arg(long)]
+    notes: Option<String>,
+    #[command(flatten)]
+    tables: TablesArg,
+    /// Write a WAV file instead of raw PCM on stdout.
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Args)]
+pub struct Intonation {
+    #[command(flatten)]
+    input: BitstreamInput,
+    /// A preset (question, statement or emphasis), or breakpoints as
+    /// `pos:semitones,...` with positions increasing from 0 to 1, and at most
+    /// 24 semitones either way.
+    #[arg(short, long, value_parser = parse_contour)]
+    contour: Contour,
+    #[command(flatten)]
+    tables: TablesArg,
+    /// Write the bitstream to a file instead of stdout, in the input's
+    /// format.
+    #[arg(short, long)]
+    output: Option<String>,
+    /// The array name for C output.
+    #[arg(long, default_value = "speech")]
+    name: String,
+    /// Also decode the result to a WAV file.
+    #[arg(long)]
+    wav: Option<String>,
+}
+
+fn parse_contour(text: &str) -> std::result::Result<Contour, String> {
+    Contour::parse(text).ok_or_else(|| format!("bad contour {text}"))
+}
+
+/// Decode a bitstream up to its stop frame, or its end.
+fn render(data: &[u8], tables: Tables, rate: FrameRate) -> Vec<i16> {
+    let mut bs = BitStream::new(data);
+    let mut speakie = Speakie::with_tables(tables);
+    speakie.set_rate(rate);
+    let mut samples = vec![];
+    while !speakie.process_frame(&mut bs) && !bs.is_overrun() {
+        samples.extend((0..speakie.frame_len()).map(|_| speakie.get_sample()));
+    }
+    samples
+}
+
+pub fn tts(args: Tts) -> Result<()> {
+    if args.phonemes {
+        let mut text = String::new();
+        for phrase in phonemes::text_to_phrases(&args.text) {
+            let phones = phrase
+                .phones
+                .iter()
+                .map(|p| format!("{}{}", p.name, if p.stressed { "1" } else { "" }))
+                .collect::<Vec<_>>();
+            text += &format!("{} ({:?})\n", phones.join(" "), phrase.end);
+        }
+        return write_output(None, text.as_bytes());
+    }
+    let table_buf = args.tables.load()?;
+    let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+    let mut inventory = Inventory::builtin(tables);
+    if let Some(dir) = &args.allophones {
+        let n = inventory
+            .load_wav_dir(Path::new(dir), tables)
+            .map_err(|e| format!("{dir}: {e}"))?;
+        eprintln!("loaded {n} allophones");
+    }
+    let voice = Voice {
+        pitch: args.pitch,
+        rate: args.rate,
+    };
+    let bytes = tts::speak(&args.text, &inventory, &voice, tables)?;
+    args.output.write(&bytes)?;
+    if let Some(wav) = &args.wav {
+        write_wav(Some(wav), &render(&bytes, tables, FrameRate::default()))?;
+    }
+    Ok(())
+}
+
+pub fn formant_synth(args: FormantSynth) -> Result<()> {
+    if args.stream.hq {
+        return Err("formant-synth makes TMS5220 frames, not --hq".into());
+    }
+    let filename = &args.timeline;
+    let text = std::fs::read_to_string(filename).map_err(|e| format!("{filename}: {e}"))?;
+    let keyframes = formant_synth::parse_timeline(&text).map_err(|e| format!("{filename}: {e}"))?;
+    let table_buf = args.stream.load_tables()?;
+    let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+    let rate = args.stream.rate();
+    let bytes = formant_synth::synthesize(&keyframes, tables, rate)
+        .map_err(|e| format!("{filename}: {e}"))?;
+    args.output.write(&bytes)?;
+    if let Some(wav) = &args.wav {
+        write_wav(Some(wav), &render(&bytes, tables, rate))?;
+    }
+    Ok(())
+}
+
+pub fn script(args: Script) -> Result<()> {
+    let (name, text) = match (&args.text, &args.script) {
+        (Some(text), _) => ("script", text.clone()),
+        (None, Some(filename)) => (
+            filename.as_str(),
+            std::fs::read_to_string(filename).map_err(|e| format!("{filename}: {e}"))?,
+        ),
+        (None, None) => unreachable!(),
+    };
+    let dir = &args.words;
+    let words = Words::load(Path::new(dir)).map_err(|e| format!("{dir}: {e}"))?;
+    let table_buf = args.tables.load()?;
+    let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+    let bytes = script::parse_script(&text)
+        .and_then(|items| script::compile(&items, &words, tables))
+        .map_err(|e| format!("{name}: {e}"))?;
+    args.output.write(&bytes)?;
+    if let Some(wav) = &args.wav {
+        write_wav(Some(wav), &render(&bytes, tables, FrameRate::default()))?;
+    }
+    Ok(())
+}
+
+fn read_notes(args: &Sing) -> Result<Vec<Note>> {
+    if let Some(midi) = &args.midi {
+        let data = std::fs::read(midi).map_err(|e| format!("{midi}: {e}"))?;
+        Ok(sing::read_midi(&data).map_err(|e| format!("{midi}: {e}"))?)
+    } else {
+        let notes = args.notes.as_ref().unwrap();
+        let text = std::fs::read_to_string(notes).map_err(|e| format!("{notes}: {e}"))?;
+        Ok(sing::parse_notes(&text).map_err(|e| format!("{notes}: {e}"))?)
+    }
+}
+
+pub fn sing(args: Sing) -> Result<()> {
+    let data = args.input.read()?;
+    let notes = read_notes(&args)?;
+    let table_buf = args.tables.load()?;
+    let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+    let mut bs = BitStream::new(&data);
+    let frames = Frames::new(&mut bs, &tables.layout()).collect::<Vec<_>>();
+    write_wav(args.output.as_deref(), &sing::sing(&frames, &notes, tables))
+}
+
+pub fn intonation(args: Intonation) -> Result<()> {
+    let (data, format) = args.input.read_with_format()?;
+    let table_buf = args.tables.load()?;
+    let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+    let layout = tables.layout();
+    let mut bs = BitStream::new(&data);
+    let mut frames = Frames::new(&mut bs, &layout);
+    let mut rewritten = frames.by_ref().collect::<Vec<_>>();
+    if !frames.is_truncated() {
+        rewritten.push(Frame::stop());
+    }
+    args.contour.apply(&mut rewritten, &tables);
+
+    let mut buf = vec![0; data.len() + 1];
+    let mut w = BitWriter::new(&mut buf);
+    for frame in &rewritten {
+        frame.write(&mut w, &layout);
+    }
+    let len = w.byte_len();
+    buf.truncate(len);
+    write_output(args.output.as_deref(), &format.format(&buf, &args.name))?;
+    if let Some(wav) = &args.wav {
+        write_wav(Some(wav), &render(&buf, tables, FrameRate::default()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,818 @@
+//! The speakie command line tool: decode, encode and inspect bitstreams, and
+//! make new ones from text, formants, scripts, melodies and pitch contours.
+//!
+//! Bitstreams are read as hex, binary or C arrays, guessing which unless
+//! `--format` is given, and `-` reads stdin. Audio goes to a WAV file with
+//! `-o`, or else to stdout as raw 16 bit little-endian PCM at 8kHz, for
+//! example to pipe into `aplay -f S16_LE -r 8000`.
+//!
+//! The exit status is 0 on success, 1 if a file can't be read, parsed or
+//! written, and 2 for bad usage.
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+    process::ExitCode,
+};
+
+mod compose;
+
+use clap::{Args, Parser, Subcommand};
+use speakie::{
+    BitStream, Frame, FrameRate, Params, Speakie,
+    hq::HqFrame,
+    postfilter::{PostFilter, Preset},
+    tables::{self, TableBuf, Tables},
+};
+use speakie_enc::{
+    asm,
+    data_file::DataFormat,
+    diff::{Diff, FrameDiff},
+    edit::Utterance,
+    optimize::optimize,
+    stats::Stats,
+    to_lpc,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const SAMPLE_RATE: u32 = 8000;
+
+#[derive(Parser)]
+#[command(
+    name = "speakie",
+    version,
+    about = "Decode, encode and inspect LPC speech"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode a bitstream to a WAV file, or raw PCM on stdout.
+    Decode {
+        #[command(flatten)]
+        input: BitstreamInput,
+        #[command(flatten)]
+        stream: StreamArgs,
+        #[command(flatten)]
+        audio: AudioOutput,
+    },
+    /// Encode a WAV file to a bitstream.
+    Encode {
+        /// An 8kHz WAV file, or `-` for stdin.
+        input: String,
+        #[command(flatten)]
+        stream: StreamArgs,
+        #[command(flatten)]
+        output: BitstreamOutput,
+    },
+    /// Print frame counts, duration and bitrate.
+    Info {
+        #[command(flatten)]
+        input: BitstreamInput,
+        #[command(flatten)]
+        stream: StreamArgs,
+    },
+    /// List frames with their decoded parameters.
+    Dump {
+        #[command(flatten)]
+        input: BitstreamInput,
+        #[command(flatten)]
+        stream: StreamArgs,
+    },
+    /// Report the frame mix, histograms of every code and where
+    /// interpolation is inhibited.
+    Stats {
+        #[command(flatten)]
+        input: BitstreamInput,
+        #[command(flatten)]
+        stream: StreamArgs,
+        /// Print JSON instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Repack a bitstream losslessly with repeat frames, checking that it
+    /// decodes to the same samples.
+    Optimize {
+        #[command(flatten)]
+        input: BitstreamInput,
+        #[command(flatten)]
+        stream: StreamArgs,
+        /// Write the bitstream to a file instead of stdout, in the input's
+        /// format.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// The array name for C output.
+        #[arg(long, default_value = "speech")]
+        name: String,
+    },
+    /// Align two bitstreams frame by frame and show what changed.
+    Diff {
+        /// The original bitstream file.
+        a: String,
+        /// The changed bitstream file.
+        b: String,
+        /// Input format of both: hex, bin or c. Guessed from the contents by
+        /// default.
+        #[arg(short, long, value_parser = parse_format)]
+        format: Option<DataFormat>,
+        #[command(flatten)]
+        stream: StreamArgs,
+        /// Frames further apart than this many dB count toward a region.
+        #[arg(long, default_value_t = 3.0)]
+        threshold: f64,
+        /// How many of the biggest changed regions to list.
+        #[arg(long, default_value_t = 5)]
+        regions: usize,
+        /// List every frame, not just the ones that changed.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Encode a WAV file and decode it again, to hear the encoder.
+    Roundtrip {
+        /// An 8kHz WAV file, or `-` for stdin.
+        input: String,
+        #[command(flatten)]
+        stream: StreamArgs,
+        #[command(flatten)]
+        audio: AudioOutput,
+        /// Also write the bitstream, in hex, to a file.
+        #[arg(long)]
+        bitstream: Option<String>,
+    },
+    /// Assemble the one frame per line text format into a bitstream.
+    Asm {
+        /// LPC assembly text, or `-` for stdin.
+        input: String,
+        #[command(flatten)]
+        tables: TablesArg,
+        /// Write a 2 bit rate code before each frame, from its `r=` field.
+        #[arg(long)]
+        variable_rate: bool,
+        #[command(flatten)]
+        output: BitstreamOutput,
+    },
+    /// Disassemble a bitstream into one frame per line of text.
+    Disasm {
+        #[command(flatten)]
+        input: BitstreamInput,
+        #[command(flatten)]
+        tables: TablesArg,
+        /// A 2 bit rate code comes before each frame.
+        #[arg(long)]
+        variable_rate: bool,
+        /// Write the text to a file instead of stdout.
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Speak English text.
+    Tts(compose::Tts),
+    /// Render a formant timeline to a bitstream.
+    FormantSynth(compose::FormantSynth),
+    /// Join encoded words into a voice prompt, following a script.
+    Script(compose::Script),
+    /// Sing a bitstream to a melody.
+    Sing(compose::Sing),
+    /// Apply a pitch contour to a bitstream, rewriting its period codes.
+    Intonation(compose::Intonation),
+}
+
+#[derive(Args)]
+struct BitstreamInput {
+    /// A bitstream file, or `-` for stdin.
+    input: String,
+    /// Input format: hex, bin or c. Guessed from the contents by default.
+    #[arg(short, long, value_parser = parse_format)]
+    format: Option<DataFormat>,
+}
+
+/// Where a new bitstream goes, and in what format.
+#[derive(Args)]
+struct BitstreamOutput {
+    /// Write the bitstream to a file instead of stdout.
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Output format: hex, bin or c.
+    #[arg(short, long, default_value = "hex", value_parser = parse_format)]
+    format: DataFormat,
+    /// The array name for C output.
+    #[arg(long, default_value = "speech")]
+    name: String,
+}
+
+#[derive(Args)]
+struct TablesArg {
+    /// Load coefficient tables from a file, instead of the TMS5220 ones.
+    #[arg(long)]
+    tables: Option<String>,
+}
+
+/// How frames are coded, shared by the subcommands that read or write
+/// bitstreams in any format.
+#[derive(Args)]
+struct StreamArgs {
+    /// Use the "speakie HQ" format.
+    #[arg(long)]
+    hq: bool,
+    #[command(flatten)]
+    tables: TablesArg,
+    /// TMS5220C frame rate code: 0 to 3 for 200, 150, 100 or 50 sample frames.
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3))]
+    rate: u8,
+    /// A 2 bit rate code comes before each frame.
+    #[arg(long)]
+    variable_rate: bool,
+}
+
+#[derive(Args)]
+struct AudioOutput {
+    /// Write a WAV file instead of raw PCM on stdout.
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Emulate an analog output stage: flat, echo-ii, small-speaker or telephone.
+    #[arg(long, default_value = "flat", value_parser = parse_preset)]
+    post_filter: Preset,
+    /// Ramp energy per sample and fade out after the stop frame.
+    #[arg(long)]
+    smooth: bool,
+}
+
+fn parse_format(name: &str) -> std::result::Result<DataFormat, String> {
+    DataFormat::from_name(name).ok_or_else(|| format!("unknown format {name}"))
+}
+
+fn parse_preset(name: &str) -> std::result::Result<Preset, String> {
+    Preset::from_name(name).ok_or_else(|| format!("unknown preset {name}"))
+}
+
+impl TablesArg {
+    fn load(&self) -> Result<Option<TableBuf>> {
+        let Some(filename) = &self.tables else {
+            return Ok(None);
+        };
+        let text = std::fs::read_to_string(filename).map_err(|e| format!("{filename}: {e}"))?;
+        let buf = TableBuf::parse(&text).map_err(|e| format!("{filename}: {e}"))?;
+        Ok(Some(buf))
+    }
+}
+
+impl StreamArgs {
+    fn load_tables(&self) -> Result<Option<TableBuf>> {
+        self.tables.load()
+    }
+
+    fn rate(&self) -> FrameRate {
+        FrameRate {
+            code: self.rate,
+            variable: self.variable_rate,
+        }
+    }
+}
+
+/// Read a file, or stdin for `-`.
+fn read_input(filename: &str) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    if filename == "-" {
+        std::io::stdin().read_to_end(&mut data)?;
+    } else {
+        data = std::fs::read(filename).map_err(|e| format!("{filename}: {e}"))?;
+    }
+    Ok(data)
+}
+
+/// Write a file, or stdout for `-` or no file.
+fn write_output(filename: Option<&str>, data: &[u8]) -> Result<()> {
+    match filename {
+        Some(filename) if filename != "-" => {
+            std::fs::write(filename, data).map_err(|e| format!("{filename}: {e}"))?
+        }
+        _ => std::io::stdout().lock().write_all(data)?,
+    }
+    Ok(())
+}
+
+/// Read a bitstream file, guessing its format if not given.
+fn read_bitstream(filename: &str, format: Option<DataFormat>) -> Result<Vec<u8>> {
+    let data = read_input(filename)?;
+    let format = format.unwrap_or_else(|| DataFormat::detect(&data));
+    let bytes = format
+        .parse(&data)
+        .map_err(|e| format!("{filename}: {e}"))?;
+    Ok(bytes)
+}
+
+impl BitstreamInput {
+    fn read(&self) -> Result<Vec<u8>> {
+        read_bitstream(&self.input, self.format)
+    }
+
+    /// Read the bitstream, also returning its format, for writing a changed
+    /// one back the same way.
+    fn read_with_format(&self) -> Result<(Vec<u8>, DataFormat)> {
+        let data = read_input(&self.input)?;
+        let format = self.format.unwrap_or_else(|| DataFormat::detect(&data));
+        let bytes = format
+            .parse(&data)
+            .map_err(|e| format!("{}: {e}", self.input))?;
+        Ok((bytes, format))
+    }
+}
+
+impl BitstreamOutput {
+    fn write(&self, bytes: &[u8]) -> Result<()> {
+        write_output(
+            self.output.as_deref(),
+            &self.format.format(bytes, &self.name),
+        )
+    }
+}
+
+/// Read a WAV file as mono samples, mixing down any other channels.
+fn read_wav(filename: &str) -> Result<Vec<f64>> {
+    let data = read_input(filename)?;
+    let reader = hound::WavReader::new(data.as_slice()).map_err(|e| format!("{filename}: {e}"))?;
+    let spec = reader.spec();
+    if spec.sample_rate != SAMPLE_RATE {
+        eprintln!("Warning: sample rate is not {SAMPLE_RATE}");
+    }
+    let samples: Vec<f64> = match spec.sample_format {
+        hound::SampleFormat::Int => {
+            let scale = (1 << 15) as f64 / (1u32 << (spec.bits_per_sample - 1)) as f64;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f64 * scale))
+                .collect::<std::result::Result<_, _>>()
+        }
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .map(|s| s.map(|s| s as f64 * (1 << 15) as f64))
+            .collect::<std::result::Result<_, _>>(),
+    }
+    .map_err(|e| format!("{filename}: {e}"))?;
+    let channels = spec.channels as usize;
+    Ok(samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f64>() / channels as f64)
+        .collect())
+}
+
+/// Write a WAV file, or raw PCM to stdout for `-` or no file.
+fn write_wav(filename: Option<&str>, samples: &[i16]) -> Result<()> {
+    match filename {
+        Some(filename) if filename != "-" => {
+            let spec = hound::WavSpec {
+                channels: 1,
+                sample_rate: SAMPLE_RATE,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let err = |e: hound::Error| format!("{filename}: {e}");
+            let mut writer = hound::WavWriter::create(filename, spec).map_err(err)?;
+            for &sample in samples {
+                writer.write_sample(sample).map_err(err)?;
+            }
+            writer.finalize().map_err(err)?;
+        }
+        _ => {
+            let pcm = samples
+                .iter()
+                .flat_map(|s| s.to_le_bytes())
+                .collect::<Vec<_>>();
+            write_output(None, &pcm)?;
+        }
+    }
+    Ok(())
+}
+
+fn decode(data: &[u8], stream: &StreamArgs, tables: Tables, audio: &AudioOutput) -> Vec<i16> {
+    let mut bs = BitStream::new(data);
+    let mut speakie = Speakie::with_tables(tables);
+    speakie.set_rate(stream.rate());
+    speakie.set_smoothing(audio.smooth);
+    let mut post_filter = PostFilter::from_preset(audio.post_filter);
+    let mut samples = vec![];
+    loop {
+        let stop = if stream.hq {
+            speakie.process_hq_frame(&mut bs)
+        } else {
+            speakie.process_frame(&mut bs)
+        };
+        if stop || bs.is_overrun() {
+            break;
+        }
+        for _ in 0..speakie.frame_len() {
+            samples.push(post_filter.process(speakie.get_sample()));
+        }
+    }
+    // Ring down after the stop frame.
+    while audio.smooth && !speakie.is_finished() {
+        samples.push(post_filter.process(speakie.get_sample()));
+    }
+    samples
+}
+
+/// One frame of a bitstream, in either format.
+struct FrameInfo {
+    bit_pos: usize,
+    bit_len: usize,
+    /// The frame's length in samples, zero for the stop frame.
+    len: usize,
+    silence: bool,
+    stop: bool,
+    repeat: bool,
+    voiced: bool,
+    params: Params,
+}
+
+/// Read the frames of a bitstream up to and including the stop frame.
+fn read_frames(data: &[u8], stream: &StreamArgs, tables: Tables) -> Vec<FrameInfo> {
+    let layout = tables.layout();
+    let rate_bits = if stream.variable_rate { 2 } else { 0 };
+    let mut bs = BitStream::new(data);
+    let mut speakie = Speakie::with_tables(tables);
+    speakie.set_rate(stream.rate());
+    let mut frames = vec![];
+    loop {
+        let bit_pos = bs.bit_pos();
+        // Parse the frame again to see its fields, past any rate code.
+        let mut peek = BitStream::new(data);
+        peek.seek(bit_pos + rate_bits);
+        let mut info = if stream.hq {
+            let frame = HqFrame::read(&mut peek);
+            let stop = speakie.process_hq_frame(&mut bs);
+            FrameInfo {
+                bit_pos,
+                bit_len: rate_bits + frame.bit_len(),
+                len: 0,
+                silence: frame.is_silence(),
+                stop,
+                repeat: frame.repeat,
+                voiced: frame.is_voiced(),
+                params: speakie.frame_params(),
+            }
+        } else {
+            let frame = Frame::read(&mut peek, &layout);
+            let stop = speakie.process_frame(&mut bs);
+            FrameInfo {
+                bit_pos,
+                bit_len: rate_bits + frame.bit_len(&layout),
+                len: 0,
+                silence: frame.is_silence(),
+                stop,
+                repeat: frame.repeat,
+                voiced: frame.is_voiced(),
+                params: speakie.frame_params(),
+            }
+        };
+        if bs.is_overrun() {
+            break;
+        }
+        if !info.stop {
+            info.len = speakie.frame_len();
+        }
+        let stop = info.stop;
+        frames.push(info);
+        if stop {
+            break;
+        }
+    }
+    frames
+}
+
+fn info(
+    out: &mut impl Write,
+    data: &[u8],
+    stream: &StreamArgs,
+    tables: Tables,
+) -> std::io::Result<()> {
+    let frames = read_frames(data, stream, tables);
+    let count = |f: fn(&FrameInfo) -> bool| frames.iter().filter(|i| f(i)).count();
+    let n_samples: usize = frames.iter().map(|i| i.len).sum();
+    let n_bits = frames.last().map_or(0, |i| i.bit_pos + i.bit_len);
+    let seconds = n_samples as f64 / SAMPLE_RATE as f64;
+    writeln!(
+        out,
+        "frames:   {} ({} voiced, {} unvoiced, {} silence, {} repeat)",
+        count(|i| !i.stop),
+        count(|i| !i.silence && !i.stop && i.voiced),
+        count(|i| !i.silence && !i.stop && !i.voiced),
+        count(|i| i.silence),
+        count(|i| !i.silence && !i.stop && i.repeat),
+    )?;
+    match frames.last() {
+        Some(last) if last.stop => writeln!(out, "stop:     at bit {}", last.bit_pos)?,
+        _ => writeln!(out, "stop:     missing, the data ends mid-frame")?,
+    }
+    writeln!(
+        out,
+        "size:     {n_bits} bits of {} bytes, {} bytes after the end",
+        data.len(),
+        data.len().saturating_sub(n_bits.div_ceil(8))
+    )?;
+    writeln!(out, "duration: {seconds:.3}s")?;
+    if seconds > 0.0 {
+        writeln!(out, "bitrate:  {:.0} bits/s", n_bits as f64 / seconds)?;
+    }
+    Ok(())
+}
+
+fn dump(
+    out: &mut impl Write,
+    data: &[u8],
+    stream: &StreamArgs,
+    tables: Tables,
+) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "frame    bit  time(s) type      energy  period  k1..k10"
+    )?;
+    let mut n_samples = 0;
+    for (i, info) in read_frames(data, stream, tables).iter().enumerate() {
+        let time = n_samples as f64 / SAMPLE_RATE as f64;
+        n_samples += info.len;
+        let kind = if info.stop {
+            "stop"
+        } else if info.silence {
+            "silence"
+        } else if info.repeat {
+            "repeat"
+        } else if info.voiced {
+            "voiced"
+        } else {
+            "unvoiced"
+        };
+        let mut line = format!("{i:5} {:6} {time:8.3} {kind:8}", info.bit_pos);
+        if !info.silence && !info.stop {
+            let p = &info.params;
+            line += &format!(" {:7} {:7.2} ", p.energy, p.period as f64 / 16.0);
+            let k = p.k.map(|k| format!("{:.3}", k as f64 / 512.0));
+            line += &k.join(" ");
+        }
+        if stream.variable_rate && !info.stop {
+            line += &format!("  ({} samples)", info.len);
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+fn print_diff(
+    out: &mut impl Write,
+    diff: &Diff,
+    frame_len: usize,
+    threshold: f64,
+    n_regions: usize,
+    all: bool,
+) -> std::io::Result<()> {
+    let index = |i: Option<usize>| i.map_or("-".to_string(), |i| i.to_string());
+    let time = |i: usize| (i * frame_len) as f64 / SAMPLE_RATE as f64;
+    writeln!(out, "    a     b  spectral  energy  changes")?;
+    for step in &diff.steps {
+        if !all && !step.is_changed() {
+            continue;
+        }
+        let changes = match (step.a, step.b) {
+            (Some(_), None) => "only in a".to_string(),
+            (None, Some(_)) => "only in b".to_string(),
+            _ => step
+                .fields
+                .iter()
+                .map(|f| format!("{} {}>{}", f.field, f.a, f.b))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let mut line = format!("{:>5} {:>5}", index(step.a), index(step.b));
+        if !step.is_gap() {
+            line += &format!(" {:7.1}dB {:5.1}dB", step.spectral_db, step.energy_db);
+        } else {
+            line += &" ".repeat(19);
+        }
+        writeln!(out, "{line}  {changes}")?;
+    }
+    let count = |f: fn(&FrameDiff) -> bool| diff.steps.iter().filter(|s| f(s)).count();
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{} aligned frames, {} changed, {} only in a, {} only in b, mean distance {:.1}dB",
+        count(|s| !s.is_gap()),
+        count(|s| !s.is_gap() && s.is_changed()),
+        count(|s| s.b.is_none()),
+        count(|s| s.a.is_none()),
+        diff.mean_distance()
+    )?;
+    let regions = diff.regions(threshold);
+    if regions.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "biggest changes:")?;
+    for region in regions.iter().take(n_regions) {
+        writeln!(
+            out,
+            "  a frames {}..{} ({:.3}s), b frames {}..{} ({:.3}s): total {:.1}dB",
+            region.a.start,
+            region.a.end,
+            time(region.a.start),
+            region.b.start,
+            region.b.end,
+            time(region.b.start),
+            region.total
+        )?;
+    }
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::Decode {
+            input,
+            stream,
+            audio,
+        } => {
+            let data = input.read()?;
+            let table_buf = stream.load_tables()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            write_wav(
+                audio.output.as_deref(),
+                &decode(&data, &stream, tables, &audio),
+            )
+        }
+        Command::Encode {
+            input,
+            stream,
+            output,
+        } => {
+            let samples = read_wav(&input)?;
+            let table_buf = stream.load_tables()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            let bytes = to_lpc(&samples, stream.hq, tables, stream.rate());
+            output.write(&bytes)
+        }
+        Command::Info { input, stream } => {
+            let data = input.read()?;
+            let table_buf = stream.load_tables()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            Ok(info(&mut std::io::stdout().lock(), &data, &stream, tables)?)
+        }
+        Command::Dump { input, stream } => {
+            let data = input.read()?;
+            let table_buf = stream.load_tables()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            Ok(dump(&mut std::io::stdout().lock(), &data, &stream, tables)?)
+        }
+        Command::Stats {
+            input,
+            stream,
+            json,
+        } => {
+            if stream.hq {
+                return Err("stats needs TMS5220 frames, not --hq".into());
+            }
+            let data = input.read()?;
+            let table_buf = stream.load_tables()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            let stats = Stats::analyze(&mut BitStream::new(&data), tables, stream.rate());
+            let report = if json {
+                stats.to_json()
+            } else {
+                stats.to_text()
+            };
+            write_output(None, report.as_bytes())
+        }
+        Command::Optimize {
+            input,
+            stream,
+            output,
+            name,
+        } => {
+            if stream.hq {
+                return Err("optimize needs TMS5220 frames, not --hq".into());
+            }
+            let (data, format) = input.read_with_format()?;
+            let table_buf = stream.load_tables()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            let optimized = optimize(&data, tables, stream.rate())?;
+            eprintln!(
+                "{} -> {} bits, {} new repeat frames, {} -> {} bytes",
+                optimized.bits_before,
+                optimized.bits_after,
+                optimized.new_repeats,
+                data.len(),
+                optimized.bytes.len()
+            );
+            write_output(output.as_deref(), &format.format(&optimized.bytes, &name))
+        }
+        Command::Diff {
+            a,
+            b,
+            format,
+            stream,
+            threshold,
+            regions,
+            all,
+        } => {
+            if stream.hq || stream.variable_rate {
+                return Err("diff needs TMS5220 frames at a fixed rate".into());
+            }
+            let (a, b) = (read_bitstream(&a, format)?, read_bitstream(&b, format)?);
+            let table_buf = stream.load_tables()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            let (a, b) = (Utterance::parse(&a, tables), Utterance::parse(&b, tables));
+            let diff = Diff::new(a.frames(), b.frames(), &tables);
+            let frame_len = stream.rate().frame_len();
+            let mut out = std::io::stdout().lock();
+            Ok(print_diff(
+                &mut out, &diff, frame_len, threshold, regions, all,
+            )?)
+        }
+        Command::Roundtrip {
+            input,
+            stream,
+            audio,
+            bitstream,
+        } => {
+            let samples = read_wav(&input)?;
+            let table_buf = stream.load_tables()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            let bytes = to_lpc(&samples, stream.hq, tables, stream.rate());
+            if let Some(filename) = &bitstream {
+                write_output(Some(filename), &DataFormat::Hex.format(&bytes, ""))?;
+            }
+            eprintln!(
+                "{} bytes, {:.0} bits/s",
+                bytes.len(),
+                bytes.len() as f64 * 8.0 * SAMPLE_RATE as f64 / samples.len().max(1) as f64
+            );
+            write_wav(
+                audio.output.as_deref(),
+                &decode(&bytes, &stream, tables, &audio),
+            )
+        }
+        Command::Asm {
+            input,
+            tables,
+            variable_rate,
+            output,
+        } => {
+            let text = String::from_utf8(read_input(&input)?)
+                .map_err(|_| format!("{input}: not UTF-8 text"))?;
+            let table_buf = tables.load()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            let rate = FrameRate {
+                code: 0,
+                variable: variable_rate,
+            };
+            let assembly =
+                asm::assemble(&text, tables, rate).map_err(|e| format!("{input}: {e}"))?;
+            for label in &assembly.labels {
+                eprintln!(
+                    "{}: frame {}, bit {}",
+                    label.name, label.frame, label.bit_pos
+                );
+            }
+            output.write(&assembly.bytes)
+        }
+        Command::Disasm {
+            input,
+            tables,
+            variable_rate,
+            output,
+        } => {
+            let data = input.read()?;
+            let table_buf = tables.load()?;
+            let tables = table_buf.as_ref().map_or(tables::TMS5220, TableBuf::tables);
+            let rate = FrameRate {
+                code: 0,
+                variable: variable_rate,
+            };
+            let text = asm::disassemble(&data, tables, rate);
+            write_output(output.as_deref(), text.as_bytes())
+        }
+        Command::Tts(args) => compose::tts(args),
+        Command::FormantSynth(args) => compose::formant_synth(args),
+        Command::Script(args) => compose::script(args),
+        Command::Sing(args) => compose::sing(args),
+        Command::Intonation(args) => compose::intonation(args),
+    }
+}
+
+fn main() -> ExitCode {
+    // Clap exits with 2 for bad usage.
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        // Stop quietly when piped into `head` and the like.
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("speakie: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
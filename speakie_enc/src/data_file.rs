@@ -0,0 +1,154 @@
+//! Reading and writing bitstreams as files.
+//!
+//! Speech data turns up in three forms: hex dumps like the encoder prints,
+//! with or without brackets and `0x`; raw binary; and C arrays pasted from
+//! firmware, such as `const uint8_t spHELLO[] PROGMEM = {0xa5, 0x4f, ...};`.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    Hex,
+    Binary,
+    C,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataError {
+    /// A word of a hex dump or C array isn't a byte.
+    BadByte(String),
+    /// A C array has no braces.
+    NoArray,
+}
+
+impl std::fmt::Display for DataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::BadByte(word) => write!(f, "bad byte {word:?}"),
+            Self::NoArray => write!(f, "no array initializer found"),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+impl DataFormat {
+    /// Look up a format by name: hex, bin or c.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hex" => Some(Self::Hex),
+            "bin" => Some(Self::Binary),
+            "c" => Some(Self::C),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of file contents: text with braces is a C array,
+    /// other text is hex, and anything else is binary.
+    pub fn detect(data: &[u8]) -> Self {
+        let is_text = !data.is_empty()
+            && data
+                .iter()
+                .all(|&b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+        if !is_text {
+            Self::Binary
+        } else if data.contains(&b'{') {
+            Self::C
+        } else {
+            Self::Hex
+        }
+    }
+
+    /// Parse file contents into a bitstream.
+    pub fn parse(self, data: &[u8]) -> Result<Vec<u8>, DataError> {
+        match self {
+            Self::Binary => Ok(data.to_vec()),
+            Self::Hex => parse_hex(&String::from_utf8_lossy(data)),
+            Self::C => parse_c(&String::from_utf8_lossy(data)),
+        }
+    }
+
+    /// Format a bitstream as file contents. `name` is the array name for C.
+    pub fn format(self, bytes: &[u8], name: &str) -> Vec<u8> {
+        match self {
+            Self::Binary => bytes.to_vec(),
+            Self::Hex => {
+                let mut text = "[".to_string();
+                for byte in bytes {
+                    text += &format!("0x{byte:02x}, ");
+                }
+                text += "]\n";
+                text.into_bytes()
+            }
+            Self::C => {
+                let mut text = format!("const unsigned char {name}[] = {{\n");
+                for line in bytes.chunks(12) {
+                    text += "   ";
+                    for byte in line {
+                        text += &format!(" 0x{byte:02x},");
+                    }
+                    text.push('\n');
+                }
+                text += "};\n";
+                text.into_bytes()
+            }
+        }
+    }
+}
+
+/// Parse a hex dump, where every word is a byte in hex.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, DataError> {
+    text.replace(['[', ']', ','], " ")
+        .split_ascii_whitespace()
+        .map(|word| {
+            u8::from_str_radix(word.trim_start_matches("0x"), 16)
+                .map_err(|_| DataError::BadByte(word.to_string()))
+        })
+        .collect()
+}
+
+/// Parse the first brace initializer of a C source, with bytes in hex,
+/// decimal or octal.
+pub fn parse_c(text: &str) -> Result<Vec<u8>, DataError> {
+    let text = strip_comments(text);
+    let start = text.find('{').ok_or(DataError::NoArray)?;
+    let end = start + text[start..].find('}').ok_or(DataError::NoArray)?;
+    text[start + 1..end]
+        .split(',')
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let bad = || DataError::BadByte(word.to_string());
+            let value =
+                if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+                    u32::from_str_radix(hex, 16)
+                } else if word.len() > 1
+                    && let Some(oct) = word.strip_prefix('0')
+                {
+                    u32::from_str_radix(oct, 8)
+                } else {
+                    word.parse()
+                };
+            value
+                .ok()
+                .and_then(|v| u8::try_from(v).ok())
+                .ok_or_else(bad)
+        })
+        .collect()
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("//") {
+            rest = after.find('\n').map_or("", |i| &after[i..]);
+        } else if let Some(after) = rest.strip_prefix("/*") {
+            rest = after.find("*/").map_or("", |i| &after[i + 2..]);
+            out.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
@@ -0,0 +1,439 @@
+//! Frame-by-frame comparison of two bitstreams, such as the same content
+//! encoded with different settings.
+//!
+//! Frames are aligned with an edit distance, so a frame only in one stream
+//! shows up as a gap instead of shifting every frame after it. Aligned
+//! frames are compared by their codes, by the log spectral distance between
+//! their all-pole envelopes, and by their energy in dB.
+
+use std::ops::Range;
+
+use speakie::{Frame, tables::Tables};
+
+use crate::spectral::AllPole;
+
+/// The cost of a frame that is only in one stream, in dB. Aligned frames
+/// further apart than twice this are treated as a deletion and an insertion.
+pub const GAP_COST: f64 = 10.0;
+
+/// Frequencies for the spectral distance, from 0 to Nyquist.
+const N_FREQS: usize = 32;
+
+const FIELD_NAMES: [&str; 12] = [
+    "energy", "period", "k1", "k2", "k3", "k4", "k5", "k6", "k7", "k8", "k9", "k10",
+];
+
+/// One code that differs between aligned frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldDiff {
+    /// `energy`, `period`, or `k1` to `k10`.
+    pub field: &'static str,
+    pub a: u8,
+    pub b: u8,
+}
+
+/// One step of the alignment: a pair of frames, or a frame in only one of
+/// the streams.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameDiff {
+    /// The frame index in the first stream, if any.
+    pub a: Option<usize>,
+    /// The frame index in the second stream, if any.
+    pub b: Option<usize>,
+    /// The codes that differ, for aligned frames.
+    pub fields: Vec<FieldDiff>,
+    /// RMS difference of the spectral envelopes, in dB.
+    pub spectral_db: f64,
+    /// Difference in energy, in dB.
+    pub energy_db: f64,
+}
+
+impl FrameDiff {
+    pub fn is_gap(&self) -> bool {
+        self.a.is_none() || self.b.is_none()
+    }
+
+    /// Whether anything differs.
+    pub fn is_changed(&self) -> bool {
+        self.is_gap() || !self.fields.is_empty()
+    }
+
+    /// How far apart the frames are: the spectral and energy differences
+    /// combined, or [`GAP_COST`] for a gap.
+    pub fn distance(&self) -> f64 {
+        if self.is_gap() {
+            GAP_COST
+        } else {
+            self.spectral_db.hypot(self.energy_db)
+        }
+    }
+}
+
+/// A run of changed steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    /// Steps of [`Diff::steps`].
+    pub steps: Range<usize>,
+    /// Frames of the first stream.
+    pub a: Range<usize>,
+    /// Frames of the second stream.
+    pub b: Range<usize>,
+    /// The sum of the distances of the steps.
+    pub total: f64,
+}
+
+/// The alignment of two streams.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diff {
+    pub steps: Vec<FrameDiff>,
+}
+
+/// A frame decoded for comparison.
+struct Decoded {
+    codes: [u8; 12],
+    silence: bool,
+    energy_db: f64,
+    spectrum: Vec<f64>,
+}
+
+/// Decode the frames of a stream, taking the coefficients of any repeat
+/// frames left from the frame they repeat.
+fn decode_all(frames: &[Frame], tables: &Tables) -> Vec<Decoded> {
+    // The last frame with coefficients, as the decoder starts from zero.
+    let mut held = Frame::default();
+    frames
+        .iter()
+        .map(|frame| {
+            if frame.repeat {
+                return decode(frame, &held, tables);
+            }
+            if !frame.is_silence() && !frame.is_stop() {
+                held = *frame;
+            }
+            decode(frame, frame, tables)
+        })
+        .collect()
+}
+
+/// Decode a frame, with coefficients from `coefs`.
+fn decode(frame: &Frame, coefs: &Frame, tables: &Tables) -> Decoded {
+    let mut codes = [0; 12];
+    codes[0] = frame.energy;
+    let silence = frame.is_silence() || frame.is_stop();
+    if !silence {
+        codes[1] = frame.period;
+        codes[2..2 + coefs.n_k()].copy_from_slice(&coefs.k[..coefs.n_k()]);
+    }
+    // Silence compares as half the quietest sound.
+    let floor = (tables.energy[1] as f64 / 2.0).max(1.0);
+    let energy = if silence {
+        floor
+    } else {
+        (tables.energy[frame.energy as usize] as f64).max(floor)
+    };
+    let mut k = [0; 10];
+    if !silence {
+        for (i, k) in k.iter_mut().enumerate().take(coefs.n_k()) {
+            *k = tables.k[i][coefs.k[i] as usize];
+        }
+    }
+    let spectrum = AllPole::from_frame_k(&k)
+        .spectrum(N_FREQS)
+        .into_iter()
+        .map(|(_, db)| db)
+        .collect();
+    Decoded {
+        codes,
+        silence,
+        energy_db: 20.0 * energy.log10(),
+        spectrum,
+    }
+}
+
+/// The RMS difference of the spectral envelopes, in dB. The envelope of
+/// silence doesn't matter.
+fn spectral_db(a: &Decoded, b: &Decoded) -> f64 {
+    if a.silence || b.silence {
+        return 0.0;
+    }
+    let sum = a
+        .spectrum
+        .iter()
+        .zip(&b.spectrum)
+        .map(|(x, y)| (x - y).powi(2))
+        .sum::<f64>();
+    (sum / N_FREQS as f64).sqrt()
+}
+
+/// [`FrameDiff::distance`] of aligned frames, without listing the codes.
+fn distance(a: &Decoded, b: &Decoded) -> f64 {
+    spectral_db(a, b).hypot(b.energy_db - a.energy_db)
+}
+
+fn compare(a: &Decoded, b: &Decoded, i: usize, j: usize) -> FrameDiff {
+    let fields = (0..12)
+        .filter(|&f| a.codes[f] != b.codes[f])
+        .map(|f| FieldDiff {
+            field: FIELD_NAMES[f],
+            a: a.codes[f],
+            b: b.codes[f],
+        })
+        .collect();
+    FrameDiff {
+        a: Some(i),
+        b: Some(j),
+        fields,
+        spectral_db: spectral_db(a, b),
+        energy_db: (b.energy_db - a.energy_db).abs(),
+    }
+}
+
+/// A step of the alignment, before it's compared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Move {
+    Pair,
+    /// A frame only in the first stream.
+    Delete,
+    /// A frame only in the second stream.
+    Insert,
+}
+
+/// The move that reached each cell of the alignment, packed four to a byte.
+struct Moves {
+    m: usize,
+    bits: Vec<u8>,
+}
+
+impl Moves {
+    fn new(n: usize, m: usize) -> Self {
+        Self {
+            m,
+            bits: vec![0; (n * m).div_ceil(4)],
+        }
+    }
+
+    /// The index of cell (i, j), for i and j from 1.
+    fn index(&self, i: usize, j: usize) -> (usize, u32) {
+        let cell = (i - 1) * self.m + (j - 1);
+        (cell / 4, cell as u32 % 4 * 2)
+    }
+
+    fn set(&mut self, i: usize, j: usize, step: Move) {
+        let (byte, shift) = self.index(i, j);
+        self.bits[byte] |= (step as u8) << shift;
+    }
+
+    /// The move into cell (i, j). The first row and column can only be
+    /// reached by gaps.
+    fn get(&self, i: usize, j: usize) -> Move {
+        if j == 0 {
+            return Move::Delete;
+        }
+        if i == 0 {
+            return Move::Insert;
+        }
+        let (byte, shift) = self.index(i, j);
+        match self.bits[byte] >> shift & 3 {
+            0 => Move::Pair,
+            1 => Move::Delete,
+            _ => Move::Insert,
+        }
+    }
+}
+
+fn gap(a: Option<usize>, b: Option<usize>) -> FrameDiff {
+    FrameDiff {
+        a,
+        b,
+        fields: vec![],
+        spectral_db: 0.0,
+        energy_db: 0.0,
+    }
+}
+
+impl Diff {
+    /// Align two streams of frames, such as from
+    /// [`Utterance::frames`](crate::edit::Utterance::frames).
+    pub fn new(a: &[Frame], b: &[Frame], tables: &Tables) -> Self {
+        let (da, db) = (decode_all(a, tables), decode_all(b, tables));
+        let (n, m) = (a.len(), b.len());
+        // Only two rows of costs are kept, where row[j] aligns the first i
+        // frames of a with the first j of b. Each cell keeps the step that
+        // reached it instead, in two bits, to backtrack from the end.
+        let mut prev = (0..=m).map(|j| j as f64 * GAP_COST).collect::<Vec<_>>();
+        let mut row = vec![0.0; m + 1];
+        let mut moves = Moves::new(n, m);
+        for i in 1..=n {
+            row[0] = i as f64 * GAP_COST;
+            for j in 1..=m {
+                let pair = prev[j - 1] + distance(&da[i - 1], &db[j - 1]);
+                let (del, ins) = (prev[j] + GAP_COST, row[j - 1] + GAP_COST);
+                // Ties prefer aligning the frames, then a deletion.
+                let (cost, step) = if pair <= del && pair <= ins {
+                    (pair, Move::Pair)
+                } else if del <= ins {
+                    (del, Move::Delete)
+                } else {
+                    (ins, Move::Insert)
+                };
+                row[j] = cost;
+                moves.set(i, j, step);
+            }
+            std::mem::swap(&mut prev, &mut row);
+        }
+        let mut steps = vec![];
+        let (mut i, mut j) = (n, m);
+        while i > 0 || j > 0 {
+            match moves.get(i, j) {
+                Move::Pair => {
+                    steps.push(compare(&da[i - 1], &db[j - 1], i - 1, j - 1));
+                    i -= 1;
+                    j -= 1;
+                }
+                Move::Delete => {
+                    steps.push(gap(Some(i - 1), None));
+                    i -= 1;
+                }
+                Move::Insert => {
+                    steps.push(gap(None, Some(j - 1)));
+                    j -= 1;
+                }
+            }
+        }
+        steps.reverse();
+        Self { steps }
+    }
+
+    /// Whether the streams have the same codes.
+    pub fn is_identical(&self) -> bool {
+        !self.steps.iter().any(FrameDiff::is_changed)
+    }
+
+    /// The mean distance over all steps.
+    pub fn mean_distance(&self) -> f64 {
+        let total = self.steps.iter().map(FrameDiff::distance).sum::<f64>();
+        total / self.steps.len().max(1) as f64
+    }
+
+    /// Runs of steps further apart than `threshold` dB, biggest first.
+    pub fn regions(&self, threshold: f64) -> Vec<Region> {
+        let mut regions: Vec<Region> = vec![];
+        // The next frame of each stream after a step.
+        let (mut next_a, mut next_b) = (0, 0);
+        let mut open = false;
+        for (i, step) in self.steps.iter().enumerate() {
+            let (start_a, start_b) = (next_a, next_b);
+            next_a = step.a.map_or(next_a, |a| a + 1);
+            next_b = step.b.map_or(next_b, |b| b + 1);
+            if step.distance() <= threshold {
+                open = false;
+                continue;
+            }
+            if open {
+                let region = regions.last_mut().unwrap();
+                region.steps.end = i + 1;
+                region.a.end = next_a;
+                region.b.end = next_b;
+                region.total += step.distance();
+            } else {
+                regions.push(Region {
+                    steps: i..i + 1,
+                    a: start_a..next_a,
+                    b: start_b..next_b,
+                    total: step.distance(),
+                });
+                open = true;
+            }
+        }
+        regions.sort_by(|x, y| y.total.total_cmp(&x.total));
+        regions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speakie::tables;
+
+    use super::*;
+
+    fn voiced(energy: u8, k1: u8) -> Frame {
+        Frame {
+            energy,
+            repeat: false,
+            period: 40,
+            k: [k1, 20, 8, 8, 8, 8, 8, 4, 4, 4],
+        }
+    }
+
+    fn frames() -> Vec<Frame> {
+        (0..20).map(|i| voiced(4 + i % 8, 8 + i % 16)).collect()
+    }
+
+    #[test]
+    fn identical() {
+        let a = frames();
+        let diff = Diff::new(&a, &a, &tables::TMS5220);
+        assert_eq!(diff.steps.len(), a.len());
+        assert!(diff.is_identical());
+        assert_eq!(diff.mean_distance(), 0.0);
+        assert!(diff.regions(0.0).is_empty());
+    }
+
+    #[test]
+    fn inserted_frame_is_one_gap() {
+        let a = frames();
+        let mut b = a.clone();
+        b.insert(7, Frame::silence());
+        let diff = Diff::new(&a, &b, &tables::TMS5220);
+        let gaps = diff
+            .steps
+            .iter()
+            .filter(|step| step.is_gap())
+            .collect::<Vec<_>>();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!((gaps[0].a, gaps[0].b), (None, Some(7)));
+        assert!(
+            diff.steps
+                .iter()
+                .filter(|step| !step.is_gap())
+                .all(|step| !step.is_changed() && step.b.unwrap() - step.a.unwrap() <= 1)
+        );
+        let regions = diff.regions(1.0);
+        assert_eq!(regions.len(), 1);
+        assert_eq!((regions[0].a.clone(), regions[0].b.clone()), (7..7, 7..8));
+    }
+
+    #[test]
+    fn deleted_and_changed_frames() {
+        let a = frames();
+        let mut b = a.clone();
+        b.remove(0);
+        b[10].k[0] += 1;
+        let diff = Diff::new(&a, &b, &tables::TMS5220);
+        assert_eq!((diff.steps[0].a, diff.steps[0].b), (Some(0), None));
+        let changed = diff
+            .steps
+            .iter()
+            .filter(|step| step.is_changed())
+            .collect::<Vec<_>>();
+        assert_eq!(changed.len(), 2);
+        assert_eq!((changed[1].a, changed[1].b), (Some(11), Some(10)));
+        assert_eq!(
+            changed[1].fields,
+            [FieldDiff {
+                field: "k1",
+                a: a[11].k[0],
+                b: b[10].k[0],
+            }]
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let a = frames();
+        let diff = Diff::new(&a, &[], &tables::TMS5220);
+        assert_eq!(diff.steps.len(), a.len());
+        assert!(diff.steps.iter().all(|step| step.b.is_none()));
+        assert!(Diff::new(&[], &[], &tables::TMS5220).steps.is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+//! Frame-level editing of bitstreams.
+//!
+//! An [`Utterance`] holds the frames of a bitstream up to its stop frame, and
+//! edits them in place: trimming, splicing, inserting silence, reversing,
+//! and shifting energy and period codes. Edits work on quantized codes, so
+//! nothing is re-encoded and untouched frames come out as they went in.
+//!
+//! Repeat frames depend on the frame before them, so they're resolved to
+//! explicit coefficients when parsing, and re-enabled wherever the
+//! coefficients are unchanged when serializing. The few repeats that no
+//! other frame can stand in for stay repeats, and take the coefficients of
+//! whatever comes before them after an edit.
+
+use std::ops::Range;
+
+use speakie::{BitStream, Frame, tables::Tables};
+
+use crate::output::Output;
+
+/// The frames of an utterance, without the stop frame.
+#[derive(Clone, Debug)]
+pub struct Utterance<'a> {
+    tables: Tables<'a>,
+    frames: Vec<Frame>,
+}
+
+/// The reflection coefficients the decoder holds after a sounding frame
+/// that isn't a repeat, scaled by 512.
+fn decoded_k(frame: &Frame, tables: &Tables) -> [i16; 10] {
+    let mut k = [0; 10];
+    for (i, k) in k.iter_mut().enumerate().take(frame.n_k()) {
+        *k = tables.k[i][frame.k[i] as usize];
+    }
+    k
+}
+
+/// Replace repeat frames with the coefficients they repeat, so that frames
+/// can be reordered, dropped or duplicated.
+///
+/// A repeat keeps all ten coefficients the decoder holds, which a frame of
+/// the other voicing can't always code: an unvoiced frame zeroes K5 to K10,
+/// and few tables have a code for zero. Repeats whose voicing differs from
+/// the frame they repeat are kept, as is a leading repeat, which repeats
+/// whatever the decoder held before.
+pub fn resolve_repeats(frames: &mut [Frame]) {
+    // The codes of the last frame that wasn't a repeat, and whether it was
+    // voiced.
+    let mut held = None;
+    for frame in frames {
+        if frame.is_silence() || frame.is_stop() {
+            continue;
+        }
+        if !frame.repeat {
+            held = Some((frame.k, frame.is_voiced()));
+        } else if let Some((k, voiced)) = held
+            && voiced == frame.is_voiced()
+        {
+            frame.repeat = false;
+            frame.k = k;
+        }
+    }
+}
+
+/// Turn frames into repeat frames wherever they decode to the coefficients
+/// the decoder already holds. Only frames after a sounding frame become
+/// repeats, so the result doesn't depend on what played before it, unless
+/// it starts with a repeat already.
+pub fn compress_repeats(frames: &mut [Frame], tables: &Tables) {
+    let mut held = None;
+    for frame in frames {
+        if frame.is_silence() || frame.is_stop() || frame.repeat {
+            continue;
+        }
+        let k = decoded_k(frame, tables);
+        if held == Some(k) {
+            frame.repeat = true;
+            frame.k = [0; 10];
+        } else {
+            held = Some(k);
+        }
+    }
+}
+
+impl<'a> Utterance<'a> {
+    pub fn new(tables: Tables<'a>) -> Self {
+        Self {
+            tables,
+            frames: vec![],
+        }
+    }
+
+    /// Parse a bitstream up to its stop frame, or its end.
+    pub fn parse(data: &[u8], tables: Tables<'a>) -> Self {
+        let layout = tables.layout();
+        let mut bs = BitStream::new(data);
+        let mut frames = vec![];
+        loop {
+            let frame = Frame::read(&mut bs, &layout);
+            if bs.is_overrun() || frame.is_stop() {
+                break;
+            }
+            frames.push(frame);
+        }
+        resolve_repeats(&mut frames);
+        Self { tables, frames }
+    }
+
+    /// The frames, with repeats resolved.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Keep only the frames in `range`.
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn trim(&mut self, range: Range<usize>) {
+        self.frames.truncate(range.end);
+        self.frames.drain(..range.start);
+    }
+
+    /// Remove silence frames from the start and end.
+    pub fn trim_silence(&mut self) {
+        let start = self.frames.iter().position(|f| !f.is_silence());
+        let end = self.frames.iter().rposition(|f| !f.is_silence());
+        match (start, end) {
+            (Some(start), Some(end)) => self.trim(start..end + 1),
+            _ => self.frames.clear(),
+        }
+    }
+
+    /// Insert the frames of another utterance before frame `at`.
+    ///
+    /// Panics if `at` is past the end.
+    pub fn splice(&mut self, at: usize, other: &Utterance) {
+        self.frames.splice(at..at, other.frames.iter().copied());
+    }
+
+    /// Add the frames of another utterance to the end.
+    pub fn append(&mut self, other: &Utterance) {
+        self.frames.extend_from_slice(&other.frames);
+    }
+
+    /// Insert `n` silence frames before frame `at`.
+    ///
+    /// Panics if `at` is past the end.
+    pub fn insert_silence(&mut self, at: usize, n: usize) {
+        self.frames
+            .splice(at..at, std::iter::repeat_n(Frame::silence(), n));
+    }
+
+    /// Play the frames backwards.
+    pub fn reverse(&mut self) {
+        self.frames.reverse();
+    }
+
+    /// Shift the energy codes of sounding frames by `steps`, of about 3dB
+    /// each, keeping them sounding.
+    pub fn scale_energy(&mut self, steps: i32) {
+        let max = self.tables.energy.len() as i32 - 2;
+        for frame in &mut self.frames {
+            if !frame.is_silence() {
+                frame.energy = (frame.energy as i32 + steps).clamp(1, max) as u8;
+            }
+        }
+    }
+
+    /// Shift the period codes of voiced frames by `steps`, keeping them
+    /// voiced. Higher codes are longer periods, so positive steps raise the
+    /// pitch by lowering the codes.
+    pub fn transpose(&mut self, steps: i32) {
+        let max = self.tables.period.len() as i32 - 1;
+        for frame in &mut self.frames {
+            if !frame.is_silence() && frame.is_voiced() {
+                frame.period = (frame.period as i32 - steps).clamp(1, max) as u8;
+            }
+        }
+    }
+
+    /// Serialize the frames with repeat frames re-enabled, ending with a stop
+    /// frame.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut frames = self.frames.clone();
+        compress_repeats(&mut frames, &self.tables);
+        let mut out = Output::new(self.tables);
+        for frame in &frames {
+            out.write_frame(frame);
+        }
+        out.write_frame(&Frame::stop());
+        out.pack(0, 7);
+        out.reap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use speakie::{Speakie, tables};
+
+    use super::*;
+    use crate::tts::{self, Inventory, Voice};
+
+    const VOICED: Frame = Frame {
+        energy: 9,
+        repeat: false,
+        period: 30,
+        k: [10, 20, 7, 8, 9, 10, 11, 4, 5, 6],
+    };
+
+    const UNVOICED: Frame = Frame {
+        energy: 8,
+        repeat: false,
+        period: 0,
+        k: [12, 18, 6, 9, 0, 0, 0, 0, 0, 0],
+    };
+
+    fn repeat(frame: Frame) -> Frame {
+        Frame {
+            repeat: true,
+            k: [0; 10],
+            ..frame
+        }
+    }
+
+    fn encode(frames: &[Frame]) -> Vec<u8> {
+        let mut out = Output::new(tables::TMS5220);
+        for frame in frames {
+            out.write_frame(frame);
+        }
+        out.write_frame(&Frame::stop());
+        out.pack(0, 7);
+        out.reap()
+    }
+
+    fn decode(data: &[u8]) -> Vec<i16> {
+        let mut bs = BitStream::new(data);
+        let mut speakie = Speakie::new();
+        let mut samples = vec![];
+        while !speakie.process_frame(&mut bs) && !bs.is_overrun() {
+            samples.extend((0..speakie.frame_len()).map(|_| speakie.get_sample()));
+        }
+        samples
+    }
+
+    fn assert_round_trip(data: &[u8]) {
+        let bytes = Utterance::parse(data, tables::TMS5220).to_bytes();
+        assert_eq!(decode(&bytes), decode(data));
+    }
+
+    #[test]
+    fn round_trip_speech() {
+        let t = tables::TMS5220;
+        let data =
+            tts::speak("Hello there.", &Inventory::builtin(t), &Voice::default(), t).unwrap();
+        assert_round_trip(&data);
+    }
+
+    #[test]
+    fn round_trip_repeats_across_voicing() {
+        let cases = [
+            vec![VOICED, repeat(UNVOICED), repeat(UNVOICED)],
+            vec![UNVOICED, repeat(VOICED), repeat(VOICED), VOICED],
+            vec![VOICED, repeat(UNVOICED), Frame::silence(), repeat(VOICED)],
+        ];
+        for frames in cases {
+            let u = Utterance::parse(&encode(&frames), tables::TMS5220);
+            // The repeats of the other voicing stay repeats.
+            assert!(u.frames()[1].repeat);
+            assert_round_trip(&encode(&frames));
+        }
+    }
+
+    #[test]
+    fn round_trip_leading_repeat() {
+        let frames = [repeat(VOICED), VOICED, repeat(VOICED)];
+        let u = Utterance::parse(&encode(&frames), tables::TMS5220);
+        assert!(u.frames()[0].repeat);
+        assert_eq!(u.frames()[2], VOICED);
+        assert_round_trip(&encode(&frames));
+    }
+
+    #[test]
+    fn resolves_repeats_of_the_same_voicing() {
+        let mut frames = vec![VOICED, Frame::silence(), VOICED, VOICED];
+        compress_repeats(&mut frames, &tables::TMS5220);
+        assert!(!frames[0].repeat && frames[2].repeat && frames[3].repeat);
+        resolve_repeats(&mut frames);
+        assert_eq!(frames, [VOICED, Frame::silence(), VOICED, VOICED]);
+    }
+}
@@ -0,0 +1,214 @@
+//! Bitstreams designed from formants.
+//!
+//! A timeline of keyframes gives formant frequencies and bandwidths, pitch
+//! and amplitude. Frames are interpolated between keyframes, each one's
+//! formants become the poles of an all-pole filter, and its reflection
+//! coefficients are quantized like the encoder's.
+//!
+//! The text form of a timeline has one keyframe per line:
+//!
+//! ```text
+//! # time_ms pitch_hz amplitude formant_hz/bandwidth_hz...
+//! 0    120 0    730/90 1090/110 2440/170
+//! 50   120 0.8  730/90 1090/110 2440/170
+//! 300  100 0.8  270/60 2290/100 3010/120
+//! 320  0   0.3  1500/400 3500/300
+//! ```
+//!
+//! A pitch of 0 is unvoiced. Amplitude is a fraction of the loudest energy
+//! code; it sets the excitation, so narrow formants sound louder.
+
+use std::f64::consts::PI;
+
+use speakie::{FrameRate, tables::Tables};
+
+use crate::{
+    output::Output,
+    spectral::{AllPole, Formant, SAMPLE_RATE},
+};
+
+/// A frame holds 10 reflection coefficients, so 5 pole pairs.
+pub const MAX_FORMANTS: usize = 5;
+
+/// A point on the timeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time_ms: f64,
+    /// Pitch in Hz, or 0 for unvoiced.
+    pub pitch: f64,
+    /// Energy, from 0 to 1.
+    pub amplitude: f64,
+    pub formants: Vec<Formant>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DesignError {
+    /// A line of text couldn't be parsed, counting from 1.
+    Syntax { line: usize },
+    /// A keyframe has more than [`MAX_FORMANTS`] formants.
+    TooManyFormants { keyframe: usize },
+    /// Keyframes aren't in time order.
+    OutOfOrder { keyframe: usize },
+}
+
+impl std::fmt::Display for DesignError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Syntax { line } => write!(f, "syntax error on line {line}"),
+            Self::TooManyFormants { keyframe } => {
+                write!(
+                    f,
+                    "keyframe {keyframe} has more than {MAX_FORMANTS} formants"
+                )
+            }
+            Self::OutOfOrder { keyframe } => write!(f, "keyframe {keyframe} is out of order"),
+        }
+    }
+}
+
+impl std::error::Error for DesignError {}
+
+impl Keyframe {
+    /// Interpolate between `self` and `next`, `t` from 0 to 1.
+    ///
+    /// Voicing changes at `next`. Formants missing from one side are held.
+    fn lerp(&self, next: &Self, t: f64) -> Self {
+        let mix = |a: f64, b: f64| a + (b - a) * t;
+        let pitch = if self.pitch > 0.0 && next.pitch > 0.0 {
+            mix(self.pitch, next.pitch)
+        } else {
+            self.pitch
+        };
+        let n = self.formants.len().max(next.formants.len());
+        let formants = (0..n)
+            .map(|i| match (self.formants.get(i), next.formants.get(i)) {
+                (Some(a), Some(b)) => Formant {
+                    freq: mix(a.freq, b.freq),
+                    bandwidth: mix(a.bandwidth, b.bandwidth),
+                },
+                (a, b) => *a.or(b).unwrap(),
+            })
+            .collect();
+        Self {
+            time_ms: mix(self.time_ms, next.time_ms),
+            pitch,
+            amplitude: mix(self.amplitude, next.amplitude),
+            formants,
+        }
+    }
+
+    /// The all-pole filter with the formants as its poles.
+    pub fn filter(&self) -> AllPole {
+        let mut a = vec![1.0];
+        for f in &self.formants {
+            let r = (-PI * f.bandwidth / SAMPLE_RATE).exp();
+            let theta = 2.0 * PI * f.freq / SAMPLE_RATE;
+            let section = [1.0, -2.0 * r * theta.cos(), r * r];
+            let mut product = vec![0.0; a.len() + 2];
+            for (i, x) in a.iter().enumerate() {
+                for (j, y) in section.iter().enumerate() {
+                    product[i + j] += x * y;
+                }
+            }
+            a = product;
+        }
+        // Poles inside the unit circle always give a stable filter.
+        AllPole::from_lpc(&a[1..]).unwrap_or_else(|| AllPole::from_k(&[]))
+    }
+}
+
+/// Parse the text form of a timeline.
+pub fn parse_timeline(text: &str) -> Result<Vec<Keyframe>, DesignError> {
+    let mut keyframes = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let err = DesignError::Syntax { line: i + 1 };
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split_ascii_whitespace();
+        let mut number =
+            || -> Result<f64, DesignError> { words.next().and_then(|w| w.parse().ok()).ok_or(err) };
+        let time_ms = number()?;
+        let pitch = number()?;
+        let amplitude = number()?;
+        let formants = words
+            .map(|w| {
+                let (freq, bandwidth) = w.split_once('/').ok_or(err)?;
+                Ok(Formant {
+                    freq: freq.parse().map_err(|_| err)?,
+                    bandwidth: bandwidth.parse().map_err(|_| err)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        keyframes.push(Keyframe {
+            time_ms,
+            pitch,
+            amplitude,
+            formants,
+        });
+    }
+    Ok(keyframes)
+}
+
+/// Render a timeline to a bitstream, ending with a stop frame.
+///
+/// Each frame takes the parameters of the timeline at its end, which is
+/// where the decoder's interpolation reaches them.
+pub fn synthesize(
+    keyframes: &[Keyframe],
+    tables: Tables,
+    rate: FrameRate,
+) -> Result<Vec<u8>, DesignError> {
+    for (i, k) in keyframes.iter().enumerate() {
+        if k.formants.len() > MAX_FORMANTS {
+            return Err(DesignError::TooManyFormants { keyframe: i });
+        }
+        if i > 0 && k.time_ms < keyframes[i - 1].time_ms {
+            return Err(DesignError::OutOfOrder { keyframe: i });
+        }
+    }
+    let mut out = Output::new(tables);
+    let max_energy = tables.energy[tables.energy.len() - 2] as f64;
+    let frame_ms = rate.frame_len() as f64 * 1000.0 / SAMPLE_RATE;
+    let end_ms = keyframes.last().map_or(0.0, |k| k.time_ms);
+    let n_frames = (end_ms / frame_ms).ceil() as usize;
+    for i in 0..n_frames {
+        let frame = at_time(keyframes, (i + 1) as f64 * frame_ms);
+        let mut ks = frame.filter().k().to_vec();
+        ks.resize(10, 0.0);
+        let period = if frame.pitch > 0.0 {
+            SAMPLE_RATE / frame.pitch
+        } else {
+            0.0
+        };
+        if rate.variable {
+            out.pack(rate.code.into(), 2);
+        }
+        out.frame(frame.amplitude.clamp(0.0, 1.0) * max_energy, period, &ks);
+    }
+    if rate.variable {
+        out.pack(rate.code.into(), 2);
+    }
+    out.pack(15, 4);
+    out.pack(0, 7);
+    Ok(out.reap())
+}
+
+/// The timeline's parameters at a time, held past either end.
+fn at_time(keyframes: &[Keyframe], time_ms: f64) -> Keyframe {
+    let next = keyframes.partition_point(|k| k.time_ms <= time_ms);
+    match (
+        next.checked_sub(1).map(|i| &keyframes[i]),
+        keyframes.get(next),
+    ) {
+        (Some(a), Some(b)) => a.lerp(b, (time_ms - a.time_ms) / (b.time_ms - a.time_ms)),
+        (Some(k), None) | (None, Some(k)) => k.clone(),
+        (None, None) => Keyframe {
+            time_ms,
+            pitch: 0.0,
+            amplitude: 0.0,
+            formants: vec![],
+        },
+    }
+}
@@ -0,0 +1,134 @@
+//! Lossless repacking of bitstreams.
+//!
+//! Many tools never set the repeat bit, and write every frame's
+//! coefficients even when they're unchanged. [`optimize`] rewrites such
+//! frames as repeat frames, which keep the decoder's coefficients and save
+//! the 18 to 39 bits of K codes, and drops anything after the stop frame.
+//! Silence frames are already a bare energy field, so long silences can't
+//! get any smaller.
+//!
+//! The result is checked by decoding both versions and comparing every
+//! sample, so a stream is never changed in a way that can be heard.
+
+use speakie::{BitStream, Frame, FrameRate, Speakie, tables::Tables};
+
+use crate::{edit, output::Output};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptimizeError {
+    /// The repacked stream decodes differently, from this sample on. This
+    /// is a bug.
+    Mismatch { sample: usize },
+}
+
+impl std::fmt::Display for OptimizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Mismatch { sample } => {
+                write!(f, "repacked stream differs from sample {sample}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for OptimizeError {}
+
+/// A repacked bitstream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Optimized {
+    pub bytes: Vec<u8>,
+    /// Frames that became repeat frames.
+    pub new_repeats: usize,
+    /// Bits up to and including the stop frame, or the last whole frame,
+    /// before and after.
+    pub bits_before: usize,
+    pub bits_after: usize,
+}
+
+/// Decode a bitstream up to its stop frame, or its end.
+fn decode(data: &[u8], tables: Tables, rate: FrameRate) -> Vec<i16> {
+    let mut bs = BitStream::new(data);
+    let mut speakie = Speakie::with_tables(tables);
+    speakie.set_rate(rate);
+    let mut samples = vec![];
+    while !speakie.process_frame(&mut bs) && !bs.is_overrun() {
+        samples.extend((0..speakie.frame_len()).map(|_| speakie.get_sample()));
+    }
+    samples
+}
+
+/// Repack a bitstream with repeat frames wherever the coefficients don't
+/// change, ending it at the stop frame.
+///
+/// A stream without a stop frame is kept to its last whole frame, and only
+/// gets one where it fits in the padding of the last byte.
+pub fn optimize(data: &[u8], tables: Tables, rate: FrameRate) -> Result<Optimized, OptimizeError> {
+    let layout = tables.layout();
+    let rate_bits = if rate.variable { 2 } else { 0 };
+    let mut bs = BitStream::new(data);
+    // Frames with their rate codes.
+    let mut frames = vec![];
+    let mut codes = vec![];
+    let mut bits_before = 0;
+    let mut stopped = false;
+    loop {
+        let code = if rate.variable {
+            bs.get_bits(2) as u8
+        } else {
+            rate.code
+        };
+        let frame = Frame::read(&mut bs, &layout);
+        if bs.is_overrun() {
+            break;
+        }
+        bits_before = bs.bit_pos();
+        if frame.is_stop() {
+            codes.push(code);
+            stopped = true;
+            break;
+        }
+        frames.push(frame);
+        codes.push(code);
+    }
+    let mut packed = frames.clone();
+    edit::compress_repeats(&mut packed, &tables);
+    let new_repeats = packed
+        .iter()
+        .zip(&frames)
+        .filter(|(p, f)| p.repeat && !f.repeat)
+        .count();
+    let bits =
+        |frames: &[Frame]| -> usize { frames.iter().map(|f| rate_bits + f.bit_len(&layout)).sum() };
+    // Without a stop frame, the zero bits padding the last byte would decode
+    // as another silence frame if there are enough of them, so a stop frame
+    // goes there instead.
+    let padding = bits(&packed).next_multiple_of(8) - bits(&packed);
+    if stopped || padding >= rate_bits + 4 {
+        if !stopped {
+            codes.push(codes.last().copied().unwrap_or(rate.code));
+        }
+        packed.push(Frame::stop());
+    }
+    let mut out = Output::new(tables);
+    for (frame, code) in packed.iter().zip(&codes) {
+        if rate.variable {
+            out.pack((*code).into(), 2);
+        }
+        out.write_frame(frame);
+    }
+    let bits_after = bits(&packed);
+    let bytes = out.reap();
+
+    let (before, after) = (decode(data, tables, rate), decode(&bytes, tables, rate));
+    if let Some(sample) =
+        (0..before.len().max(after.len())).find(|&i| before.get(i) != after.get(i))
+    {
+        return Err(OptimizeError::Mismatch { sample });
+    }
+    Ok(Optimized {
+        bytes,
+        new_repeats,
+        bits_before,
+        bits_after,
+    })
+}
@@ -0,0 +1,220 @@
+//! Phrase scripts: voice prompts built from encoded words.
+//!
+//! A script is a sequence of word names and bracketed directives:
+//!
+//! ```text
+//! HELLO [pause 150ms] WORLD [rate 1.2] [pitch +2] GOODBYE
+//! ```
+//!
+//! - `[pause 150ms]` inserts silence, in `ms` or `s`.
+//! - `[rate 1.2]` speaks the following words faster (above 1) or slower, by
+//!   dropping or repeating frames.
+//! - `[pitch +2]` transposes the following words by semitones, rewriting
+//!   their period codes. `[pitch 0]` restores the original pitch.
+//!
+//! `#` starts a comment to the end of the line.
+//!
+//! Words are looked up in a [`Words`] directory. Each word's frames are
+//! taken up to its stop frame, so the words join into one bitstream with a
+//! single stop frame at the end.
+
+use std::{collections::HashMap, io, path::Path};
+
+use speakie::{
+    BitStream, BitWriter, Frame, Frames,
+    intonation::{Breakpoint, Contour},
+    tables::Tables,
+};
+
+use crate::{data_file::DataFormat, edit::resolve_repeats};
+
+/// The length of a frame at the default rate, in milliseconds.
+const FRAME_MS: f64 = 25.0;
+
+/// An element of a script.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Word(String),
+    Pause {
+        ms: f64,
+    },
+    /// Speaking rate, 1 for normal.
+    Rate(f64),
+    /// Pitch offset in semitones.
+    Pitch(f32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScriptError {
+    /// A directive couldn't be parsed, on a line counting from 1.
+    Syntax { line: usize, text: String },
+    /// Words that aren't in the directory, each listed once.
+    UnknownWords(Vec<String>),
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Syntax { line, text } => write!(f, "syntax error on line {line}: {text}"),
+            Self::UnknownWords(words) => write!(f, "unknown words: {}", words.join(" ")),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Encoded words by name, with names compared ignoring case.
+#[derive(Clone, Debug, Default)]
+pub struct Words {
+    words: HashMap<String, Vec<u8>>,
+}
+
+impl Words {
+    /// Load each `NAME.hex` file in a directory, of hex bytes as printed by
+    /// the encoder, as the word NAME.
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let mut words = Self::default();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let (Some(name), Some("hex")) = (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.extension().and_then(|s| s.to_str()),
+            ) else {
+                continue;
+            };
+            let data = DataFormat::Hex.parse(&std::fs::read(&path)?).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {e}", path.display()),
+                )
+            })?;
+            words.insert(name, data);
+        }
+        Ok(words)
+    }
+
+    pub fn insert(&mut self, name: &str, data: Vec<u8>) {
+        self.words.insert(name.to_ascii_uppercase(), data);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.words
+            .get(&name.to_ascii_uppercase())
+            .map(Vec::as_slice)
+    }
+}
+
+/// Parse a directive, without its brackets.
+fn parse_directive(text: &str) -> Option<Item> {
+    let (name, arg) = text.trim().split_once(char::is_whitespace)?;
+    let arg = arg.trim();
+    match name {
+        "pause" => {
+            let ms = if let Some(ms) = arg.strip_suffix("ms") {
+                ms.trim().parse().ok()?
+            } else {
+                arg.strip_suffix('s')?.trim().parse::<f64>().ok()? * 1000.0
+            };
+            (ms >= 0.0).then_some(Item::Pause { ms })
+        }
+        "rate" => {
+            let rate: f64 = arg.parse().ok()?;
+            (rate > 0.0).then_some(Item::Rate(rate))
+        }
+        "pitch" => Some(Item::Pitch(arg.parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// Parse a script.
+pub fn parse_script(text: &str) -> Result<Vec<Item>, ScriptError> {
+    let mut items = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let syntax = |text: &str| ScriptError::Syntax {
+            line: i + 1,
+            text: text.to_string(),
+        };
+        let mut rest = line.split('#').next().unwrap();
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(directive) = rest.strip_prefix('[') {
+                let (directive, after) = directive.split_once(']').ok_or(syntax(rest))?;
+                items.push(parse_directive(directive).ok_or(syntax(directive))?);
+                rest = after;
+            } else {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '[')
+                    .unwrap_or(rest.len());
+                items.push(Item::Word(rest[..end].to_string()));
+                rest = &rest[end..];
+            }
+        }
+    }
+    Ok(items)
+}
+
+/// Compile a script to one bitstream, ending with a stop frame.
+///
+/// All unknown words are reported together, once each.
+pub fn compile(items: &[Item], words: &Words, tables: Tables) -> Result<Vec<u8>, ScriptError> {
+    let mut unknown = vec![];
+    for item in items {
+        if let Item::Word(name) = item
+            && words.get(name).is_none()
+            && !unknown
+                .iter()
+                .any(|u: &String| u.eq_ignore_ascii_case(name))
+        {
+            unknown.push(name.clone());
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(ScriptError::UnknownWords(unknown));
+    }
+
+    let mut frames = vec![];
+    let (mut rate, mut semitones) = (1.0, 0.0);
+    for item in items {
+        match item {
+            Item::Word(name) => {
+                let mut bs = BitStream::new(words.get(name).unwrap());
+                let mut word = Frames::new(&mut bs, &tables.layout()).collect::<Vec<_>>();
+                if rate != 1.0 && !word.is_empty() {
+                    // Dropping or repeating frames breaks the chains of
+                    // repeat frames.
+                    resolve_repeats(&mut word);
+                    let n = ((word.len() as f64 / rate).round() as usize).max(1);
+                    word = (0..n).map(|i| word[i * word.len() / n]).collect();
+                }
+                if semitones != 0.0 {
+                    let shift = Breakpoint {
+                        pos: 0.0,
+                        semitones,
+                    };
+                    Contour::new(&[shift]).apply(&mut word, &tables);
+                }
+                frames.extend(word);
+            }
+            Item::Pause { ms } => {
+                let n = (ms / FRAME_MS).round() as usize;
+                frames.extend((0..n).map(|_| Frame::silence()));
+            }
+            Item::Rate(r) => rate = *r,
+            Item::Pitch(s) => semitones = *s,
+        }
+    }
+    frames.push(Frame::stop());
+
+    let layout = tables.layout();
+    let mut buf = vec![0; (frames.len() * layout.max_frame_bits()).div_ceil(8)];
+    let mut w = BitWriter::new(&mut buf);
+    for frame in &frames {
+        frame.write(&mut w, &layout);
+    }
+    let len = w.byte_len();
+    buf.truncate(len);
+    Ok(buf)
+}
@@ -0,0 +1,276 @@
+//! Singing: an utterance performed to a melody.
+//!
+//! Each note takes the next syllable of the utterance, a run of voiced frames
+//! with the unvoiced and silent frames before it. The consonants keep their
+//! length, and the voiced frames are stretched or squeezed so the syllable
+//! fills the note, with the pitch held at the note's. When the notes outlast
+//! the syllables, the utterance starts over.
+//!
+//! Notes come from a MIDI file, or a note list with one note per line:
+//!
+//! ```text
+//! # note duration_ms
+//! C4 400
+//! E4 400
+//! rest 200
+//! 67 800
+//! ```
+//!
+//! Notes are names with an optional `#` or `b`, or MIDI key numbers. Lines
+//! starting with `#` are comments.
+
+use speakie::{Frame, Speakie, effects::Effects, tables::Tables};
+
+use crate::edit::resolve_repeats;
+
+/// A note, or a rest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    /// MIDI key number, or `None` for a rest.
+    pub key: Option<u8>,
+    pub duration_ms: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NoteError {
+    /// A line of a note list couldn't be parsed, counting from 1.
+    Syntax { line: usize },
+    /// The MIDI file couldn't be read.
+    Midi(String),
+}
+
+impl std::fmt::Display for NoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Syntax { line } => write!(f, "syntax error on line {line}"),
+            Self::Midi(e) => write!(f, "bad MIDI file: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for NoteError {}
+
+impl Note {
+    /// The pitch period at 8kHz, in 1/16 samples.
+    pub fn period(&self) -> Option<u16> {
+        let freq = 440.0 * 2f64.powf((self.key? as f64 - 69.0) / 12.0);
+        Some((8000.0 * 16.0 / freq).round().min(u16::MAX as f64) as u16)
+    }
+}
+
+/// Parse a note name such as `C4`, `F#3` or `Bb2`, or a MIDI key number.
+pub fn parse_key(name: &str) -> Option<u8> {
+    if let Ok(key) = name.parse() {
+        return (key < 128).then_some(key);
+    }
+    let mut chars = name.chars();
+    let step = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.as_bytes().first() {
+        Some(b'#') => (1, &rest[1..]),
+        Some(b'b') => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let octave: i32 = octave.parse().ok()?;
+    u8::try_from((octave + 1) * 12 + step + accidental)
+        .ok()
+        .filter(|key| *key < 128)
+}
+
+/// Parse a note list.
+pub fn parse_notes(text: &str) -> Result<Vec<Note>, NoteError> {
+    let mut notes = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let err = NoteError::Syntax { line: i + 1 };
+        // Sharps use '#', so comments are whole lines.
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, duration) = line.split_once(char::is_whitespace).ok_or(err.clone())?;
+        let key = match name {
+            "rest" | "r" => None,
+            _ => Some(parse_key(name).ok_or(err.clone())?),
+        };
+        let duration_ms = duration.trim().parse().map_err(|_| err)?;
+        notes.push(Note { key, duration_ms });
+    }
+    Ok(notes)
+}
+
+/// Read the melody of a MIDI file.
+///
+/// Notes of all tracks are merged, and a note cuts off any note still
+/// sounding, so chords play their last note. Gaps become rests.
+pub fn read_midi(data: &[u8]) -> Result<Vec<Note>, NoteError> {
+    use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+    let smf = Smf::parse(data).map_err(|e| NoteError::Midi(e.to_string()))?;
+    // Events as (tick, key, on), and tempo changes as (tick, us per beat).
+    let mut events = vec![];
+    let mut tempos = vec![(0, 500_000)];
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Midi { message, .. } => match message {
+                    MidiMessage::NoteOn { key, vel } => {
+                        events.push((tick, key.as_int(), vel > 0));
+                    }
+                    MidiMessage::NoteOff { key, .. } => events.push((tick, key.as_int(), false)),
+                    _ => {}
+                },
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                    tempos.push((tick, tempo.as_int()));
+                }
+                _ => {}
+            }
+        }
+    }
+    events.sort_by_key(|(tick, _, on)| (*tick, *on));
+    tempos.sort_by_key(|(tick, _)| *tick);
+    let to_ms = |tick: u64| match smf.header.timing {
+        Timing::Metrical(tpb) => {
+            let tpb = tpb.as_int() as f64;
+            let mut ms = 0.0;
+            for (i, (start, tempo)) in tempos.iter().enumerate() {
+                let end = tempos.get(i + 1).map_or(u64::MAX, |t| t.0).min(tick);
+                if end > *start {
+                    ms += (end - start) as f64 / tpb * *tempo as f64 / 1000.0;
+                }
+            }
+            ms
+        }
+        Timing::Timecode(fps, subframe) => {
+            tick as f64 * 1000.0 / (fps.as_f32() as f64 * subframe as f64)
+        }
+    };
+
+    let mut notes = vec![];
+    let mut time = 0.0;
+    let mut sounding: Option<(u8, f64)> = None;
+    for (tick, key, on) in events {
+        let ms = to_ms(tick);
+        if let Some((k, start)) = sounding
+            && (on || k == key)
+        {
+            notes.push(Note {
+                key: Some(k),
+                duration_ms: ms - start,
+            });
+            time = ms;
+            sounding = None;
+        }
+        if on {
+            if ms > time {
+                notes.push(Note {
+                    key: None,
+                    duration_ms: ms - time,
+                });
+            }
+            sounding = Some((key, ms));
+        }
+    }
+    notes.retain(|note| note.duration_ms > 0.0);
+    Ok(notes)
+}
+
+/// Split frames, up to the first stop frame, into syllables: the frames up
+/// to and including each run of voiced frames. Frames after the last run
+/// join the last syllable.
+///
+/// Returns each syllable's range and where its voiced run starts.
+fn syllables(frames: &[Frame]) -> Vec<(std::ops::Range<usize>, usize)> {
+    let mut syllables = vec![];
+    let mut start = 0;
+    let mut i = 0;
+    while i < frames.len() {
+        if frames[i].is_voiced() && !frames[i].is_silence() {
+            let voiced = i;
+            while i < frames.len() && frames[i].is_voiced() && !frames[i].is_silence() {
+                i += 1;
+            }
+            syllables.push((start..i, voiced));
+            start = i;
+        } else {
+            i += 1;
+        }
+    }
+    if let Some((last, _)) = syllables.last_mut() {
+        last.end = frames.len();
+    }
+    syllables
+}
+
+/// Sing an utterance to notes, returning 8kHz samples.
+pub fn sing(frames: &[Frame], notes: &[Note], tables: Tables) -> Vec<i16> {
+    let end = frames
+        .iter()
+        .position(Frame::is_stop)
+        .unwrap_or(frames.len());
+    let mut frames = frames[..end].to_vec();
+    resolve_repeats(&mut frames);
+    let syllables = syllables(&frames);
+
+    let mut speakie = Speakie::with_tables(tables);
+    let frame_ms = speakie.frame_len() as f64 / 8.0;
+    let mut samples = vec![];
+    let mut play = |speakie: &mut Speakie, frame: &Frame| {
+        speakie.apply_frame(frame);
+        samples.extend((0..speakie.frame_len()).map(|_| speakie.get_sample()));
+    };
+    let mut next = syllables.iter().cycle();
+    // Time owed to or by earlier notes, from rounding to whole frames.
+    let mut debt_ms = 0.0;
+    for note in notes {
+        let target_ms = note.duration_ms + debt_ms;
+        let (Some(period), Some((range, voiced))) = (note.period(), next.next()) else {
+            let n = (target_ms / frame_ms).round().max(0.0) as usize;
+            debt_ms = target_ms - n as f64 * frame_ms;
+            speakie.set_effects(Effects::NONE);
+            for _ in 0..n {
+                play(&mut speakie, &Frame::silence());
+            }
+            continue;
+        };
+        speakie.set_effects(Effects {
+            robot_period: Some(period),
+            ..Effects::NONE
+        });
+        let run = *voiced..range.end.min(after_run(&frames, *voiced));
+        let consonants = range.len() - run.len();
+        let n_voiced = ((target_ms / frame_ms).round() as usize)
+            .saturating_sub(consonants)
+            .max(1);
+        debt_ms = target_ms - (consonants + n_voiced) as f64 * frame_ms;
+        for frame in &frames[range.start..run.start] {
+            play(&mut speakie, frame);
+        }
+        for j in 0..n_voiced {
+            play(&mut speakie, &frames[run.start + j * run.len() / n_voiced]);
+        }
+        for frame in &frames[run.end..range.end] {
+            play(&mut speakie, frame);
+        }
+    }
+    play(&mut speakie, &Frame::silence());
+    samples
+}
+
+/// The end of the voiced run starting at `start`.
+fn after_run(frames: &[Frame], start: usize) -> usize {
+    frames[start..]
+        .iter()
+        .position(|f| !f.is_voiced() || f.is_silence())
+        .map_or(frames.len(), |n| start + n)
+}
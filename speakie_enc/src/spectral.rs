@@ -0,0 +1,258 @@
+//! Spectral analysis of LPC frames.
+//!
+//! An [`AllPole`] filter is built from reflection coefficients, either
+//! decoded from a bitstream or measured by a [`Reflector`], and converted to
+//! the other common representations: direct-form predictor coefficients,
+//! log-area ratios and line spectral frequencies. Formants are found from the
+//! roots of the predictor polynomial.
+//!
+//! Reflection coefficients use the lattice's sign convention, so that voiced
+//! speech has a negative K1. The predictor polynomial is
+//! `A(z) = 1 + a[1] z^-1 + ... + a[p] z^-p`, and the filter is `1 / A(z)`.
+
+use std::f64::consts::PI;
+
+use num_complex::Complex64;
+use speakie::Params;
+
+use crate::reflector::Reflector;
+
+/// The sample rate that frequencies are given for.
+pub const SAMPLE_RATE: f64 = 8000.0;
+
+/// Poles wider than this aren't counted as formants.
+const MAX_FORMANT_BANDWIDTH: f64 = 500.0;
+
+/// Poles this close to DC or Nyquist are spectral tilt, not formants.
+const MIN_FORMANT_FREQ: f64 = 90.0;
+
+/// An all-pole filter, described by its reflection coefficients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AllPole {
+    k: Vec<f64>,
+}
+
+/// A resonance of the filter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Formant {
+    /// Centre frequency in Hz.
+    pub freq: f64,
+    /// 3dB bandwidth in Hz.
+    pub bandwidth: f64,
+}
+
+impl AllPole {
+    pub fn from_k(k: &[f64]) -> Self {
+        Self { k: k.to_vec() }
+    }
+
+    /// The filter measured by the encoder's analysis.
+    pub fn from_reflector(reflector: &Reflector) -> Self {
+        // The first entry is unused.
+        Self::from_k(&reflector.ks()[1..])
+    }
+
+    /// The filter of decoded parameters, from [`speakie::Speakie::frame_params`]
+    /// or a [`speakie::Tap`].
+    pub fn from_params(params: &Params) -> Self {
+        Self::from_frame_k(&params.k)
+    }
+
+    /// The filter of reflection coefficients scaled by 512, as in the
+    /// decoder's tables.
+    pub fn from_frame_k(k: &[i16; 10]) -> Self {
+        Self::from_k(&k.map(|k| k as f64 / 512.0))
+    }
+
+    /// Convert predictor coefficients `a[1..=p]` back to reflection
+    /// coefficients, with the step-down recursion.
+    ///
+    /// Returns `None` if the filter is unstable.
+    pub fn from_lpc(a: &[f64]) -> Option<Self> {
+        let mut a = a.to_vec();
+        let mut k = vec![0.0; a.len()];
+        for m in (0..a.len()).rev() {
+            k[m] = a[m];
+            let denom = 1.0 - k[m] * k[m];
+            if denom <= 0.0 {
+                return None;
+            }
+            let prev = a[..m]
+                .iter()
+                .zip(a[..m].iter().rev())
+                .map(|(aj, am_j)| (aj - k[m] * am_j) / denom)
+                .collect::<Vec<_>>();
+            a[..m].copy_from_slice(&prev);
+        }
+        Some(Self { k })
+    }
+
+    pub fn k(&self) -> &[f64] {
+        &self.k
+    }
+
+    pub fn order(&self) -> usize {
+        self.k.len()
+    }
+
+    /// Whether all reflection coefficients are inside the unit circle.
+    pub fn is_stable(&self) -> bool {
+        self.k.iter().all(|k| k.abs() < 1.0)
+    }
+
+    /// Direct-form predictor coefficients `a[1..=p]`, with the step-up
+    /// recursion.
+    pub fn lpc(&self) -> Vec<f64> {
+        let mut a: Vec<f64> = Vec::with_capacity(self.k.len());
+        for &k in &self.k {
+            let prev = a.clone();
+            for (aj, am_j) in a.iter_mut().zip(prev.iter().rev()) {
+                *aj += k * am_j;
+            }
+            a.push(k);
+        }
+        a
+    }
+
+    /// Log-area ratios, `ln((1 + k) / (1 - k))`.
+    ///
+    /// Coefficients on the unit circle give infinite ratios.
+    pub fn lar(&self) -> Vec<f64> {
+        self.k
+            .iter()
+            .map(|k| ((1.0 + k) / (1.0 - k)).ln())
+            .collect()
+    }
+
+    /// Line spectral frequencies in Hz, in ascending order.
+    ///
+    /// These are the zeros of the sum and difference polynomials
+    /// `A(z) ± z^-(p+1) A(1/z)`, found by evaluating their real-valued forms
+    /// on the unit circle. A stable filter has `p` of them, interleaved.
+    pub fn lsf(&self) -> Vec<f64> {
+        const GRID: usize = 1024;
+        let a = self.polynomial();
+        let half = (a.len() as f64) / 2.0;
+        // A(e^jw) e^(jw(p+1)/2); its real part is zero at the roots of the
+        // sum polynomial, its imaginary part at those of the difference.
+        let parts = |w: f64| {
+            a.iter().enumerate().fold((0.0, 0.0), |(re, im), (j, aj)| {
+                let phase = w * (half - j as f64);
+                (re + aj * phase.cos(), im + aj * phase.sin())
+            })
+        };
+        let mut lsf = vec![];
+        for part in [|p: (f64, f64)| p.0, |p: (f64, f64)| p.1] {
+            let f = |w| part(parts(w));
+            let mut w0 = PI * 0.5 / GRID as f64;
+            for i in 1..GRID {
+                let w1 = PI * (i as f64 + 0.5) / GRID as f64;
+                if f(w0).signum() != f(w1).signum() {
+                    lsf.push(bisect(f, w0, w1) * SAMPLE_RATE / (2.0 * PI));
+                }
+                w0 = w1;
+            }
+        }
+        lsf.sort_by(f64::total_cmp);
+        lsf
+    }
+
+    /// The magnitude response of `1 / A(z)` at `freq` Hz, in dB.
+    ///
+    /// The gain of the excitation isn't included.
+    pub fn response_db(&self, freq: f64) -> f64 {
+        let w = 2.0 * PI * freq / SAMPLE_RATE;
+        let a = self
+            .polynomial()
+            .iter()
+            .enumerate()
+            .map(|(j, aj)| Complex64::from_polar(*aj, -w * j as f64))
+            .sum::<Complex64>();
+        -20.0 * a.norm().log10()
+    }
+
+    /// The magnitude response at `n` frequencies evenly spaced from 0 to
+    /// Nyquist, in dB.
+    pub fn spectrum(&self, n: usize) -> Vec<(f64, f64)> {
+        (0..n)
+            .map(|i| {
+                let freq = i as f64 * SAMPLE_RATE / 2.0 / (n - 1).max(1) as f64;
+                (freq, self.response_db(freq))
+            })
+            .collect()
+    }
+
+    /// The poles of the filter, one of each conjugate pair, with their
+    /// frequencies and bandwidths, in ascending order of frequency.
+    pub fn poles(&self) -> Vec<Formant> {
+        let mut poles = roots(&self.polynomial())
+            .into_iter()
+            .filter(|z| z.im >= 0.0)
+            .map(|z| Formant {
+                freq: z.arg() * SAMPLE_RATE / (2.0 * PI),
+                bandwidth: -z.norm().ln() * SAMPLE_RATE / PI,
+            })
+            .collect::<Vec<_>>();
+        poles.sort_by(|a, b| a.freq.total_cmp(&b.freq));
+        poles
+    }
+
+    /// Estimate formants: the poles that are narrow enough to be resonances.
+    pub fn formants(&self) -> Vec<Formant> {
+        self.poles()
+            .into_iter()
+            .filter(|p| {
+                p.bandwidth < MAX_FORMANT_BANDWIDTH
+                    && p.freq > MIN_FORMANT_FREQ
+                    && p.freq < SAMPLE_RATE / 2.0 - MIN_FORMANT_FREQ
+            })
+            .collect()
+    }
+
+    /// `[1, a[1], ..., a[p]]`.
+    fn polynomial(&self) -> Vec<f64> {
+        let mut a = vec![1.0];
+        a.extend(self.lpc());
+        a
+    }
+}
+
+/// Find a zero of `f` between `w0` and `w1`, where it changes sign.
+fn bisect(f: impl Fn(f64) -> f64, mut w0: f64, mut w1: f64) -> f64 {
+    let s0 = f(w0).signum();
+    for _ in 0..40 {
+        let mid = (w0 + w1) / 2.0;
+        if f(mid).signum() == s0 {
+            w0 = mid;
+        } else {
+            w1 = mid;
+        }
+    }
+    (w0 + w1) / 2.0
+}
+
+/// The roots in z of `a[0] + a[1] z^-1 + ... + a[p] z^-p`, with `a[0]` = 1,
+/// by Durand-Kerner iteration.
+fn roots(a: &[f64]) -> Vec<Complex64> {
+    let p = a.len() - 1;
+    // In z, this is the monic polynomial z^p + a[1] z^(p-1) + ... + a[p].
+    let eval = |z: Complex64| a.iter().fold(Complex64::ZERO, |acc, aj| acc * z + aj);
+    let seed = Complex64::new(0.4, 0.9);
+    let mut z = (0..p).map(|i| seed.powu(i as u32)).collect::<Vec<_>>();
+    for _ in 0..500 {
+        let mut change: f64 = 0.0;
+        for i in 0..p {
+            let denom = (0..p)
+                .filter(|j| *j != i)
+                .map(|j| z[i] - z[j])
+                .product::<Complex64>();
+            let step = eval(z[i]) / denom;
+            z[i] -= step;
+            change = change.max(step.norm());
+        }
+        if change < 1e-12 {
+            break;
+        }
+    }
+    z
+}
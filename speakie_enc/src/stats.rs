@@ -0,0 +1,280 @@
+//! Statistics of a bitstream, for tuning content.
+//!
+//! [`Stats::analyze`] reads TMS5220 frames up to the stop frame and counts
+//! the mix of frame types, every code of every field, and the frames where
+//! the decoder jumps to new parameters instead of interpolating. Codes piled
+//! up at the ends of a table suggest the encoder is clipping, and frequent
+//! inhibited interpolation is heard as roughness.
+
+use std::fmt::Write;
+
+use speakie::{BitStream, Frame, FrameRate, Speakie, tables::Tables};
+
+const SAMPLE_RATE: f64 = 8000.0;
+
+/// Counts of each code of one field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    pub name: String,
+    /// Counts indexed by code.
+    pub counts: Vec<usize>,
+    /// The lowest code with a meaning for the field, 1 for energy and
+    /// period, as 0 is silence or unvoiced.
+    pub min: u8,
+    /// The highest code with a meaning for the field, 14 for energy, as 15
+    /// is stop.
+    pub max: u8,
+}
+
+impl Histogram {
+    fn new(name: impl Into<String>, len: usize, min: u8, max: u8) -> Self {
+        Self {
+            name: name.into(),
+            counts: vec![0; len],
+            min,
+            max,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// How many codes sit at the lowest or highest end of the table.
+    pub fn at_extremes(&self) -> usize {
+        self.counts[self.min as usize] + self.counts[self.max as usize]
+    }
+}
+
+/// The statistics of one bitstream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// Frames before the stop frame.
+    pub frames: usize,
+    pub voiced: usize,
+    pub unvoiced: usize,
+    pub silence: usize,
+    /// Repeat frames, which are also counted as voiced or unvoiced.
+    pub repeat: usize,
+    /// Whether the stream ends with a stop frame.
+    pub stopped: bool,
+    /// Bits up to and including the stop frame.
+    pub bits: usize,
+    pub samples: usize,
+    pub energy: Histogram,
+    pub period: Histogram,
+    /// K1 to K10. Repeat frames add nothing, and unvoiced frames only K1
+    /// to K4.
+    pub k: Vec<Histogram>,
+    /// The frames where interpolation was inhibited.
+    pub inhibited: Vec<usize>,
+}
+
+impl Stats {
+    /// Read frames from `bs` up to the stop frame, or the end.
+    pub fn analyze(bs: &mut BitStream<impl AsRef<[u8]>>, tables: Tables, rate: FrameRate) -> Self {
+        let layout = tables.layout();
+        let max_code = |len: usize| (len - 1) as u8;
+        let mut stats = Stats {
+            frames: 0,
+            voiced: 0,
+            unvoiced: 0,
+            silence: 0,
+            repeat: 0,
+            stopped: false,
+            bits: 0,
+            samples: 0,
+            energy: Histogram::new(
+                "energy",
+                tables.energy.len(),
+                1,
+                max_code(tables.energy.len()) - 1,
+            ),
+            period: Histogram::new(
+                "period",
+                tables.period.len(),
+                1,
+                max_code(tables.period.len()),
+            ),
+            k: (0..10)
+                .map(|i| {
+                    Histogram::new(
+                        format!("k{}", i + 1),
+                        tables.k[i].len(),
+                        0,
+                        max_code(tables.k[i].len()),
+                    )
+                })
+                .collect(),
+            inhibited: vec![],
+        };
+        let start = bs.bit_pos();
+        let mut speakie = Speakie::with_tables(tables);
+        loop {
+            let code = if rate.variable {
+                bs.get_bits(2) as u8
+            } else {
+                rate.code
+            };
+            let frame = Frame::read(bs, &layout);
+            if bs.is_overrun() {
+                break;
+            }
+            stats.bits = bs.bit_pos() - start;
+            let last = speakie.frame_params();
+            if speakie.apply_frame(&frame) {
+                stats.stopped = true;
+                break;
+            }
+            if last.inhibit_interp(&speakie.frame_params()) {
+                stats.inhibited.push(stats.frames);
+            }
+            stats.frames += 1;
+            stats.samples += FrameRate {
+                code,
+                variable: false,
+            }
+            .frame_len();
+            if frame.is_silence() {
+                stats.silence += 1;
+                continue;
+            }
+            stats.energy.counts[frame.energy as usize] += 1;
+            if frame.is_voiced() {
+                stats.voiced += 1;
+                stats.period.counts[frame.period as usize] += 1;
+            } else {
+                stats.unvoiced += 1;
+            }
+            if frame.repeat {
+                stats.repeat += 1;
+                continue;
+            }
+            for (hist, k) in stats.k.iter_mut().zip(frame.k).take(frame.n_k()) {
+                hist.counts[k as usize] += 1;
+            }
+        }
+        stats
+    }
+
+    /// The duration in seconds.
+    pub fn duration(&self) -> f64 {
+        self.samples as f64 / SAMPLE_RATE
+    }
+
+    /// Bits per second, or zero for an empty stream.
+    pub fn bitrate(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.bits as f64 / self.duration()
+        }
+    }
+
+    fn histograms(&self) -> impl Iterator<Item = &Histogram> {
+        [&self.energy, &self.period].into_iter().chain(&self.k)
+    }
+
+    /// A report for reading.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let percent = |n: usize| 100.0 * n as f64 / self.frames.max(1) as f64;
+        writeln!(
+            text,
+            "frames:   {} ({:.0}% voiced, {:.0}% unvoiced, {:.0}% silence, {:.0}% repeat)",
+            self.frames,
+            percent(self.voiced),
+            percent(self.unvoiced),
+            percent(self.silence),
+            percent(self.repeat)
+        )
+        .unwrap();
+        if !self.stopped {
+            writeln!(text, "          no stop frame").unwrap();
+        }
+        writeln!(text, "duration: {:.3}s", self.duration()).unwrap();
+        writeln!(
+            text,
+            "bits:     {}, {:.0} bits/s",
+            self.bits,
+            self.bitrate()
+        )
+        .unwrap();
+        writeln!(text).unwrap();
+        writeln!(text, "field   extremes  codes (code:count)").unwrap();
+        for hist in self.histograms() {
+            let codes = hist
+                .counts
+                .iter()
+                .enumerate()
+                .filter(|(_, n)| **n > 0)
+                .map(|(code, n)| format!("{code}:{n}"))
+                .collect::<Vec<_>>();
+            writeln!(
+                text,
+                "{:7} {:4}/{:<4} {}",
+                hist.name,
+                hist.at_extremes(),
+                hist.total(),
+                codes.join(" ")
+            )
+            .unwrap();
+        }
+        writeln!(text).unwrap();
+        let frames = self
+            .inhibited
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+        writeln!(
+            text,
+            "interpolation inhibited at {} frames: {}",
+            frames.len(),
+            frames.join(" ")
+        )
+        .unwrap();
+        text
+    }
+
+    /// The same report as JSON.
+    pub fn to_json(&self) -> String {
+        let list = |values: &[usize]| {
+            let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+            format!("[{}]", values.join(", "))
+        };
+        let mut json = String::from("{\n");
+        for (key, value) in [
+            ("frames", self.frames),
+            ("voiced", self.voiced),
+            ("unvoiced", self.unvoiced),
+            ("silence", self.silence),
+            ("repeat", self.repeat),
+            ("bits", self.bits),
+            ("samples", self.samples),
+        ] {
+            writeln!(json, "  \"{key}\": {value},").unwrap();
+        }
+        writeln!(json, "  \"stopped\": {},", self.stopped).unwrap();
+        writeln!(json, "  \"duration\": {:.4},", self.duration()).unwrap();
+        writeln!(json, "  \"bitrate\": {:.1},", self.bitrate()).unwrap();
+        writeln!(json, "  \"histograms\": {{").unwrap();
+        let hists = self
+            .histograms()
+            .map(|hist| {
+                format!(
+                    "    \"{}\": {{\"min\": {}, \"max\": {}, \"at_extremes\": {}, \"counts\": {}}}",
+                    hist.name,
+                    hist.min,
+                    hist.max,
+                    hist.at_extremes(),
+                    list(&hist.counts)
+                )
+            })
+            .collect::<Vec<_>>();
+        writeln!(json, "{}", hists.join(",\n")).unwrap();
+        writeln!(json, "  }},").unwrap();
+        writeln!(json, "  \"inhibited\": {}", list(&self.inhibited)).unwrap();
+        json += "}\n";
+        json
+    }
+}
@@ -0,0 +1,317 @@
+//! Text to speech from allophones.
+//!
+//! Text is converted to phonemes by [letter-to-sound rules](phonemes), and
+//! each phoneme is looked up in an [`Inventory`] of LPC frames. The frames
+//! are then given prosody:
+//!
+//! - Durations: stressed vowels are lengthened and unstressed ones
+//!   shortened, the last vowel of a phrase is lengthened, and consonants in
+//!   clusters are shortened.
+//! - Coarticulation: where two sounding phonemes meet, the frames either side
+//!   are pulled towards each other, as log area ratios, so formants glide
+//!   across the boundary.
+//! - Pitch: each phrase declines, stressed vowels are raised, and the end of
+//!   the phrase follows its punctuation, rising for questions and falling
+//!   for statements.
+//!
+//! Phrases are separated by silence, and the result is re-quantized to a
+//! single bitstream with a stop frame.
+
+use speakie::{
+    Frame,
+    intonation::{self, Breakpoint, Contour},
+    tables::Tables,
+};
+
+use crate::output::Output;
+
+pub mod inventory;
+pub mod phonemes;
+
+pub use inventory::Inventory;
+use inventory::NOMINAL_PERIOD;
+use phonemes::{Phrase, PhraseEnd, is_vowel_phoneme};
+
+/// Frames are 25ms, at the TMS5220's default rate.
+const FRAME_MS: f64 = 25.0;
+
+/// Frames either side of a phoneme boundary that coarticulation reaches.
+const COARTICULATION_SPAN: usize = 2;
+
+/// Pitch raise of stressed vowels, in semitones.
+const STRESS_SEMITONES: f32 = 2.5;
+
+/// The voice's settings.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Voice {
+    /// The middle of the pitch range, in Hz.
+    pub pitch: f64,
+    /// Speaking rate, 1 for normal. Above 1 is faster.
+    pub rate: f64,
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Self {
+            pitch: 110.0,
+            rate: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TtsError {
+    /// A phoneme isn't in the inventory.
+    UnknownPhoneme(String),
+}
+
+impl std::fmt::Display for TtsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownPhoneme(name) => write!(f, "no allophone for phoneme {name}"),
+        }
+    }
+}
+
+impl std::error::Error for TtsError {}
+
+/// Frame parameters, unquantized.
+#[derive(Clone, Copy, Debug, Default)]
+struct Params {
+    energy: f64,
+    /// Pitch period in samples, or 0 for unvoiced.
+    period: f64,
+    k: [f64; 10],
+}
+
+impl Params {
+    fn is_sounding(&self) -> bool {
+        self.energy > 0.0
+    }
+}
+
+/// A frame of an utterance, and what it belongs to.
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    params: Params,
+    /// Index of the phone in its phrase.
+    phone: usize,
+    stressed_vowel: bool,
+}
+
+/// Decode frames, resolving repeats.
+fn decode(frames: &[Frame], tables: &Tables) -> Vec<Params> {
+    let mut k = [0.0; 10];
+    frames
+        .iter()
+        .map(|frame| {
+            if frame.is_silence() {
+                return Params::default();
+            }
+            if !frame.repeat {
+                let n_k = if frame.is_voiced() { 10 } else { 4 };
+                k = [0.0; 10];
+                for (i, k) in k.iter_mut().enumerate().take(n_k) {
+                    *k = tables.k[i][frame.k[i] as usize] as f64 / 512.0;
+                }
+            }
+            Params {
+                energy: tables.energy[frame.energy as usize] as f64,
+                period: tables.period[frame.period as usize] as f64,
+                k,
+            }
+        })
+        .collect()
+}
+
+/// How much a phone is lengthened.
+fn duration_scale(phrase: &Phrase, i: usize, last_vowel: Option<usize>) -> f64 {
+    let phone = &phrase.phones[i];
+    if is_vowel_phoneme(phone.name) {
+        let mut scale = if phone.stressed { 1.2 } else { 0.8 };
+        if Some(i) == last_vowel {
+            scale *= 1.4;
+        }
+        scale
+    } else {
+        let is_consonant = |i: usize| {
+            phrase
+                .phones
+                .get(i)
+                .is_some_and(|p| !is_vowel_phoneme(p.name))
+        };
+        if i.checked_sub(1).is_some_and(is_consonant) || is_consonant(i + 1) {
+            0.8
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Log area ratios of reflection coefficients, and back.
+fn to_lar(k: &[f64; 10]) -> [f64; 10] {
+    k.map(|k| {
+        let k = k.clamp(-0.999, 0.999);
+        ((1.0 + k) / (1.0 - k)).ln()
+    })
+}
+
+fn from_lar(lar: &[f64; 10]) -> [f64; 10] {
+    lar.map(|g| (g / 2.0).tanh())
+}
+
+/// Pull the frames either side of each boundary between sounding phones
+/// towards the boundary's midpoint.
+fn coarticulate(slots: &mut [Slot]) {
+    let lars = slots
+        .iter()
+        .map(|s| to_lar(&s.params.k))
+        .collect::<Vec<_>>();
+    for b in 1..slots.len() {
+        let (left, right) = (slots[b - 1], slots[b]);
+        if left.phone == right.phone
+            || !left.params.is_sounding()
+            || !right.params.is_sounding()
+            || (left.params.period == 0.0) != (right.params.period == 0.0)
+        {
+            continue;
+        }
+        let (l, r) = (lars[b - 1], lars[b]);
+        for d in 0..COARTICULATION_SPAN {
+            let weight = (COARTICULATION_SPAN - d) as f64 / (COARTICULATION_SPAN + 1) as f64;
+            for (i, edge, phone) in [
+                (b.checked_sub(d + 1), l, left.phone),
+                (Some(b + d), r, right.phone),
+            ] {
+                let Some(slot) = i.and_then(|i| slots.get_mut(i)) else {
+                    continue;
+                };
+                if slot.phone != phone || !slot.params.is_sounding() {
+                    continue;
+                }
+                let mut lar = to_lar(&slot.params.k);
+                for j in 0..10 {
+                    lar[j] += weight * ((l[j] + r[j]) / 2.0 - edge[j]);
+                }
+                slot.params.k = from_lar(&lar);
+            }
+        }
+    }
+}
+
+/// The pitch contour for a phrase ending.
+fn contour(end: PhraseEnd) -> Contour {
+    match end {
+        PhraseEnd::Statement => Contour::new(&intonation::STATEMENT),
+        PhraseEnd::Question => Contour::new(&intonation::QUESTION),
+        // Declining, with a slight rise to show there's more to come.
+        PhraseEnd::Continue => Contour::new(&[
+            Breakpoint {
+                pos: 0.0,
+                semitones: 1.0,
+            },
+            Breakpoint {
+                pos: 0.8,
+                semitones: -1.0,
+            },
+            Breakpoint {
+                pos: 1.0,
+                semitones: 0.0,
+            },
+        ]),
+    }
+}
+
+/// The frames of a phrase, with durations, coarticulation and pitch.
+fn phrase_frames(
+    phrase: &Phrase,
+    inventory: &Inventory,
+    voice: &Voice,
+    tables: &Tables,
+) -> Result<Vec<Params>, TtsError> {
+    let last_vowel = phrase.phones.iter().rposition(|p| is_vowel_phoneme(p.name));
+    let mut slots = vec![];
+    for (i, phone) in phrase.phones.iter().enumerate() {
+        let frames = inventory
+            .get(phone.name)
+            .ok_or_else(|| TtsError::UnknownPhoneme(phone.name.to_string()))?;
+        let params = decode(frames, tables);
+        if params.is_empty() {
+            continue;
+        }
+        let scale = duration_scale(phrase, i, last_vowel) / voice.rate;
+        let n = ((params.len() as f64 * scale).round() as usize).max(1);
+        let stressed_vowel = phone.stressed && is_vowel_phoneme(phone.name);
+        slots.extend((0..n).map(|j| Slot {
+            params: params[j * params.len() / n],
+            phone: i,
+            stressed_vowel,
+        }));
+    }
+    coarticulate(&mut slots);
+
+    let contour = contour(phrase.end);
+    let n = slots.len();
+    Ok(slots
+        .iter()
+        .enumerate()
+        .map(|(i, slot)| {
+            let mut params = slot.params;
+            if params.period != 0.0 {
+                let pos = i as f32 / (n - 1).max(1) as f32;
+                let mut semitones = contour.semitones_at(pos);
+                if slot.stressed_vowel {
+                    semitones += STRESS_SEMITONES;
+                }
+                let pitch = voice.pitch * 2f64.powf(semitones as f64 / 12.0);
+                params.period = 8000.0 / pitch;
+                // A chirp comes once a period, so keep the power, energy
+                // squared over period, the same.
+                params.energy *= (params.period / NOMINAL_PERIOD).sqrt();
+            }
+            params
+        })
+        .collect())
+}
+
+/// The silence after a phrase, in milliseconds.
+fn pause_ms(end: PhraseEnd) -> f64 {
+    match end {
+        PhraseEnd::Continue => 200.0,
+        PhraseEnd::Statement | PhraseEnd::Question => 400.0,
+    }
+}
+
+/// Speak phrases, returning a bitstream ending with a stop frame.
+pub fn speak_phrases(
+    phrases: &[Phrase],
+    inventory: &Inventory,
+    voice: &Voice,
+    tables: Tables,
+) -> Result<Vec<u8>, TtsError> {
+    let mut out = Output::new(tables);
+    for (i, phrase) in phrases.iter().enumerate() {
+        for params in phrase_frames(phrase, inventory, voice, &tables)? {
+            out.frame(params.energy, params.period, &params.k);
+        }
+        if i + 1 < phrases.len() {
+            let n = (pause_ms(phrase.end) / voice.rate / FRAME_MS).round() as usize;
+            for _ in 0..n {
+                out.frame(0.0, 0.0, &[]);
+            }
+        }
+    }
+    out.pack(15, 4);
+    out.pack(0, 7);
+    Ok(out.reap())
+}
+
+/// Speak text, returning a bitstream ending with a stop frame.
+pub fn speak(
+    text: &str,
+    inventory: &Inventory,
+    voice: &Voice,
+    tables: Tables,
+) -> Result<Vec<u8>, TtsError> {
+    speak_phrases(&phonemes::text_to_phrases(text), inventory, voice, tables)
+}
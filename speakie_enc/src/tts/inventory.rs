@@ -0,0 +1,379 @@
+//! The allophone inventory: a sequence of LPC frames for each phoneme.
+//!
+//! The built-in inventory is designed from formant targets and encoded with
+//! [`formant_synth`](crate::formant_synth). Recorded allophones can replace
+//! any of them, encoded from WAV files like the encoder's input.
+
+use std::{collections::HashMap, io, path::Path};
+
+use speakie::{BitStream, Frame, FrameRate, Frames, tables::Tables};
+
+use crate::{
+    formant_synth::{self, Keyframe},
+    spectral::Formant,
+    to_lpc,
+};
+
+/// The pitch period that voiced allophones are designed at, in samples.
+pub const NOMINAL_PERIOD: f64 = 80.0;
+
+/// Output level of an allophone with amplitude 1, as the RMS level of noise
+/// excitation through its filter.
+const OUTPUT_RMS: f64 = 3000.0;
+
+/// The default bandwidths of F1..F3, and a fixed F4.
+const BANDWIDTHS: [f64; 3] = [80.0, 100.0, 150.0];
+const F4: Formant = Formant {
+    freq: 3300.0,
+    bandwidth: 250.0,
+};
+
+/// LPC frames for each phoneme, by ARPAbet name.
+#[derive(Clone, Debug, Default)]
+pub struct Inventory {
+    allophones: HashMap<String, Vec<Frame>>,
+}
+
+/// A stretch of an allophone with constant or gliding parameters.
+struct Segment {
+    duration_ms: f64,
+    voiced: bool,
+    amplitude: f64,
+    /// Formants at the start and end.
+    formants: [Vec<Formant>; 2],
+}
+
+fn formants(freqs: &[f64]) -> Vec<Formant> {
+    let mut formants = freqs
+        .iter()
+        .zip(BANDWIDTHS)
+        .map(|(&freq, bandwidth)| Formant { freq, bandwidth })
+        .collect::<Vec<_>>();
+    if freqs.len() == 3 {
+        formants.push(F4);
+    }
+    formants
+}
+
+/// Formants with explicit bandwidths.
+fn peaks(peaks: &[(f64, f64)]) -> Vec<Formant> {
+    peaks
+        .iter()
+        .map(|&(freq, bandwidth)| Formant { freq, bandwidth })
+        .collect()
+}
+
+fn steady(duration_ms: f64, voiced: bool, amplitude: f64, formants: Vec<Formant>) -> Segment {
+    Segment {
+        duration_ms,
+        voiced,
+        amplitude,
+        formants: [formants.clone(), formants],
+    }
+}
+
+fn silence(duration_ms: f64) -> Segment {
+    steady(duration_ms, false, 0.0, vec![])
+}
+
+fn vowel(duration_ms: f64, f: [f64; 3]) -> Vec<Segment> {
+    vec![steady(duration_ms, true, 1.0, formants(&f))]
+}
+
+fn diphthong(duration_ms: f64, from: [f64; 3], to: [f64; 3]) -> Vec<Segment> {
+    vec![
+        steady(duration_ms * 0.3, true, 1.0, formants(&from)),
+        Segment {
+            duration_ms: duration_ms * 0.7,
+            voiced: true,
+            amplitude: 1.0,
+            formants: [formants(&from), formants(&to)],
+        },
+    ]
+}
+
+fn sonorant(duration_ms: f64, amplitude: f64, f: Vec<Formant>) -> Vec<Segment> {
+    vec![steady(duration_ms, true, amplitude, f)]
+}
+
+fn fricative(duration_ms: f64, voiced: bool, amplitude: f64, f: Vec<Formant>) -> Vec<Segment> {
+    vec![steady(duration_ms, voiced, amplitude, f)]
+}
+
+/// A closure, then a burst, then for unvoiced stops aspiration.
+fn stop(closure_ms: f64, voiced: bool, burst: Vec<Formant>) -> Vec<Segment> {
+    let mut segments = vec![if voiced {
+        // The voice bar, low frequency voicing through the closure.
+        steady(closure_ms, true, 0.1, peaks(&[(200.0, 80.0)]))
+    } else {
+        silence(closure_ms)
+    }];
+    segments.push(steady(25.0, voiced, 0.4, burst));
+    if !voiced {
+        let aspiration = peaks(&[(640.0, 300.0), (1190.0, 300.0), (2390.0, 400.0)]);
+        segments.push(steady(25.0, false, 0.15, aspiration));
+    }
+    segments
+}
+
+/// The built-in allophone designs.
+fn designs() -> Vec<(&'static str, Vec<Segment>)> {
+    let s = peaks(&[(2600.0, 400.0), (3600.0, 300.0)]);
+    let sh = peaks(&[(2200.0, 300.0), (3000.0, 400.0)]);
+    let z = peaks(&[(250.0, 100.0), (2600.0, 400.0), (3600.0, 300.0)]);
+    let zh = peaks(&[(250.0, 100.0), (2200.0, 300.0), (3000.0, 400.0)]);
+    let nasal = |f2: f64, f3: f64| peaks(&[(250.0, 100.0), (f2, 200.0), (f3, 250.0)]);
+    let mut ch = stop(50.0, false, peaks(&[(2000.0, 400.0), (3500.0, 300.0)]));
+    ch.pop();
+    ch.extend(fricative(70.0, false, 0.5, sh.clone()));
+    let mut jh = stop(40.0, true, peaks(&[(250.0, 100.0), (1700.0, 200.0)]));
+    jh.extend(fricative(60.0, true, 0.3, zh.clone()));
+    vec![
+        ("IY", vowel(150.0, [270.0, 2290.0, 3010.0])),
+        ("IH", vowel(110.0, [390.0, 1990.0, 2550.0])),
+        ("EH", vowel(120.0, [530.0, 1840.0, 2480.0])),
+        ("AE", vowel(170.0, [660.0, 1720.0, 2410.0])),
+        ("AA", vowel(170.0, [730.0, 1090.0, 2440.0])),
+        ("AO", vowel(170.0, [570.0, 840.0, 2410.0])),
+        ("UH", vowel(110.0, [440.0, 1020.0, 2240.0])),
+        ("UW", vowel(150.0, [300.0, 870.0, 2240.0])),
+        ("AH", vowel(120.0, [640.0, 1190.0, 2390.0])),
+        ("AX", vowel(70.0, [500.0, 1500.0, 2500.0])),
+        ("ER", vowel(150.0, [490.0, 1350.0, 1690.0])),
+        (
+            "EY",
+            diphthong(200.0, [530.0, 1840.0, 2480.0], [300.0, 2200.0, 2900.0]),
+        ),
+        (
+            "AY",
+            diphthong(220.0, [730.0, 1090.0, 2440.0], [350.0, 2100.0, 2800.0]),
+        ),
+        (
+            "AW",
+            diphthong(220.0, [730.0, 1090.0, 2440.0], [350.0, 800.0, 2300.0]),
+        ),
+        (
+            "OW",
+            diphthong(200.0, [570.0, 840.0, 2410.0], [350.0, 800.0, 2300.0]),
+        ),
+        (
+            "OY",
+            diphthong(230.0, [570.0, 840.0, 2410.0], [350.0, 2000.0, 2800.0]),
+        ),
+        ("W", sonorant(70.0, 0.6, formants(&[290.0, 610.0, 2150.0]))),
+        ("Y", sonorant(70.0, 0.6, formants(&[260.0, 2070.0, 3020.0]))),
+        ("R", sonorant(80.0, 0.7, formants(&[310.0, 1060.0, 1380.0]))),
+        ("L", sonorant(80.0, 0.6, formants(&[360.0, 1300.0, 2700.0]))),
+        ("M", sonorant(80.0, 0.4, nasal(1270.0, 2130.0))),
+        ("N", sonorant(80.0, 0.4, nasal(1340.0, 2470.0))),
+        ("NG", sonorant(80.0, 0.4, nasal(2000.0, 2800.0))),
+        ("S", fricative(110.0, false, 0.5, s)),
+        ("SH", fricative(110.0, false, 0.6, sh)),
+        (
+            "F",
+            fricative(
+                100.0,
+                false,
+                0.15,
+                peaks(&[(1500.0, 1500.0), (3000.0, 1000.0)]),
+            ),
+        ),
+        (
+            "TH",
+            fricative(
+                90.0,
+                false,
+                0.12,
+                peaks(&[(1700.0, 1500.0), (3200.0, 1000.0)]),
+            ),
+        ),
+        (
+            "HH",
+            fricative(
+                70.0,
+                false,
+                0.25,
+                peaks(&[(640.0, 300.0), (1190.0, 300.0), (2390.0, 400.0)]),
+            ),
+        ),
+        (
+            "V",
+            fricative(
+                70.0,
+                true,
+                0.25,
+                peaks(&[(250.0, 100.0), (1100.0, 300.0), (2300.0, 400.0)]),
+            ),
+        ),
+        (
+            "DH",
+            fricative(
+                50.0,
+                true,
+                0.25,
+                peaks(&[(250.0, 100.0), (1500.0, 300.0), (2500.0, 400.0)]),
+            ),
+        ),
+        ("Z", fricative(90.0, true, 0.3, z)),
+        ("ZH", fricative(90.0, true, 0.3, zh)),
+        (
+            "P",
+            stop(
+                70.0,
+                false,
+                peaks(&[(800.0, 300.0), (1800.0, 500.0), (2600.0, 500.0)]),
+            ),
+        ),
+        (
+            "T",
+            stop(60.0, false, peaks(&[(2000.0, 400.0), (3500.0, 300.0)])),
+        ),
+        (
+            "K",
+            stop(60.0, false, peaks(&[(1800.0, 200.0), (2800.0, 400.0)])),
+        ),
+        (
+            "B",
+            stop(
+                50.0,
+                true,
+                peaks(&[(250.0, 100.0), (900.0, 200.0), (2300.0, 300.0)]),
+            ),
+        ),
+        (
+            "D",
+            stop(
+                40.0,
+                true,
+                peaks(&[(250.0, 100.0), (1700.0, 200.0), (2600.0, 300.0)]),
+            ),
+        ),
+        (
+            "G",
+            stop(
+                50.0,
+                true,
+                peaks(&[(250.0, 100.0), (1900.0, 200.0), (2700.0, 300.0)]),
+            ),
+        ),
+        ("CH", ch),
+        ("JH", jh),
+    ]
+}
+
+/// Keyframes for segments, with excitation scaled so that each segment
+/// sounds at its amplitude whatever its filter's gain.
+fn keyframes(segments: &[Segment], max_energy: f64) -> Vec<Keyframe> {
+    let mut keyframes = vec![];
+    let mut time_ms = 0.0;
+    for segment in segments {
+        for (formants, time_ms) in segment
+            .formants
+            .iter()
+            .zip([time_ms, time_ms + segment.duration_ms])
+        {
+            let mut keyframe = Keyframe {
+                time_ms,
+                pitch: if segment.voiced {
+                    8000.0 / NOMINAL_PERIOD
+                } else {
+                    0.0
+                },
+                amplitude: 0.0,
+                formants: formants.clone(),
+            };
+            // Noise through the filter has this gain.
+            let gain = 1.0
+                / keyframe
+                    .filter()
+                    .k()
+                    .iter()
+                    .map(|k| 1.0 - k * k)
+                    .product::<f64>()
+                    .sqrt();
+            let mut energy = segment.amplitude * OUTPUT_RMS / gain;
+            if segment.voiced {
+                // Chirps excite once a period, noise every sample.
+                energy *= NOMINAL_PERIOD / 30.0;
+            }
+            keyframe.amplitude = energy / max_energy;
+            keyframes.push(keyframe);
+        }
+        time_ms += segment.duration_ms;
+    }
+    keyframes
+}
+
+impl Inventory {
+    /// The built-in inventory, designed from formants.
+    pub fn builtin(tables: Tables) -> Self {
+        let max_energy = tables.energy[tables.energy.len() - 2] as f64;
+        let allophones = designs()
+            .into_iter()
+            .map(|(name, segments)| {
+                let keyframes = keyframes(&segments, max_energy);
+                let data = formant_synth::synthesize(&keyframes, tables, FrameRate::default())
+                    .expect("bad allophone design");
+                let frames = Frames::new(&mut BitStream::new(&data), &tables.layout()).collect();
+                (name.to_string(), frames)
+            })
+            .collect();
+        Self { allophones }
+    }
+
+    /// Replace allophones with recordings: each `NAME.wav` in `dir`, 8kHz
+    /// mono, is encoded as the phoneme NAME. Silence around the recording is
+    /// trimmed.
+    ///
+    /// Returns the number of allophones loaded.
+    pub fn load_wav_dir(&mut self, dir: &Path, tables: Tables) -> io::Result<usize> {
+        let mut n = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let (Some(name), Some("wav")) = (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.extension().and_then(|s| s.to_str()),
+            ) else {
+                continue;
+            };
+            let mut reader = hound::WavReader::open(&path).map_err(io::Error::other)?;
+            let samples = reader
+                .samples::<i16>()
+                .map(|s| s.map(|s| s as f64))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(io::Error::other)?;
+            let data = to_lpc(&samples, false, tables, FrameRate::default());
+            let mut frames =
+                Frames::new(&mut BitStream::new(&data), &tables.layout()).collect::<Vec<_>>();
+            let start = frames.iter().position(|f| !f.is_silence()).unwrap_or(0);
+            let end = frames
+                .iter()
+                .rposition(|f| !f.is_silence())
+                .map_or(0, |i| i + 1);
+            frames.truncate(end);
+            frames.drain(..start.min(end));
+            self.insert(&name.to_ascii_uppercase(), frames);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    pub fn insert(&mut self, name: &str, frames: Vec<Frame>) {
+        self.allophones.insert(name.to_string(), frames);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[Frame]> {
+        self.allophones.get(name).map(Vec::as_slice)
+    }
+
+    /// The phoneme names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self
+            .allophones
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
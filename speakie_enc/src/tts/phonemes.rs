@@ -0,0 +1,614 @@
+//! English letter-to-phoneme rules.
+//!
+//! These follow the rules of Elovitz et al., "Letter-to-Sound Rules for
+//! Automatic Translation of English Text to Phonetics" (NRL Report 7948),
+//! with ARPAbet phonemes. A rule is written `left[match]right=PHONEMES`, and
+//! the first rule for a letter whose match and contexts fit applies.
+//!
+//! In contexts, letters and `'` match themselves, and:
+//!
+//! | Symbol | Matches                                   |
+//! |--------|-------------------------------------------|
+//! | space  | the start or end of the word              |
+//! | `#`    | one or more vowels                        |
+//! | `:`    | zero or more consonants                   |
+//! | `^`    | one consonant                             |
+//! | `.`    | one voiced consonant                      |
+//! | `+`    | a front vowel: E, I or Y                  |
+//! | `%`    | a suffix: ER, E, ES, ED, ING or ELY       |
+//! | `&`    | a sibilant: S, C, G, Z, X, J, CH or SH    |
+//! | `@`    | T, S, R, D, L, Z, N, J, TH, CH or SH      |
+
+const RULES: &[&str] = &[
+    " [A] =AX",
+    " [ARE] =AA R",
+    " [AR]O=AX R",
+    "[AR]#=EH R",
+    " ^[AS]#=EY S",
+    "[A]WA=AX",
+    "[AW]=AO",
+    " :[ANY]=EH N IY",
+    "[A]^+#=EY",
+    "#:[ALLY]=AX L IY",
+    " [AL]#=AX L",
+    "[AGAIN]=AX G EH N",
+    "#:[AG]E=IH JH",
+    "[A]^+:#=AE",
+    " :[A]^+ =EY",
+    "[A]^%=EY",
+    " [ARR]=AX R",
+    "[ARR]=AE R",
+    " :[AR] =AA R",
+    "[AR] =ER",
+    "[AR]=AA R",
+    "[AIR]=EH R",
+    "[AI]=EY",
+    "[AY]=EY",
+    "[AU]=AO",
+    "#:[AL] =AX L",
+    "#:[ALS] =AX L Z",
+    "[ALK]=AO K",
+    "[AL]^=AO L",
+    " :[ABLE]=EY B AX L",
+    "[ABLE]=AX B AX L",
+    "[ANG]+=EY N JH",
+    "[A]=AE",
+    " [BE]^#=B IH",
+    "[BEING]=B IY IH NG",
+    " [BOTH] =B OW TH",
+    " [BUS]#=B IH Z",
+    "[BUIL]=B IH L",
+    "[B]=B",
+    " [CH]^=K",
+    "^E[CH]=K",
+    "[CH]=CH",
+    " S[CI]#=S AY",
+    "[CI]A=SH",
+    "[CI]O=SH",
+    "[CI]EN=SH",
+    "[C]+=S",
+    "[CK]=K",
+    "[COM]%=K AH M",
+    "[C]=K",
+    "#:[DED] =D IH D",
+    ".E[D] =D",
+    "#:^E[D] =T",
+    " [DE]^#=D IH",
+    " [DO] =D UW",
+    " [DOES]=D AH Z",
+    " [DOING]=D UW IH NG",
+    " [DOW]=D AW",
+    "[DU]A=JH UW",
+    "[D]=D",
+    "#:[E] =",
+    "':^[E] =",
+    " :[E] =IY",
+    "#[ED] =D",
+    "#:[E]D =",
+    "[EV]ER=EH V",
+    "[E]^%=IY",
+    "[ERI]#=IY R IY",
+    "[ERI]=EH R IH",
+    "#:[ER]#=ER",
+    "[ER]#=EH R",
+    "[ER]=ER",
+    " [EVEN]=IY V EH N",
+    "#:[E]W=",
+    "@[EW]=UW",
+    "[EW]=Y UW",
+    "[E]O=IY",
+    "#:&[ES] =IH Z",
+    "#:[E]S =",
+    "#:[ELY] =L IY",
+    "#:[EMENT]=M EH N T",
+    "[EFUL]=F UH L",
+    "[EE]=IY",
+    "[EARN]=ER N",
+    " [EAR]^=ER",
+    "[EAD]=EH D",
+    "#:[EA] =IY AX",
+    "[EA]SU=EH",
+    "[EA]=IY",
+    "[EIGH]=EY",
+    "[EI]=IY",
+    " [EYE]=AY",
+    "[EY]=IY",
+    "[EU]=Y UW",
+    "[E]=EH",
+    "[FUL]=F UH L",
+    "[F]=F",
+    "[GIV]=G IH V",
+    " [G]I^=G",
+    "[GE]T=G EH",
+    "SU[GGES]=G JH EH S",
+    "[GG]=G",
+    " B#[G]=G",
+    "[G]+=JH",
+    "[GREAT]=G R EY T",
+    "#[GH]=",
+    "[G]=G",
+    " [HAV]=HH AE V",
+    " [HERE]=HH IY R",
+    " [HOUR]=AW ER",
+    "[HOW]=HH AW",
+    "[H]#=HH",
+    "[H]=",
+    " [IN]=IH N",
+    " [I] =AY",
+    "[IN]D=AY N",
+    "[IER]=IY ER",
+    "#:R[IED] =IY D",
+    "[IED] =AY D",
+    "[IEN]=IY EH N",
+    "[IE]T=AY EH",
+    " :[I]%=AY",
+    "[I]%=IY",
+    "[IE]=IY",
+    "[I]^+:#=IH",
+    "[IR]#=AY R",
+    "[IZ]%=AY Z",
+    "[IS]%=AY Z",
+    "[I]D%=AY",
+    "+^[I]^+=IH",
+    "[I]T%=AY",
+    "#:^[I]^+=IH",
+    "[I]^+=AY",
+    "[IR]=ER",
+    "[IGH]=AY",
+    "[ILD]=AY L D",
+    "[IGN] =AY N",
+    "[IGN]^=AY N",
+    "[IGN]%=AY N",
+    "[IQUE]=IY K",
+    "[I]=IH",
+    "[J]=JH",
+    " [K]N=",
+    "[K]=K",
+    "[LO]C#=L OW",
+    "L[L]=",
+    "#:^[L]%=AX L",
+    "[LEAD]=L IY D",
+    "[L]=L",
+    "[MOV]=M UW V",
+    "[M]=M",
+    "E[NG]+=N JH",
+    "[NG]R=NG G",
+    "[NG]#=NG G",
+    "[NGL]%=NG G AX L",
+    "[NG]=NG",
+    "[NK]=NG K",
+    " [NOW] =N AW",
+    "[N]=N",
+    "[OF] =AX V",
+    "[OROUGH]=ER OW",
+    "#:[OR] =ER",
+    "#:[ORS] =ER Z",
+    "[OR]=AO R",
+    " [ONE]=W AH N",
+    "[OW]=OW",
+    " [OVER]=OW V ER",
+    "[OV]=AH V",
+    "[O]^%=OW",
+    "[O]^EN=OW",
+    "[O]^I#=OW",
+    "[OL]D=OW L",
+    "[OUGHT]=AO T",
+    "[OUGH]=AH F",
+    " [OU]=AW",
+    "H[OU]S#=AW",
+    "[OUS]=AX S",
+    "[OUR]=AO R",
+    "[OULD]=UH D",
+    "^[OU]^L=AH",
+    "[OUP]=UW P",
+    "[OU]=AW",
+    "[OY]=OY",
+    "[OING]=OW IH NG",
+    "[OI]=OY",
+    "[OOR]=AO R",
+    "[OOK]=UH K",
+    "[OOD]=UH D",
+    "[OO]=UW",
+    "[O]E=OW",
+    "[O] =OW",
+    "[OA]=OW",
+    " [ONLY]=OW N L IY",
+    " [ONCE]=W AH N S",
+    "[ON'T]=OW N T",
+    "C[O]N=AA",
+    "[O]NG=AO",
+    " :^[O]N=AH",
+    "I[ON]=AX N",
+    "#:[ON] =AX N",
+    "#^[ON]=AX N",
+    "[O]ST =OW",
+    "[OF]^=AO F",
+    "[OTHER]=AH DH ER",
+    "[OSS] =AO S",
+    "#:^[OM]=AH M",
+    "[O]=AA",
+    "[PH]=F",
+    "[PEOP]=P IY P",
+    "[POW]=P AW",
+    "[PUT] =P UH T",
+    "[P]=P",
+    "[QUAR]=K W AO R",
+    "[QU]=K W",
+    "[Q]=K",
+    " [RE]^#=R IY",
+    "[R]=R",
+    "[SH]=SH",
+    "#[SION]=ZH AX N",
+    "[SOME]=S AH M",
+    "#[SUR]#=ZH ER",
+    "[SUR]#=SH ER",
+    "#[SU]#=ZH UW",
+    "#[SSU]#=SH UW",
+    "#[SED] =Z D",
+    "#[S]#=Z",
+    "[SAID]=S EH D",
+    "^[SION]=SH AX N",
+    "[S]S=",
+    ".[S] =Z",
+    "#:.E[S] =Z",
+    "#:^##[S] =Z",
+    "#:^#[S] =S",
+    "U[S] =S",
+    " :#[S] =Z",
+    " [SCH]=S K",
+    "[S]C+=",
+    "#[SM]=Z M",
+    "#[SN]'=Z AX N",
+    "[S]=S",
+    " [THE] =DH AX",
+    "[TO] =T UW",
+    "[THAT] =DH AE T",
+    " [THIS] =DH IH S",
+    " [THEY]=DH EY",
+    " [THERE]=DH EH R",
+    "[THER]=DH ER",
+    "[THEIR]=DH EH R",
+    " [THAN] =DH AE N",
+    " [THEM] =DH EH M",
+    "[THESE] =DH IY Z",
+    " [THEN]=DH EH N",
+    "[THROUGH]=TH R UW",
+    "[THOSE]=DH OW Z",
+    "[THOUGH] =DH OW",
+    " [THUS]=DH AH S",
+    "[TH]=TH",
+    "#:[TED] =T IH D",
+    "S[TI]#N=CH",
+    "[TI]O=SH",
+    "[TI]A=SH",
+    "[TIEN]=SH AX N",
+    "[TUR]#=CH ER",
+    "[TU]A=CH UW",
+    " [TWO]=T UW",
+    "[T]=T",
+    " [UN]I=Y UW N",
+    " [UN]=AH N",
+    " [UPON]=AX P AO N",
+    "@[UR]#=UH R",
+    "[UR]#=Y UH R",
+    "[UR]=ER",
+    "[U]^ =AH",
+    "[U]^^=AH",
+    "[UY]=AY",
+    " G[U]#=",
+    "G[U]%=",
+    "G[U]#=W",
+    "#N[U]=Y UW",
+    "@[U]=UW",
+    "[U]=Y UW",
+    "[VIEW]=V Y UW",
+    "[V]=V",
+    " [WERE]=W ER",
+    "[WA]S=W AA",
+    "[WA]T=W AA",
+    "[WHERE]=W EH R",
+    "[WHAT]=W AA T",
+    "[WHOL]=HH OW L",
+    "[WHO]=HH UW",
+    "[WH]=W",
+    "[WAR]=W AO R",
+    "[WOR]^=W ER",
+    "[WR]=R",
+    "[W]=W",
+    "[X]=K S",
+    "[YOUNG]=Y AH NG",
+    " [YOU]=Y UW",
+    " [YES]=Y EH S",
+    " [Y]=Y",
+    "#:^[Y] =IY",
+    "#:^[Y]I=IY",
+    " :[Y] =AY",
+    " :[Y]#=AY",
+    " :[Y]^+:#=IH",
+    " :[Y]^#=AY",
+    "[Y]=IH",
+    "[Z]=Z",
+];
+
+/// Words spoken without stress.
+const FUNCTION_WORDS: &[&str] = &[
+    "A", "AN", "THE", "OF", "TO", "AND", "OR", "IN", "ON", "AT", "IS", "ARE", "WAS", "FOR", "IT",
+    "AS", "BY", "BE",
+];
+
+/// A phoneme of an utterance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Phone {
+    /// ARPAbet name.
+    pub name: &'static str,
+    /// Whether this is the stressed vowel of a word.
+    pub stressed: bool,
+}
+
+struct Rule {
+    left: &'static [u8],
+    matched: &'static [u8],
+    right: &'static [u8],
+    phonemes: &'static str,
+}
+
+impl Rule {
+    fn parse(rule: &'static str) -> Self {
+        let (pattern, phonemes) = rule.split_once('=').unwrap();
+        let (left, rest) = pattern.split_once('[').unwrap();
+        let (matched, right) = rest.split_once(']').unwrap();
+        Self {
+            left: left.as_bytes(),
+            matched: matched.as_bytes(),
+            right: right.as_bytes(),
+            phonemes,
+        }
+    }
+
+    /// Whether the rule applies to `word` at `pos`, `word` being padded with
+    /// spaces.
+    fn matches(&self, word: &[u8], pos: usize) -> bool {
+        word[pos..].starts_with(self.matched)
+            && match_left(self.left, &word[..pos])
+            && match_right(self.right, &word[pos + self.matched.len()..])
+    }
+}
+
+fn is_vowel(c: u8) -> bool {
+    b"AEIOUY".contains(&c)
+}
+
+fn is_consonant(c: u8) -> bool {
+    c.is_ascii_uppercase() && !is_vowel(c)
+}
+
+/// Match a context leftwards from the end of `text`.
+fn match_left(pattern: &[u8], text: &[u8]) -> bool {
+    let mut pos = text.len();
+    for &p in pattern.iter().rev() {
+        match p {
+            b'#' => {
+                if !before(text, pos, 1).is_some_and(is_vowel) {
+                    return false;
+                }
+                while before(text, pos, 1).is_some_and(is_vowel) {
+                    pos -= 1;
+                }
+            }
+            b':' => {
+                while before(text, pos, 1).is_some_and(is_consonant) {
+                    pos -= 1;
+                }
+            }
+            b'&' if matches!(
+                (before(text, pos, 2), before(text, pos, 1)),
+                (Some(b'C' | b'S'), Some(b'H'))
+            ) =>
+            {
+                pos -= 2
+            }
+            b'@' if matches!(
+                (before(text, pos, 2), before(text, pos, 1)),
+                (Some(b'T' | b'C' | b'S'), Some(b'H'))
+            ) =>
+            {
+                pos -= 2
+            }
+            _ => match before(text, pos, 1) {
+                Some(c) if class_matches(p, c) => pos -= 1,
+                None if p == b' ' => {}
+                _ => return false,
+            },
+        }
+    }
+    true
+}
+
+/// Match a context rightwards from the start of `text`.
+fn match_right(pattern: &[u8], text: &[u8]) -> bool {
+    let mut pos = 0;
+    for &p in pattern {
+        match p {
+            b'#' => {
+                if !after(text, pos, 0).is_some_and(is_vowel) {
+                    return false;
+                }
+                while after(text, pos, 0).is_some_and(is_vowel) {
+                    pos += 1;
+                }
+            }
+            b':' => {
+                while after(text, pos, 0).is_some_and(is_consonant) {
+                    pos += 1;
+                }
+            }
+            b'%' => {
+                let rest = &text[pos..];
+                let Some(suffix) = ["ING", "ELY", "ER", "ES", "ED", "E"]
+                    .iter()
+                    .find(|s| rest.starts_with(s.as_bytes()))
+                else {
+                    return false;
+                };
+                pos += suffix.len();
+            }
+            b'&' if matches!(
+                (after(text, pos, 0), after(text, pos, 1)),
+                (Some(b'C' | b'S'), Some(b'H'))
+            ) =>
+            {
+                pos += 2
+            }
+            b'@' if matches!(
+                (after(text, pos, 0), after(text, pos, 1)),
+                (Some(b'T' | b'C' | b'S'), Some(b'H'))
+            ) =>
+            {
+                pos += 2
+            }
+            _ => match after(text, pos, 0) {
+                Some(c) if class_matches(p, c) => pos += 1,
+                None if p == b' ' => {}
+                _ => return false,
+            },
+        }
+    }
+    true
+}
+
+/// The character `n` before `pos`.
+fn before(text: &[u8], pos: usize, n: usize) -> Option<u8> {
+    pos.checked_sub(n).map(|i| text[i])
+}
+
+/// The character `n` after `pos`.
+fn after(text: &[u8], pos: usize, n: usize) -> Option<u8> {
+    text.get(pos + n).copied()
+}
+
+/// Whether a single character matches a pattern symbol or letter.
+fn class_matches(p: u8, c: u8) -> bool {
+    match p {
+        b' ' => !c.is_ascii_uppercase(),
+        b'^' => is_consonant(c),
+        b'.' => b"BDVGJLMNRWZ".contains(&c),
+        b'+' => b"EIY".contains(&c),
+        b'&' => b"SCGZXJ".contains(&c),
+        b'@' => b"TSRDLZNJ".contains(&c),
+        _ => p == c,
+    }
+}
+
+/// Convert one word to phonemes. Letters other than A to Z and `'` are
+/// ignored.
+pub fn word_to_phonemes(word: &str) -> Vec<Phone> {
+    let rules = RULES.iter().map(|r| Rule::parse(r)).collect::<Vec<_>>();
+    let upper = word
+        .to_ascii_uppercase()
+        .bytes()
+        .filter(|c| c.is_ascii_uppercase() || *c == b'\'')
+        .collect::<Vec<_>>();
+    let mut text = vec![b' '];
+    text.extend(&upper);
+    text.push(b' ');
+
+    let mut phones = vec![];
+    let mut pos = 1;
+    while pos < text.len() - 1 {
+        let rule = rules
+            .iter()
+            .filter(|r| r.matched.first() == Some(&text[pos]))
+            .find(|r| r.matches(&text, pos));
+        match rule {
+            Some(rule) => {
+                phones.extend(rule.phonemes.split_ascii_whitespace().map(|name| Phone {
+                    name,
+                    stressed: false,
+                }));
+                pos += rule.matched.len();
+            }
+            // Apostrophes without a rule are silent.
+            None => pos += 1,
+        }
+    }
+
+    let word = String::from_utf8(upper).unwrap();
+    if !FUNCTION_WORDS.contains(&word.as_str())
+        && let Some(vowel) = phones.iter_mut().find(|p| is_vowel_phoneme(p.name))
+    {
+        vowel.stressed = true;
+    }
+    phones
+}
+
+/// Whether an ARPAbet phoneme is a vowel.
+pub fn is_vowel_phoneme(name: &str) -> bool {
+    is_vowel(name.as_bytes()[0]) && name != "Y"
+}
+
+/// How a phrase ends, from its punctuation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhraseEnd {
+    /// A comma or similar, or the end of the text.
+    Continue,
+    Statement,
+    Question,
+}
+
+/// A phrase of phonemes, between punctuation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Phrase {
+    pub phones: Vec<Phone>,
+    pub end: PhraseEnd,
+}
+
+/// Convert text to phrases of phonemes.
+///
+/// Digits are read one at a time.
+pub fn text_to_phrases(text: &str) -> Vec<Phrase> {
+    const DIGITS: [&str; 10] = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+    ];
+    let mut phrases = vec![];
+    let mut phones = vec![];
+    let mut word = String::new();
+    let flush = |word: &mut String, phones: &mut Vec<Phone>| {
+        phones.extend(word_to_phonemes(word));
+        word.clear();
+    };
+    for c in text.chars() {
+        let end = match c {
+            'a'..='z' | 'A'..='Z' | '\'' => {
+                word.push(c);
+                continue;
+            }
+            '0'..='9' => {
+                flush(&mut word, &mut phones);
+                phones.extend(word_to_phonemes(DIGITS[c as usize - '0' as usize]));
+                continue;
+            }
+            ',' | ';' | ':' => PhraseEnd::Continue,
+            '.' | '!' => PhraseEnd::Statement,
+            '?' => PhraseEnd::Question,
+            _ => {
+                flush(&mut word, &mut phones);
+                continue;
+            }
+        };
+        flush(&mut word, &mut phones);
+        if !phones.is_empty() {
+            phrases.push(Phrase {
+                phones: std::mem::take(&mut phones),
+                end,
+            });
+        }
+    }
+    flush(&mut word, &mut phones);
+    if !phones.is_empty() {
+        phrases.push(Phrase {
+            phones,
+            end: PhraseEnd::Continue,
+        });
+    }
+    phrases
+}